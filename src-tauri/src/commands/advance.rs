@@ -0,0 +1,56 @@
+use crate::database::advance::{self, OpenAdvances, SalaryAdvance};
+use crate::database::fee::{parse_month, PaymentMethod};
+use chrono::NaiveDate;
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+#[command(rename_all = "snake_case")]
+pub fn create_salary_advance(
+    staff_id: i32,
+    amount: u32,
+    given_on: String,
+    method: Option<PaymentMethod>,
+    installments: u32,
+    first_month: String,
+    note: Option<String>,
+) -> Result<SalaryAdvance, String> {
+    if parse_month(&first_month).is_none() {
+        return Err(format!("Invalid month: {}", first_month));
+    }
+    SalaryAdvance::create(
+        staff_id,
+        amount as i32,
+        parse_date(&given_on)?,
+        method.unwrap_or(PaymentMethod::Cash),
+        installments as i32,
+        &first_month,
+        note,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_salary_advances(
+    staff_id: Option<i32>,
+    open_only: Option<bool>,
+) -> Result<Vec<SalaryAdvance>, String> {
+    SalaryAdvance::get_all(staff_id, open_only.unwrap_or(false)).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn repay_salary_advance(id: i32, amount: u32, date: String) -> Result<SalaryAdvance, String> {
+    SalaryAdvance::repay(id, amount as i32, parse_date(&date)?).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_salary_advance(id: i32) -> Result<(), String> {
+    SalaryAdvance::delete(id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_open_advances() -> Result<OpenAdvances, String> {
+    advance::open_advances().map_err(|e| e.to_string())
+}
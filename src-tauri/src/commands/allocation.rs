@@ -0,0 +1,25 @@
+use crate::database::allocation::{self, AllocationInput, PaymentAllocations};
+use tauri::command;
+
+#[command(rename_all = "snake_case")]
+pub fn get_payment_allocations(payment_id: i32) -> Result<PaymentAllocations, String> {
+    PaymentAllocations::get(payment_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_receipt_allocations(receipt_id: i32) -> Result<Vec<PaymentAllocations>, String> {
+    PaymentAllocations::get_by_receipt(receipt_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn reallocate_payment(
+    payment_id: i32,
+    allocations: Option<Vec<AllocationInput>>,
+) -> Result<PaymentAllocations, String> {
+    allocation::reallocate(payment_id, allocations).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_student_credit(student_id: i32) -> Result<i32, String> {
+    allocation::get_credit(student_id).map_err(|e| e.to_string())
+}
@@ -0,0 +1,78 @@
+use crate::database::analytics::{
+    self, ChronicAbsenceAlert, ChronicAbsenceRule, SectionAnalytics, StudentAnalytics,
+};
+use chrono::{Local, NaiveDate};
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_student_analytics(
+    student_id: i32,
+    from: String,
+    to: String,
+) -> Result<StudentAnalytics, String> {
+    let from = parse_date(&from)?;
+    let to = parse_date(&to)?;
+
+    StudentAnalytics::get(student_id, from, to).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_section_analytics(
+    session_id: i32,
+    class_id: Option<i32>,
+    section_id: Option<i32>,
+    from: String,
+    to: String,
+) -> Result<SectionAnalytics, String> {
+    let from = parse_date(&from)?;
+    let to = parse_date(&to)?;
+
+    SectionAnalytics::get(session_id, class_id, section_id, from, to).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_chronic_absentees(
+    session_id: i32,
+    date: Option<String>,
+) -> Result<Vec<ChronicAbsenceAlert>, String> {
+    let date = match date {
+        Some(date) => parse_date(&date)?,
+        None => Local::now().naive_local().date(),
+    };
+
+    analytics::chronic_absentees(session_id, date).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_chronic_absence_rule() -> Result<ChronicAbsenceRule, String> {
+    ChronicAbsenceRule::load().map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn set_chronic_absence_rule(
+    min_percentage: f64,
+    consecutive_absences: i32,
+    lookback_days: i64,
+) -> Result<ChronicAbsenceRule, String> {
+    if !(0.0..=100.0).contains(&min_percentage) {
+        return Err("Minimum percentage must be between 0 and 100".to_string());
+    }
+    if consecutive_absences < 0 {
+        return Err("Consecutive absences cannot be negative".to_string());
+    }
+    if lookback_days < 1 {
+        return Err("Lookback must be at least one day".to_string());
+    }
+
+    ChronicAbsenceRule {
+        min_percentage,
+        consecutive_absences,
+        lookback_days,
+    }
+    .save()
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,66 @@
+use crate::database::attendance::{self, AttendanceKind, AttendanceLock, AttendanceOverride};
+use chrono::NaiveDate;
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+#[command]
+pub fn get_attendance_lock() -> Result<AttendanceLock, String> {
+    AttendanceLock::load().map_err(|e| e.to_string())
+}
+
+// 0 turns the lock off
+#[command]
+pub fn set_attendance_lock(days: u32) -> Result<AttendanceLock, String> {
+    AttendanceLock::set(days).map_err(|e| e.to_string())
+}
+
+// Changes a mark even when it is locked. Without a status the mark is removed.
+#[allow(clippy::too_many_arguments)]
+#[command(rename_all = "snake_case")]
+pub fn override_attendance(
+    kind: AttendanceKind,
+    owner_id: i32,
+    date: String,
+    status: Option<String>,
+    remark: Option<String>,
+    arrival_time: Option<String>,
+    reason: String,
+    changed_by: String,
+) -> Result<AttendanceOverride, String> {
+    let date = parse_date(&date)?;
+    let (status, remark) = match status {
+        Some(status) => {
+            let (status, remark) = attendance::parse_status(&status, remark)?;
+            (Some(status), remark)
+        }
+        None => (None, None),
+    };
+    let arrival_time = attendance::parse_time(arrival_time)?;
+
+    AttendanceOverride::apply(
+        kind,
+        owner_id,
+        date,
+        status,
+        remark,
+        arrival_time,
+        &reason,
+        &changed_by,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_attendance_overrides(
+    kind: Option<AttendanceKind>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<AttendanceOverride>, String> {
+    let from = from.as_deref().map(parse_date).transpose()?;
+    let to = to.as_deref().map(parse_date).transpose()?;
+
+    AttendanceOverride::get(kind, from, to).map_err(|e| e.to_string())
+}
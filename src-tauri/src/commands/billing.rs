@@ -0,0 +1,63 @@
+use crate::database::billing::{self, BillingRun, Defaulter, InvoiceLine, StudentDues};
+use crate::database::late_fee;
+use chrono::{Local, NaiveDate};
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+#[command(rename_all = "snake_case")]
+pub fn run_monthly_billing(session_id: i32, month: String) -> Result<BillingRun, String> {
+    if crate::database::fee::parse_month(&month).is_none() {
+        return Err(format!("Invalid month: {}", month));
+    }
+
+    let run = billing::run_billing(session_id, &month).map_err(|e| e.to_string())?;
+    // Fees billed earlier may have fallen overdue since the last run
+    late_fee::apply_late_fees(session_id, Local::now().naive_local().date())
+        .map_err(|e| e.to_string())?;
+    Ok(run)
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_invoice_lines(student_id: i32) -> Result<Vec<InvoiceLine>, String> {
+    InvoiceLine::get_by_student(student_id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_invoice_line(id: i32) -> Result<(), String> {
+    InvoiceLine::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_dues(
+    session_id: i32,
+    class_id: Option<i32>,
+    section_id: Option<i32>,
+) -> Result<Vec<StudentDues>, String> {
+    billing::get_dues(session_id, class_id, section_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_defaulters(
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+    min_months: Option<i32>,
+    as_of: Option<String>,
+) -> Result<Vec<Defaulter>, String> {
+    let as_of = match as_of {
+        Some(date) => parse_date(&date)?,
+        None => Local::now().naive_local().date(),
+    };
+
+    billing::defaulters(
+        session_id,
+        class_id,
+        section_id,
+        min_months.unwrap_or(1),
+        as_of,
+    )
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,35 @@
+use crate::database::biometric::{self, BiometricImport, DeviceMapping};
+use tauri::command;
+
+#[command(rename_all = "snake_case")]
+pub fn set_staff_device_id(staff_id: i32, device_user_id: String) -> Result<DeviceMapping, String> {
+    if device_user_id.trim().is_empty() {
+        return Err("Device user id is required".to_string());
+    }
+    DeviceMapping::set(staff_id, &device_user_id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_staff_device_ids() -> Result<Vec<DeviceMapping>, String> {
+    DeviceMapping::get_all().map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn delete_staff_device_id(device_user_id: String) -> Result<(), String> {
+    DeviceMapping::delete(&device_user_id).map_err(|e| e.to_string())
+}
+
+// `content` is the text of the exported CSV/TXT log
+#[command(rename_all = "snake_case")]
+pub fn import_biometric_log(
+    content: String,
+    overwrite: Option<bool>,
+    mark_absent: Option<bool>,
+) -> Result<BiometricImport, String> {
+    biometric::import_log(
+        &content,
+        overwrite.unwrap_or(false),
+        mark_absent.unwrap_or(true),
+    )
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,106 @@
+use crate::database::calendar::{
+    self, CalendarDay, CalendarEvent, CalendarEventKind, SchoolCalendar,
+};
+use chrono::{NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use tauri::command;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WorkingDays {
+    pub count: usize,
+    pub days: Vec<NaiveDate>,
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+#[command(rename_all = "snake_case")]
+pub fn create_calendar_event(
+    session_id: i32,
+    title: String,
+    kind: CalendarEventKind,
+    start_date: String,
+    end_date: String,
+    description: Option<String>,
+) -> Result<CalendarEvent, String> {
+    let start_date = parse_date(&start_date)?;
+    let end_date = parse_date(&end_date)?;
+
+    CalendarEvent::create(session_id, &title, kind, start_date, end_date, description)
+        .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_calendar_events(
+    session_id: i32,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<CalendarEvent>, String> {
+    let from = from.as_deref().map(parse_date).transpose()?;
+    let to = to.as_deref().map(parse_date).transpose()?;
+
+    CalendarEvent::get(session_id, from, to).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn edit_calendar_event(
+    id: i32,
+    title: String,
+    kind: CalendarEventKind,
+    start_date: String,
+    end_date: String,
+    description: Option<String>,
+) -> Result<CalendarEvent, String> {
+    let start_date = parse_date(&start_date)?;
+    let end_date = parse_date(&end_date)?;
+
+    CalendarEvent::edit(id, &title, kind, start_date, end_date, description)
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_calendar_event(id: i32) -> Result<(), String> {
+    CalendarEvent::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_weekly_off_days(session_id: i32) -> Result<Vec<Weekday>, String> {
+    SchoolCalendar::load(session_id)
+        .map(|c| c.weekly_off_days)
+        .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn set_weekly_off_days(session_id: i32, days: Vec<Weekday>) -> Result<Vec<Weekday>, String> {
+    calendar::set_weekly_off_days(session_id, days).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_calendar_days(
+    session_id: i32,
+    from: String,
+    to: String,
+) -> Result<Vec<CalendarDay>, String> {
+    let from = parse_date(&from)?;
+    let to = parse_date(&to)?;
+
+    SchoolCalendar::load(session_id)
+        .map(|c| c.days(from, to))
+        .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_working_days(session_id: i32, from: String, to: String) -> Result<WorkingDays, String> {
+    let from = parse_date(&from)?;
+    let to = parse_date(&to)?;
+
+    let days = SchoolCalendar::load(session_id)
+        .map(|c| c.working_days(from, to))
+        .map_err(|e| e.to_string())?;
+
+    Ok(WorkingDays {
+        count: days.len(),
+        days,
+    })
+}
@@ -0,0 +1,47 @@
+use crate::database::card::{self, CardTemplate, CardToken, IdCard, ScanResult};
+use crate::report;
+use chrono::Local;
+use tauri::command;
+
+#[command(rename_all = "snake_case")]
+pub fn get_student_card(student_id: i32) -> Result<CardToken, String> {
+    CardToken::for_student(student_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_class_cards(
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+) -> Result<Vec<CardToken>, String> {
+    CardToken::for_class(session_id, class_id, section_id).map_err(|e| e.to_string())
+}
+
+// Called by the gate kiosk with whatever the scanner typed
+#[command]
+pub fn scan_attendance(token: String) -> Result<ScanResult, String> {
+    card::scan(&token, Local::now().naive_local()).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_card_template() -> Result<CardTemplate, String> {
+    CardTemplate::load().map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn set_card_template(template: CardTemplate) -> Result<CardTemplate, String> {
+    template.validate()?;
+    template.save().map_err(|e| e.to_string())
+}
+
+// Returned as a data URL so the frontend can preview or save it directly
+#[command(rename_all = "snake_case")]
+pub fn export_id_cards_pdf(
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+) -> Result<String, String> {
+    let cards = IdCard::get(session_id, class_id, section_id).map_err(|e| e.to_string())?;
+    let template = CardTemplate::load().map_err(|e| e.to_string())?;
+    card::render_cards(&cards, &template).map(|bytes| report::pdf_data_url(&bytes))
+}
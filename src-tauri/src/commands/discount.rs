@@ -0,0 +1,117 @@
+use crate::database::discount::{
+    self, DiscountGrant, DiscountKind, DiscountRule, Eligibility, FeeDiscounts,
+};
+use crate::database::fee::FeeType;
+use chrono::{Local, NaiveDate};
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[command(rename_all = "snake_case")]
+pub fn create_discount_rule(
+    name: String,
+    kind: DiscountKind,
+    value: u32,
+    fee_type: Option<FeeType>,
+    eligibility: Eligibility,
+    min_sibling_order: Option<u32>,
+    valid_from: Option<String>,
+    valid_to: Option<String>,
+    stackable: bool,
+) -> Result<DiscountRule, String> {
+    let valid_from = valid_from.as_deref().map(parse_date).transpose()?;
+    let valid_to = valid_to.as_deref().map(parse_date).transpose()?;
+
+    DiscountRule::create(
+        &name,
+        kind,
+        value as i32,
+        fee_type,
+        eligibility,
+        min_sibling_order.map(|o| o as i32),
+        valid_from,
+        valid_to,
+        stackable,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_discount_rules() -> Result<Vec<DiscountRule>, String> {
+    DiscountRule::get_all().map_err(|e| e.to_string())
+}
+
+#[allow(clippy::too_many_arguments)]
+#[command(rename_all = "snake_case")]
+pub fn edit_discount_rule(
+    id: i32,
+    name: String,
+    kind: DiscountKind,
+    value: u32,
+    fee_type: Option<FeeType>,
+    eligibility: Eligibility,
+    min_sibling_order: Option<u32>,
+    valid_from: Option<String>,
+    valid_to: Option<String>,
+    stackable: bool,
+) -> Result<DiscountRule, String> {
+    let valid_from = valid_from.as_deref().map(parse_date).transpose()?;
+    let valid_to = valid_to.as_deref().map(parse_date).transpose()?;
+
+    DiscountRule::edit(
+        id,
+        &name,
+        kind,
+        value as i32,
+        fee_type,
+        eligibility,
+        min_sibling_order.map(|o| o as i32),
+        valid_from,
+        valid_to,
+        stackable,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_discount_rule(id: i32) -> Result<(), String> {
+    DiscountRule::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn grant_discount(
+    rule_id: i32,
+    student_id: i32,
+    note: Option<String>,
+) -> Result<DiscountGrant, String> {
+    DiscountGrant::grant(rule_id, student_id, note).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_discount_grants(
+    rule_id: Option<i32>,
+    student_id: Option<i32>,
+) -> Result<Vec<DiscountGrant>, String> {
+    DiscountGrant::get(rule_id, student_id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn revoke_discount(id: i32) -> Result<(), String> {
+    DiscountGrant::revoke(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_student_discounts(
+    student_id: i32,
+    date: Option<String>,
+) -> Result<Vec<FeeDiscounts>, String> {
+    let date = match date {
+        Some(date) => parse_date(&date)?,
+        None => Local::now().naive_local().date(),
+    };
+
+    discount::preview(student_id, date).map_err(|e| e.to_string())
+}
@@ -0,0 +1,31 @@
+use crate::database::duplicate::{self, DuplicateCandidate};
+use crate::database::history::History;
+use tauri::command;
+
+#[command(rename_all = "snake_case")]
+pub fn find_duplicate_students(
+    session_id: Option<i32>,
+    threshold: Option<f64>,
+) -> Result<Vec<DuplicateCandidate>, String> {
+    DuplicateCandidate::find_students(session_id, threshold).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn find_duplicate_guardians(threshold: Option<f64>) -> Result<Vec<DuplicateCandidate>, String> {
+    DuplicateCandidate::find_guardians(threshold).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn merge_students(keep_id: i32, merge_id: i32) -> Result<(), String> {
+    duplicate::merge_students(keep_id, merge_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn merge_guardians(keep_id: i32, merge_id: i32) -> Result<(), String> {
+    duplicate::merge_guardians(keep_id, merge_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_history(entity: Option<String>, entity_id: Option<i32>) -> Result<Vec<History>, String> {
+    History::get(entity, entity_id).map_err(|e| e.to_string())
+}
@@ -0,0 +1,27 @@
+use crate::database::family::{Family, FamilySummary, SiblingSuggestion};
+use tauri::command;
+
+#[command(rename_all = "snake_case")]
+pub fn get_family(guardian_id: i32) -> Result<Family, String> {
+    Family::get(guardian_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_families(session_id: i32) -> Result<Vec<Family>, String> {
+    Family::get_all(session_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_families_by_student(student_id: i32) -> Result<Vec<Family>, String> {
+    Family::get_by_student(student_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_family_summary(guardian_id: i32) -> Result<FamilySummary, String> {
+    FamilySummary::get(guardian_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn detect_siblings(session_id: i32) -> Result<Vec<SiblingSuggestion>, String> {
+    Family::detect_siblings(session_id).map_err(|e| e.to_string())
+}
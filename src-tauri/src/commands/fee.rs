@@ -0,0 +1,85 @@
+use crate::database::fee::{
+    FeeType, Payment, PaymentMethod, RelatedType, StudentFeeOverride, StudentLedger,
+};
+use chrono::NaiveDate;
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[command(rename_all = "snake_case")]
+pub fn record_payment(
+    student_id: i32,
+    amount: i32,
+    payment_date: String,
+    fee_type: FeeType,
+    month: Option<String>,
+    payer_id: i32,
+    payer_type: RelatedType,
+    method: Option<PaymentMethod>,
+    transaction_ref: Option<String>,
+    remark: Option<String>,
+) -> Result<Payment, String> {
+    let payment_date = parse_date(&payment_date)?;
+
+    Payment::create(
+        student_id,
+        amount,
+        payment_date,
+        fee_type,
+        month,
+        payer_id,
+        payer_type,
+        method.unwrap_or(PaymentMethod::Cash),
+        transaction_ref,
+        remark,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_student_payments(student_id: i32) -> Result<Vec<Payment>, String> {
+    Payment::get_by_student(student_id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_payment(id: i32) -> Result<(), String> {
+    Payment::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn set_fee_override(
+    student_id: i32,
+    admission_fee: Option<u32>,
+    monthly_fee: Option<u32>,
+    readmission_fee: Option<u32>,
+    resident_fee: Option<u32>,
+) -> Result<StudentFeeOverride, String> {
+    StudentFeeOverride::set(
+        student_id,
+        admission_fee.map(|f| f as i32),
+        monthly_fee.map(|f| f as i32),
+        readmission_fee.map(|f| f as i32),
+        resident_fee.map(|f| f as i32),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_fee_override(student_id: i32) -> Result<Option<StudentFeeOverride>, String> {
+    StudentFeeOverride::get(student_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn delete_fee_override(student_id: i32) -> Result<(), String> {
+    StudentFeeOverride::delete(student_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_student_ledger(student_id: i32, as_of: Option<String>) -> Result<StudentLedger, String> {
+    let as_of = as_of.as_deref().map(parse_date).transpose()?;
+
+    StudentLedger::get(student_id, as_of).map_err(|e| e.to_string())
+}
@@ -1,4 +1,5 @@
 use crate::database::guardian::{Guardian, StudentRelationship};
+use crate::phone;
 use tauri::command;
 
 #[command(rename_all = "snake_case")]
@@ -8,6 +9,7 @@ pub fn create_guardian(
     address: Option<String>,
     photo: Option<String>,
 ) -> Result<Guardian, String> {
+    let phone = phone::normalize_default(&phone)?;
     Guardian::create(&name, &phone, address, photo).map_err(|e| e.to_string())
 }
 
@@ -34,6 +36,7 @@ pub fn edit_guardian(
     address: Option<String>,
     photo: Option<String>,
 ) -> Result<Guardian, String> {
+    let phone = phone::normalize_default(&phone)?;
     Guardian::edit(id, &name, &phone, address, photo).map_err(|e| e.to_string())
 }
 
@@ -0,0 +1,52 @@
+use crate::database::late_fee::{self, LateFeePolicy, LateFeeRun, PenaltyKind};
+use chrono::{Local, NaiveDate};
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+#[command(rename_all = "snake_case")]
+pub fn set_late_fee_policy(
+    session_id: i32,
+    class_id: Option<i32>,
+    due_day: u32,
+    kind: PenaltyKind,
+    amount: u32,
+    cap: Option<u32>,
+) -> Result<LateFeePolicy, String> {
+    LateFeePolicy::set(
+        session_id,
+        class_id,
+        due_day as i32,
+        kind,
+        amount as i32,
+        cap.map(|c| c as i32),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_late_fee_policies(session_id: i32) -> Result<Vec<LateFeePolicy>, String> {
+    LateFeePolicy::get_all(session_id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_late_fee_policy(id: i32) -> Result<(), String> {
+    LateFeePolicy::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn apply_late_fees(session_id: i32, as_of: Option<String>) -> Result<LateFeeRun, String> {
+    let as_of = match as_of {
+        Some(date) => parse_date(&date)?,
+        None => Local::now().naive_local().date(),
+    };
+
+    late_fee::apply_late_fees(session_id, as_of).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn waive_late_fee(invoice_line_id: i32, reason: String) -> Result<(), String> {
+    late_fee::waive(invoice_line_id, &reason).map_err(|e| e.to_string())
+}
@@ -0,0 +1,117 @@
+use crate::database::leave::{
+    self, LeaveApplication, LeaveEntitlement, LeaveStatus, LeaveType, StaffLeaveBalance,
+};
+use chrono::NaiveDate;
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+#[command]
+pub fn create_leave_type(name: String, paid: bool) -> Result<LeaveType, String> {
+    if name.trim().is_empty() {
+        return Err("Leave type name is required".to_string());
+    }
+    LeaveType::create(&name, paid).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_leave_types() -> Result<Vec<LeaveType>, String> {
+    LeaveType::get_all().map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn edit_leave_type(id: i32, name: String, paid: bool) -> Result<LeaveType, String> {
+    if name.trim().is_empty() {
+        return Err("Leave type name is required".to_string());
+    }
+    LeaveType::edit(id, &name, paid).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_leave_type(id: i32) -> Result<(), String> {
+    LeaveType::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn set_leave_entitlement(
+    leave_type_id: i32,
+    role: String,
+    days: u32,
+) -> Result<LeaveEntitlement, String> {
+    LeaveEntitlement::set(leave_type_id, &role, days as i32).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_leave_entitlements() -> Result<Vec<LeaveEntitlement>, String> {
+    LeaveEntitlement::get_all().map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_leave_entitlement(id: i32) -> Result<(), String> {
+    LeaveEntitlement::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn apply_leave(
+    staff_id: i32,
+    leave_type_id: i32,
+    from_date: String,
+    to_date: String,
+    reason: Option<String>,
+) -> Result<LeaveApplication, String> {
+    let from_date = parse_date(&from_date)?;
+    let to_date = parse_date(&to_date)?;
+
+    LeaveApplication::apply(staff_id, leave_type_id, from_date, to_date, reason)
+        .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_leave_applications(
+    staff_id: Option<i32>,
+    status: Option<LeaveStatus>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<LeaveApplication>, String> {
+    let from = from.as_deref().map(parse_date).transpose()?;
+    let to = to.as_deref().map(parse_date).transpose()?;
+
+    LeaveApplication::get(staff_id, status, from, to).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn approve_leave(
+    id: i32,
+    decided_by: Option<String>,
+    note: Option<String>,
+) -> Result<LeaveApplication, String> {
+    LeaveApplication::approve(id, decided_by, note).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn reject_leave(
+    id: i32,
+    decided_by: Option<String>,
+    note: Option<String>,
+) -> Result<LeaveApplication, String> {
+    LeaveApplication::reject(id, decided_by, note).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn cancel_leave(
+    id: i32,
+    decided_by: Option<String>,
+    note: Option<String>,
+) -> Result<LeaveApplication, String> {
+    LeaveApplication::cancel(id, decided_by, note).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_leave_balances(
+    session_id: i32,
+    staff_id: Option<i32>,
+) -> Result<Vec<StaffLeaveBalance>, String> {
+    leave::balances(session_id, staff_id).map_err(|e| e.to_string())
+}
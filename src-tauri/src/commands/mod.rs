@@ -4,3 +4,25 @@ pub mod subjects;
 pub mod session;
 pub mod student;
 pub mod staff;
+pub mod family;
+pub mod duplicate;
+pub mod settings;
+pub mod calendar;
+pub mod register;
+pub mod analytics;
+pub mod routine;
+pub mod attendance;
+pub mod biometric;
+pub mod shift;
+pub mod leave;
+pub mod card;
+pub mod fee;
+pub mod billing;
+pub mod receipt;
+pub mod discount;
+pub mod late_fee;
+pub mod allocation;
+pub mod reconciliation;
+pub mod residency;
+pub mod payroll;
+pub mod advance;
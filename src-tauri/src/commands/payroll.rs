@@ -0,0 +1,110 @@
+use crate::database::fee::{parse_month, PaymentMethod};
+use crate::database::payroll::{
+    self, render_payslip, PayrollRun, SalaryComponent, SalaryDeduction, SalaryPayment,
+    SalaryRevision,
+};
+use crate::report;
+use chrono::NaiveDate;
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+fn check_month(month: &str) -> Result<(), String> {
+    match parse_month(month) {
+        Some(_) => Ok(()),
+        None => Err(format!("Invalid month: {}", month)),
+    }
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_salary_revisions(staff_id: i32) -> Result<Vec<SalaryRevision>, String> {
+    SalaryRevision::get(staff_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn create_salary_revision(
+    staff_id: i32,
+    effective_from: String,
+    basic: u32,
+    allowances: Vec<SalaryComponent>,
+    provident_fund_percent: Option<u32>,
+    note: Option<String>,
+) -> Result<SalaryRevision, String> {
+    SalaryRevision::create(
+        staff_id,
+        parse_date(&effective_from)?,
+        basic as i32,
+        allowances,
+        provident_fund_percent.unwrap_or(0) as i32,
+        note,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_salary_revision(id: i32) -> Result<(), String> {
+    SalaryRevision::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn create_salary_deduction(
+    staff_id: i32,
+    month: String,
+    name: String,
+    amount: u32,
+    note: Option<String>,
+) -> Result<SalaryDeduction, String> {
+    check_month(&month)?;
+    SalaryDeduction::create(staff_id, &month, &name, amount as i32, note).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_salary_deductions(
+    month: Option<String>,
+    staff_id: Option<i32>,
+) -> Result<Vec<SalaryDeduction>, String> {
+    SalaryDeduction::get(month, staff_id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_salary_deduction(id: i32) -> Result<(), String> {
+    SalaryDeduction::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn run_payroll(
+    month: String,
+    payment_date: String,
+    method: Option<PaymentMethod>,
+    remark: Option<String>,
+) -> Result<PayrollRun, String> {
+    check_month(&month)?;
+    payroll::run_payroll(
+        &month,
+        parse_date(&payment_date)?,
+        method.unwrap_or(PaymentMethod::Cash),
+        remark,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_salary_payments(
+    month: Option<String>,
+    staff_id: Option<i32>,
+) -> Result<Vec<SalaryPayment>, String> {
+    SalaryPayment::get_all(month, staff_id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_salary_payment(id: i32) -> Result<(), String> {
+    SalaryPayment::delete(id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn export_payslip_pdf(id: i32) -> Result<String, String> {
+    let payment = SalaryPayment::get(id).map_err(|e| e.to_string())?;
+    render_payslip(&payment).map(|bytes| report::pdf_data_url(&bytes))
+}
@@ -0,0 +1,94 @@
+use crate::database::fee::{PaymentMethod, RelatedType};
+use crate::database::receipt::{render_receipt, Receipt, ReceiptItemInput};
+use crate::report;
+use chrono::NaiveDate;
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[command(rename_all = "snake_case")]
+pub fn create_receipt(
+    student_id: i32,
+    receipt_date: String,
+    payer_id: i32,
+    payer_type: RelatedType,
+    method: Option<PaymentMethod>,
+    transaction_ref: Option<String>,
+    items: Vec<ReceiptItemInput>,
+    remark: Option<String>,
+) -> Result<Receipt, String> {
+    let receipt_date = parse_date(&receipt_date)?;
+
+    Receipt::create(
+        student_id,
+        receipt_date,
+        payer_id,
+        payer_type,
+        method.unwrap_or(PaymentMethod::Cash),
+        transaction_ref,
+        items,
+        remark,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[allow(clippy::too_many_arguments)]
+#[command(rename_all = "snake_case")]
+pub fn create_lump_sum_receipt(
+    student_id: i32,
+    receipt_date: String,
+    payer_id: i32,
+    payer_type: RelatedType,
+    method: Option<PaymentMethod>,
+    transaction_ref: Option<String>,
+    amount: u32,
+    invoice_line_ids: Option<Vec<i32>>,
+    remark: Option<String>,
+) -> Result<Receipt, String> {
+    let receipt_date = parse_date(&receipt_date)?;
+
+    Receipt::create_lump_sum(
+        student_id,
+        receipt_date,
+        payer_id,
+        payer_type,
+        method.unwrap_or(PaymentMethod::Cash),
+        transaction_ref,
+        amount as i32,
+        invoice_line_ids.unwrap_or_default(),
+        remark,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_receipt(id: i32) -> Result<Receipt, String> {
+    Receipt::get(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_receipts(
+    session_id: i32,
+    student_id: Option<i32>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<Receipt>, String> {
+    let from = from.as_deref().map(parse_date).transpose()?;
+    let to = to.as_deref().map(parse_date).transpose()?;
+
+    Receipt::get_all(session_id, student_id, from, to).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn cancel_receipt(id: i32, reason: String) -> Result<Receipt, String> {
+    Receipt::cancel(id, &reason).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn export_receipt_pdf(id: i32) -> Result<String, String> {
+    let receipt = Receipt::get(id).map_err(|e| e.to_string())?;
+    render_receipt(&receipt).map(|bytes| report::pdf_data_url(&bytes))
+}
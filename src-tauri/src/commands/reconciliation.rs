@@ -0,0 +1,111 @@
+use crate::database::fee::{PaymentMethod, RelatedType};
+use crate::database::receipt::Receipt;
+use crate::database::reconciliation::{
+    self, CashDeposit, CashSummary, Reconciliation, StatementImport, StatementLine, StatementStatus,
+};
+use chrono::NaiveDate;
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+// `content` is the text of the statement exported as CSV
+#[command]
+pub fn import_payment_statement(
+    method: PaymentMethod,
+    content: String,
+) -> Result<StatementImport, String> {
+    reconciliation::import_statement(method, &content).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn rematch_statement_lines(method: Option<PaymentMethod>) -> Result<StatementImport, String> {
+    reconciliation::rematch(method).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_statement_lines(
+    method: Option<PaymentMethod>,
+    status: Option<StatementStatus>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<StatementLine>, String> {
+    let from = from.as_deref().map(parse_date).transpose()?;
+    let to = to.as_deref().map(parse_date).transpose()?;
+
+    StatementLine::get_all(method, status, from, to).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn match_statement_line(id: i32, receipt_id: i32) -> Result<StatementLine, String> {
+    StatementLine::match_receipt(id, receipt_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn record_statement_line(
+    id: i32,
+    student_id: i32,
+    payer_id: i32,
+    payer_type: RelatedType,
+) -> Result<Receipt, String> {
+    StatementLine::record(id, student_id, payer_id, payer_type).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn ignore_statement_line(id: i32, note: String) -> Result<StatementLine, String> {
+    StatementLine::ignore(id, &note).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn unmatch_statement_line(id: i32) -> Result<StatementLine, String> {
+    StatementLine::unmatch(id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_reconciliation(
+    method: PaymentMethod,
+    from: String,
+    to: String,
+) -> Result<Reconciliation, String> {
+    let from = parse_date(&from)?;
+    let to = parse_date(&to)?;
+
+    reconciliation::reconcile(method, from, to).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn record_cash_deposit(
+    deposit_date: String,
+    amount: u32,
+    reference: Option<String>,
+    remark: Option<String>,
+) -> Result<CashDeposit, String> {
+    let deposit_date = parse_date(&deposit_date)?;
+
+    CashDeposit::create(deposit_date, amount as i32, reference, remark).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_cash_deposits(
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<CashDeposit>, String> {
+    let from = from.as_deref().map(parse_date).transpose()?;
+    let to = to.as_deref().map(parse_date).transpose()?;
+
+    CashDeposit::get_all(from, to).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_cash_deposit(id: i32) -> Result<(), String> {
+    CashDeposit::delete(id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_cash_summary(from: String, to: String) -> Result<CashSummary, String> {
+    let from = parse_date(&from)?;
+    let to = parse_date(&to)?;
+
+    reconciliation::cash_summary(from, to).map_err(|e| e.to_string())
+}
@@ -0,0 +1,41 @@
+use crate::database::register::MonthlyRegister;
+use crate::report;
+use tauri::command;
+
+#[command(rename_all = "snake_case")]
+pub fn monthly_register(
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+    year: i32,
+    month: u32,
+) -> Result<MonthlyRegister, String> {
+    MonthlyRegister::get(session_id, class_id, section_id, year, month).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn export_monthly_register_csv(
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+    year: i32,
+    month: u32,
+) -> Result<String, String> {
+    MonthlyRegister::get(session_id, class_id, section_id, year, month)
+        .map_err(|e| e.to_string())?
+        .to_csv()
+}
+
+// Returned as a data URL so the frontend can preview or save it directly
+#[command(rename_all = "snake_case")]
+pub fn export_monthly_register_pdf(
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+    year: i32,
+    month: u32,
+) -> Result<String, String> {
+    let register = MonthlyRegister::get(session_id, class_id, section_id, year, month)
+        .map_err(|e| e.to_string())?;
+    register.to_pdf().map(|bytes| report::pdf_data_url(&bytes))
+}
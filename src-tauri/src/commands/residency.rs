@@ -0,0 +1,28 @@
+use crate::database::residency::ResidentPeriod;
+use chrono::NaiveDate;
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_resident_periods(student_id: i32) -> Result<Vec<ResidentPeriod>, String> {
+    ResidentPeriod::get(student_id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn start_residency(student_id: i32, date: String) -> Result<ResidentPeriod, String> {
+    ResidentPeriod::start(student_id, parse_date(&date)?).map_err(|e| e.to_string())
+}
+
+// `date` is the day the student moved out
+#[command(rename_all = "snake_case")]
+pub fn end_residency(student_id: i32, date: String) -> Result<(), String> {
+    ResidentPeriod::end(student_id, parse_date(&date)?).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_resident_period(id: i32) -> Result<(), String> {
+    ResidentPeriod::delete(id).map_err(|e| e.to_string())
+}
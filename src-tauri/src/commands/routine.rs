@@ -0,0 +1,157 @@
+use crate::database::attendance::parse_time;
+use crate::database::routine::{
+    self, ClassRoutine, DailyStatusRule, PeriodAttendance, PeriodEntry, StudentSubjectAttendance,
+    SubjectAttendance,
+};
+use chrono::{NaiveDate, NaiveTime, Weekday};
+use tauri::command;
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))
+}
+
+fn parse_period_times(
+    start_time: String,
+    end_time: String,
+) -> Result<(NaiveTime, NaiveTime), String> {
+    let start_time = parse_time(Some(start_time))?.ok_or("Start time is required")?;
+    let end_time = parse_time(Some(end_time))?.ok_or("End time is required")?;
+    if end_time <= start_time {
+        return Err("End time must be after start time".to_string());
+    }
+    Ok((start_time, end_time))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[command(rename_all = "snake_case")]
+pub fn create_class_routine(
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+    weekday: Weekday,
+    period: i32,
+    subject_id: i32,
+    teacher_id: Option<i32>,
+    start_time: String,
+    end_time: String,
+) -> Result<ClassRoutine, String> {
+    let (start_time, end_time) = parse_period_times(start_time, end_time)?;
+
+    ClassRoutine::create(
+        session_id, class_id, section_id, weekday, period, subject_id, teacher_id, start_time,
+        end_time,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_class_routines(
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+    weekday: Option<Weekday>,
+) -> Result<Vec<ClassRoutine>, String> {
+    ClassRoutine::get(session_id, class_id, section_id, weekday).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_teacher_routine(session_id: i32, teacher_id: i32) -> Result<Vec<ClassRoutine>, String> {
+    ClassRoutine::get_by_teacher(session_id, teacher_id).map_err(|e| e.to_string())
+}
+
+#[allow(clippy::too_many_arguments)]
+#[command(rename_all = "snake_case")]
+pub fn edit_class_routine(
+    id: i32,
+    weekday: Weekday,
+    period: i32,
+    subject_id: i32,
+    teacher_id: Option<i32>,
+    start_time: String,
+    end_time: String,
+) -> Result<ClassRoutine, String> {
+    let (start_time, end_time) = parse_period_times(start_time, end_time)?;
+
+    ClassRoutine::edit(
+        id, weekday, period, subject_id, teacher_id, start_time, end_time,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_class_routine(id: i32) -> Result<(), String> {
+    ClassRoutine::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn mark_period_attendance(
+    routine_id: i32,
+    date: String,
+    teacher_id: Option<i32>,
+    entries: Vec<PeriodEntry>,
+) -> Result<Vec<PeriodAttendance>, String> {
+    let date = parse_date(&date)?;
+
+    PeriodAttendance::mark(routine_id, date, teacher_id, entries).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_period_attendance(
+    routine_id: i32,
+    date: String,
+) -> Result<Vec<PeriodAttendance>, String> {
+    let date = parse_date(&date)?;
+
+    PeriodAttendance::get_by_routine(routine_id, date).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_student_period_attendance(
+    student_id: i32,
+    date: String,
+) -> Result<Vec<PeriodAttendance>, String> {
+    let date = parse_date(&date)?;
+
+    PeriodAttendance::get_by_student(student_id, date).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_period_attendance(id: i32) -> Result<(), String> {
+    PeriodAttendance::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_subject_attendance_report(
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+    from: String,
+    to: String,
+) -> Result<Vec<StudentSubjectAttendance>, String> {
+    let from = parse_date(&from)?;
+    let to = parse_date(&to)?;
+
+    routine::subject_report(session_id, class_id, section_id, from, to).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_student_subject_attendance(
+    student_id: i32,
+    from: String,
+    to: String,
+) -> Result<Vec<SubjectAttendance>, String> {
+    let from = parse_date(&from)?;
+    let to = parse_date(&to)?;
+
+    routine::subject_summary(student_id, from, to).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_daily_status_rule() -> Result<DailyStatusRule, String> {
+    DailyStatusRule::load().map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn set_daily_status_rule(rule: DailyStatusRule) -> Result<DailyStatusRule, String> {
+    rule.save().map_err(|e| e.to_string())
+}
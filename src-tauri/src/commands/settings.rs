@@ -0,0 +1,62 @@
+use crate::database::settings::{
+    Setting, BANGLA_FONT_KEY, DEFAULT_RESIDENT_FEE_KEY, SCHOOL_LOGO_KEY,
+};
+use crate::database::{attendance, card, receipt, shift};
+use crate::phone;
+use crate::report;
+use chrono::NaiveTime;
+use tauri::command;
+
+#[command]
+pub fn get_settings() -> Result<Vec<Setting>, String> {
+    Setting::get_all().map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_setting(key: String) -> Result<Option<String>, String> {
+    Setting::get(&key).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn set_setting(key: String, value: String) -> Result<Setting, String> {
+    let value = value.trim().to_string();
+
+    if key == phone::DEFAULT_COUNTRY_KEY {
+        if !phone::is_supported_country(&value) {
+            return Err(format!("Unsupported country: {}", value));
+        }
+        return Setting::set(&key, &value.to_uppercase()).map_err(|e| e.to_string());
+    }
+
+    if (key == shift::SHIFT_START_KEY || key == shift::SHIFT_END_KEY)
+        && NaiveTime::parse_from_str(&value, "%H:%M").is_err()
+    {
+        return Err(format!("Invalid shift time: {}", value));
+    }
+
+    if key == card::SCAN_LATE_AFTER_KEY && NaiveTime::parse_from_str(&value, "%H:%M").is_err() {
+        return Err(format!("Invalid late cutoff: {}", value));
+    }
+
+    if key == shift::LATE_GRACE_KEY && value.parse::<u32>().is_err() {
+        return Err(format!("Invalid grace period: {}", value));
+    }
+
+    if key == SCHOOL_LOGO_KEY && !value.is_empty() && report::decode_image(&value, 1).is_none() {
+        return Err("Logo is not a readable image".to_string());
+    }
+
+    if key == BANGLA_FONT_KEY && !value.trim().is_empty() {
+        receipt::read_font(value.trim())?;
+    }
+
+    if key == attendance::LOCK_DAYS_KEY && value.parse::<u32>().is_err() {
+        return Err(format!("Invalid lock window: {}", value));
+    }
+
+    if key == DEFAULT_RESIDENT_FEE_KEY && value.parse::<u32>().is_err() {
+        return Err(format!("Invalid resident fee: {}", value));
+    }
+
+    Setting::set(&key, &value).map_err(|e| e.to_string())
+}
@@ -0,0 +1,104 @@
+use crate::database::attendance::parse_time;
+use crate::database::shift::{self, Shift, StaffMonthlySummary, StaffShift};
+use crate::database::staff::Attendance;
+use chrono::{Local, NaiveDate, NaiveTime};
+use tauri::command;
+
+fn parse_shift_times(
+    start_time: String,
+    end_time: String,
+) -> Result<(NaiveTime, NaiveTime), String> {
+    let start_time = parse_time(Some(start_time))?.ok_or("Start time is required")?;
+    let end_time = parse_time(Some(end_time))?.ok_or("End time is required")?;
+    if end_time <= start_time {
+        return Err("End time must be after start time".to_string());
+    }
+    Ok((start_time, end_time))
+}
+
+// Check-in and check-out default to the current date and time
+fn parse_moment(
+    date: Option<String>,
+    time: Option<String>,
+) -> Result<(NaiveDate, NaiveTime), String> {
+    let now = Local::now().naive_local();
+    let date = match date {
+        Some(date) => NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+            .map_err(|e| format!("Invalid date: {}", e))?,
+        None => now.date(),
+    };
+    let time = parse_time(time)?.unwrap_or_else(|| now.time());
+    Ok((date, time))
+}
+
+#[command(rename_all = "snake_case")]
+pub fn create_shift(
+    name: String,
+    start_time: String,
+    end_time: String,
+    grace_minutes: u32,
+) -> Result<Shift, String> {
+    let (start_time, end_time) = parse_shift_times(start_time, end_time)?;
+    Shift::create(&name, start_time, end_time, grace_minutes as i32).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_shifts() -> Result<Vec<Shift>, String> {
+    Shift::get_all().map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn edit_shift(
+    id: i32,
+    name: String,
+    start_time: String,
+    end_time: String,
+    grace_minutes: u32,
+) -> Result<Shift, String> {
+    let (start_time, end_time) = parse_shift_times(start_time, end_time)?;
+    Shift::edit(id, &name, start_time, end_time, grace_minutes as i32).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_shift(id: i32) -> Result<(), String> {
+    Shift::delete(id).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn assign_staff_shift(staff_id: i32, shift_id: Option<i32>) -> Result<Shift, String> {
+    Shift::assign(staff_id, shift_id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_staff_shifts() -> Result<Vec<StaffShift>, String> {
+    Shift::get_assignments().map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn staff_check_in(
+    staff_id: i32,
+    date: Option<String>,
+    time: Option<String>,
+) -> Result<Attendance, String> {
+    let (date, time) = parse_moment(date, time)?;
+    shift::check_in(staff_id, date, time).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn staff_check_out(
+    staff_id: i32,
+    date: Option<String>,
+    time: Option<String>,
+) -> Result<Attendance, String> {
+    let (date, time) = parse_moment(date, time)?;
+    shift::check_out(staff_id, date, time).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_staff_monthly_summary(
+    year: i32,
+    month: u32,
+    staff_id: Option<i32>,
+) -> Result<Vec<StaffMonthlySummary>, String> {
+    shift::monthly_summary(year, month, staff_id).map_err(|e| e.to_string())
+}
@@ -1,7 +1,9 @@
 use chrono::NaiveDate;
 use tauri::command;
 
-use crate::database::staff::{Attendance, Complaint, Staff, TeacherClassSubject};
+use crate::database::attendance::{self, AttendanceSummary};
+use crate::database::staff::{Attendance, Complaint, Staff, StudentStaffLink, TeacherClassSubject};
+use crate::phone;
 
 #[command(rename_all = "snake_case")]
 pub fn create_staff(
@@ -19,6 +21,7 @@ pub fn create_staff(
 ) -> Result<Staff, String> {
     let hire_date = NaiveDate::parse_from_str(&hire_date, "%Y-%m-%d")
         .map_err(|e| format!("Invalid date: {}", e))?;
+    let phone = phone::normalize_default(&phone)?;
 
     Staff::create(
         &name,
@@ -58,6 +61,7 @@ pub fn update_staff(
 ) -> Result<(), String> {
     let hire_date = NaiveDate::parse_from_str(&hire_date, "%Y-%m-%d")
         .map_err(|e| format!("Invalid date: {}", e))?;
+    let phone = phone::normalize_default(&phone)?;
 
     Staff::update(
         id,
@@ -125,10 +129,17 @@ pub fn create_attendance_staff(
     staff_id: i32,
     date: String,
     status: String,
+    remark: Option<String>,
+    arrival_time: Option<String>,
+    departure_time: Option<String>,
 ) -> Result<Attendance, String> {
     let date =
         NaiveDate::parse_from_str(&date, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))?;
-    Attendance::create(staff_id, date, &status).map_err(|e| e.to_string())
+    let (status, remark) = attendance::parse_status(&status, remark)?;
+    let arrival_time = attendance::parse_time(arrival_time)?;
+    let departure_time = attendance::parse_time(departure_time)?;
+    Attendance::create(staff_id, date, status, remark, arrival_time, departure_time)
+        .map_err(|e| e.to_string())
 }
 
 #[command]
@@ -149,10 +160,39 @@ pub fn update_attendance(
     staff_id: i32,
     date: String,
     status: String,
+    remark: Option<String>,
+    arrival_time: Option<String>,
+    departure_time: Option<String>,
 ) -> Result<(), String> {
     let date =
         NaiveDate::parse_from_str(&date, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))?;
-    Attendance::update(id, staff_id, date, &status).map_err(|e| e.to_string())
+    let (status, remark) = attendance::parse_status(&status, remark)?;
+    let arrival_time = attendance::parse_time(arrival_time)?;
+    let departure_time = attendance::parse_time(departure_time)?;
+    Attendance::update(
+        id,
+        staff_id,
+        date,
+        status,
+        remark,
+        arrival_time,
+        departure_time,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_staff_attendance_summary(
+    staff_id: i32,
+    from: String,
+    to: String,
+) -> Result<AttendanceSummary, String> {
+    let from =
+        NaiveDate::parse_from_str(&from, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))?;
+    let to =
+        NaiveDate::parse_from_str(&to, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))?;
+
+    AttendanceSummary::for_staff(staff_id, from, to).map_err(|e| e.to_string())
 }
 
 #[command]
@@ -198,3 +238,26 @@ pub fn update_teacher_subject_link(
 pub fn delete_teacher_subject_link(id: i32) -> Result<(), String> {
     TeacherClassSubject::delete(id).map_err(|e| e.to_string())
 }
+
+//
+// ─── STUDENT-STAFF LINK COMMANDS ────────────────────────────────────────────────
+//
+
+#[command(rename_all = "snake_case")]
+pub fn create_student_staff_link(
+    student_id: i32,
+    staff_id: i32,
+    relationship: Option<String>,
+) -> Result<StudentStaffLink, String> {
+    StudentStaffLink::create(student_id, staff_id, relationship).map_err(|e| e.to_string())
+}
+
+#[command(rename_all = "snake_case")]
+pub fn get_student_staff_links(staff_id: i32) -> Result<Vec<StudentStaffLink>, String> {
+    StudentStaffLink::get_by_staff(staff_id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_student_staff_link(id: i32) -> Result<(), String> {
+    StudentStaffLink::delete(id).map_err(|e| e.to_string())
+}
@@ -1,7 +1,9 @@
 use chrono::NaiveDate;
 use tauri::command;
 
-use crate::database::student::{Attendance, Student};
+use crate::database::attendance::{self, AttendanceStatus, AttendanceSummary};
+use crate::database::student::{Attendance, AttendanceEntry, ClassRegister, Student};
+use crate::phone;
 
 #[command(rename_all = "snake_case")]
 pub fn create_student(
@@ -25,6 +27,7 @@ pub fn create_student(
         .map_err(|e| format!("Invalid date of birth: {}", e))?;
     let admission_date = NaiveDate::parse_from_str(&admission_date, "%Y-%m-%d")
         .map_err(|e| format!("Invalid admission date: {}", e))?;
+    let phone = phone::normalize_optional(phone)?;
 
     Student::create(
         &name,
@@ -78,6 +81,7 @@ pub fn edit_student(
         .map_err(|e| format!("Invalid date of birth: {}", e))?;
     let admission_date = NaiveDate::parse_from_str(&admission_date, "%Y-%m-%d")
         .map_err(|e| format!("Invalid admission date: {}", e))?;
+    let phone = phone::normalize_optional(phone)?;
 
     Student::edit(
         id,
@@ -110,11 +114,15 @@ pub fn create_attendance(
     student_id: i32,
     date: String,
     status: String,
+    remark: Option<String>,
+    arrival_time: Option<String>,
 ) -> Result<Attendance, String> {
     let date =
         NaiveDate::parse_from_str(&date, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))?;
+    let (status, remark) = attendance::parse_status(&status, remark)?;
+    let arrival_time = attendance::parse_time(arrival_time)?;
 
-    match Attendance::create(student_id, date, &status) {
+    match Attendance::create(student_id, date, status, remark.clone(), arrival_time) {
         Ok(attendance) => Ok(attendance),
 
         Err(e) => {
@@ -122,7 +130,7 @@ pub fn create_attendance(
                 Attendance::delete_by_student_and_date(student_id, date)
                     .map_err(|err| format!("Failed to delete existing record: {}", err))?;
 
-                Attendance::create(student_id, date, &status)
+                Attendance::create(student_id, date, status, remark, arrival_time)
                     .map_err(|err| format!("Retry failed: {}", err))
             } else {
                 Err(format!("Insert failed: {}", e))
@@ -131,6 +139,29 @@ pub fn create_attendance(
     }
 }
 
+#[command(rename_all = "snake_case")]
+pub fn mark_class_attendance(
+    date: String,
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+    entries: Vec<AttendanceEntry>,
+    default_status: Option<AttendanceStatus>,
+) -> Result<ClassRegister, String> {
+    let date =
+        NaiveDate::parse_from_str(&date, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))?;
+
+    Attendance::mark_class(
+        date,
+        session_id,
+        class_id,
+        section_id,
+        entries,
+        default_status,
+    )
+    .map_err(|e| e.to_string())
+}
+
 #[command(rename_all = "snake_case")]
 pub fn get_attendance_by_date(
     date: String,
@@ -153,6 +184,20 @@ pub fn get_attendance_by_student(
     Attendance::get_by_student(student_id, year, month).map_err(|e| e.to_string())
 }
 
+#[command(rename_all = "snake_case")]
+pub fn get_attendance_summary(
+    student_id: i32,
+    from: String,
+    to: String,
+) -> Result<AttendanceSummary, String> {
+    let from =
+        NaiveDate::parse_from_str(&from, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))?;
+    let to =
+        NaiveDate::parse_from_str(&to, "%Y-%m-%d").map_err(|e| format!("Invalid date: {}", e))?;
+
+    AttendanceSummary::for_student(student_id, from, to).map_err(|e| e.to_string())
+}
+
 #[command(rename_all = "snake_case")]
 pub fn delete_attendance(id: i32) -> Result<(), String> {
     Attendance::delete(id).map_err(|e| e.to_string())
@@ -0,0 +1,414 @@
+use super::conn;
+use super::fee::{month_key, parse_month, PaymentMethod};
+use super::history::History;
+use super::payroll::has_payroll;
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+fn advance_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Installment {
+    pub month: String,
+    pub amount: i32,
+    // Recoveries are set against the earliest installments first
+    pub recovered: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Recovery {
+    pub id: i32,
+    pub date: NaiveDate,
+    pub amount: i32,
+    // Deducted from this salary payment; none when repaid in hand
+    pub salary_payment_id: Option<i32>,
+    pub month: Option<String>,
+}
+
+// Money lent to a staff member ahead of salary, recovered from payroll in
+// monthly installments. An installment missed or only partly recovered is
+// taken in the following month.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SalaryAdvance {
+    pub id: i32,
+    pub staff_id: i32,
+    pub staff_name: String,
+    pub amount: i32,
+    pub given_on: NaiveDate,
+    pub method: PaymentMethod,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub installments: Vec<Installment>,
+    pub recoveries: Vec<Recovery>,
+    pub recovered: i32,
+    pub outstanding: i32,
+    // Scheduled up to the current month and not yet recovered
+    pub overdue: i32,
+}
+
+const ADVANCE_SELECT: &str =
+    "SELECT a.id, a.staff_id, s.name, a.amount, a.given_on, a.method, a.note, a.created_at
+     FROM salary_advances a JOIN staffs s ON s.id = a.staff_id";
+
+impl SalaryAdvance {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS salary_advances (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    staff_id INTEGER NOT NULL,
+                    amount INTEGER NOT NULL CHECK (amount > 0),
+                    given_on DATE NOT NULL,
+                    method TEXT NOT NULL {},
+                    note TEXT,
+                    created_at DATETIME NOT NULL,
+                    FOREIGN KEY (staff_id) REFERENCES staffs(id)
+                )",
+                PaymentMethod::check_constraint("method")
+            ),
+            [],
+        )?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS advance_installments (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                advance_id INTEGER NOT NULL,
+                month TEXT NOT NULL,
+                amount INTEGER NOT NULL CHECK (amount > 0),
+                FOREIGN KEY (advance_id) REFERENCES salary_advances(id) ON DELETE CASCADE,
+                UNIQUE (advance_id, month)
+            )",
+            [],
+        )?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS advance_recoveries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                advance_id INTEGER NOT NULL,
+                salary_payment_id INTEGER,
+                date DATE NOT NULL,
+                amount INTEGER NOT NULL CHECK (amount > 0),
+                FOREIGN KEY (advance_id) REFERENCES salary_advances(id) ON DELETE CASCADE,
+                FOREIGN KEY (salary_payment_id) REFERENCES salary_payments(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(db: &Connection, row: &rusqlite::Row) -> Result<Self> {
+        let id: i32 = row.get(0)?;
+        let amount: i32 = row.get(3)?;
+
+        let recoveries: Vec<Recovery> = {
+            let mut stmt = db.prepare(
+                "SELECT r.id, r.date, r.amount, r.salary_payment_id, p.month
+                 FROM advance_recoveries r
+                 LEFT JOIN salary_payments p ON p.id = r.salary_payment_id
+                 WHERE r.advance_id = ?1
+                 ORDER BY r.date ASC, r.id ASC",
+            )?;
+            let rows = stmt.query_map(params![id], |row| {
+                Ok(Recovery {
+                    id: row.get(0)?,
+                    date: row.get(1)?,
+                    amount: row.get(2)?,
+                    salary_payment_id: row.get(3)?,
+                    month: row.get(4)?,
+                })
+            })?;
+            rows.collect::<Result<_>>()?
+        };
+        let recovered: i32 = recoveries.iter().map(|r| r.amount).sum();
+
+        let mut installments: Vec<Installment> = {
+            let mut stmt = db.prepare(
+                "SELECT month, amount FROM advance_installments
+                 WHERE advance_id = ?1 ORDER BY month ASC",
+            )?;
+            let rows = stmt.query_map(params![id], |row| {
+                Ok(Installment {
+                    month: row.get(0)?,
+                    amount: row.get(1)?,
+                    recovered: 0,
+                })
+            })?;
+            rows.collect::<Result<_>>()?
+        };
+        let mut left = recovered;
+        for installment in &mut installments {
+            installment.recovered = installment.amount.min(left);
+            left -= installment.recovered;
+        }
+
+        let current = month_key(Local::now().date_naive());
+        let scheduled: i32 = installments
+            .iter()
+            .filter(|i| i.month <= current)
+            .map(|i| i.amount)
+            .sum();
+
+        Ok(Self {
+            id,
+            staff_id: row.get(1)?,
+            staff_name: row.get(2)?,
+            amount,
+            given_on: row.get(4)?,
+            method: row.get(5)?,
+            note: row.get(6)?,
+            created_at: row.get(7)?,
+            installments,
+            recoveries,
+            recovered,
+            outstanding: amount - recovered,
+            overdue: (scheduled - recovered).max(0),
+        })
+    }
+
+    fn get_with(db: &Connection, id: i32) -> Result<Self> {
+        db.query_row(
+            &format!("{} WHERE a.id = ?1", ADVANCE_SELECT),
+            params![id],
+            |row| Self::from_row(db, row),
+        )
+    }
+
+    pub fn get(id: i32) -> Result<Self> {
+        let db = conn()?;
+        Self::get_with(&db, id)
+    }
+
+    pub fn get_all(staff_id: Option<i32>, open_only: bool) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(&format!(
+            "{} WHERE (?1 IS NULL OR a.staff_id = ?1)
+             ORDER BY a.given_on DESC, a.id DESC",
+            ADVANCE_SELECT
+        ))?;
+        let rows = stmt.query_map(params![staff_id], |row| Self::from_row(&db, row))?;
+        let advances = rows.collect::<Result<Vec<_>>>()?;
+        Ok(advances
+            .into_iter()
+            .filter(|a| !open_only || a.outstanding > 0)
+            .collect())
+    }
+
+    // Split into `installments` equal monthly amounts from `first_month`, the
+    // last taking what does not divide evenly
+    pub fn create(
+        staff_id: i32,
+        amount: i32,
+        given_on: NaiveDate,
+        method: PaymentMethod,
+        installments: i32,
+        first_month: &str,
+        note: Option<String>,
+    ) -> Result<Self> {
+        let first = parse_month(first_month).ok_or(rusqlite::Error::InvalidQuery)?;
+        if amount <= 0 {
+            return Err(advance_error("An advance must be more than zero"));
+        }
+        if installments < 1 || installments > amount {
+            return Err(advance_error(&format!(
+                "Installments must be between 1 and {}",
+                amount
+            )));
+        }
+        if month_key(first) < month_key(given_on) {
+            return Err(advance_error(
+                "Recovery cannot start before the advance is given",
+            ));
+        }
+        let note = note.filter(|n| !n.trim().is_empty());
+
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        if has_payroll(&tx, staff_id, &month_key(first))? {
+            return Err(advance_error(&format!(
+                "Salary has already been paid for {} or later",
+                month_key(first)
+            )));
+        }
+        let id: i32 = tx.query_row(
+            "INSERT INTO salary_advances (staff_id, amount, given_on, method, note, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             RETURNING id",
+            params![
+                staff_id,
+                amount,
+                given_on,
+                method,
+                note,
+                Local::now().naive_local()
+            ],
+            |row| row.get(0),
+        )?;
+        let each = amount / installments;
+        let mut month = first;
+        for index in 0..installments {
+            let installment = if index == installments - 1 {
+                amount - each * (installments - 1)
+            } else {
+                each
+            };
+            tx.execute(
+                "INSERT INTO advance_installments (advance_id, month, amount)
+                 VALUES (?1, ?2, ?3)",
+                params![id, month_key(month), installment],
+            )?;
+            month = month
+                .checked_add_months(chrono::Months::new(1))
+                .ok_or(rusqlite::Error::InvalidQuery)?;
+        }
+        History::record(
+            &tx,
+            "staff",
+            staff_id,
+            "advance",
+            Some(format!(
+                "{} Tk on {} in {} installment(s) from {}",
+                amount,
+                given_on,
+                installments,
+                month_key(first)
+            )),
+        )?;
+
+        let advance = Self::get_with(&tx, id)?;
+        tx.commit()?;
+        Ok(advance)
+    }
+
+    // Paid back in hand rather than from salary
+    pub fn repay(id: i32, amount: i32, date: NaiveDate) -> Result<Self> {
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        let advance = Self::get_with(&tx, id)?;
+        if amount <= 0 || amount > advance.outstanding {
+            return Err(advance_error(&format!(
+                "Repayment must be between 1 and the {} Tk outstanding",
+                advance.outstanding
+            )));
+        }
+        recover_with(&tx, id, None, date, amount)?;
+        History::record(
+            &tx,
+            "staff",
+            advance.staff_id,
+            "advance_repay",
+            Some(format!(
+                "{} Tk on {} against the advance of {}",
+                amount, date, advance.given_on
+            )),
+        )?;
+
+        let advance = Self::get_with(&tx, id)?;
+        tx.commit()?;
+        Ok(advance)
+    }
+
+    // Only an advance nothing has been recovered on can be removed
+    pub fn delete(id: i32) -> Result<()> {
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        let advance = Self::get_with(&tx, id)?;
+        if advance.recovered > 0 {
+            return Err(advance_error(
+                "Part of this advance has already been recovered",
+            ));
+        }
+        tx.execute("DELETE FROM salary_advances WHERE id = ?1", params![id])?;
+        History::record(
+            &tx,
+            "staff",
+            advance.staff_id,
+            "advance_delete",
+            Some(format!("{} Tk on {}", advance.amount, advance.given_on)),
+        )?;
+
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+pub(super) fn recover_with(
+    db: &Connection,
+    advance_id: i32,
+    salary_payment_id: Option<i32>,
+    date: NaiveDate,
+    amount: i32,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO advance_recoveries (advance_id, salary_payment_id, date, amount)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![advance_id, salary_payment_id, date, amount],
+    )?;
+    Ok(())
+}
+
+pub(super) struct AdvanceDue {
+    pub(super) advance_id: i32,
+    pub(super) given_on: NaiveDate,
+    pub(super) amount: i32,
+}
+
+// What a month's payroll should recover from each of the staff member's
+// advances: the installments scheduled up to it, less what is recovered
+pub(super) fn due_with(db: &Connection, staff_id: i32, month: &str) -> Result<Vec<AdvanceDue>> {
+    let mut stmt = db.prepare(
+        "SELECT a.id, a.given_on,
+                (SELECT COALESCE(SUM(i.amount), 0) FROM advance_installments i
+                 WHERE i.advance_id = a.id AND i.month <= ?2)
+                - (SELECT COALESCE(SUM(r.amount), 0) FROM advance_recoveries r
+                   WHERE r.advance_id = a.id)
+         FROM salary_advances a
+         WHERE a.staff_id = ?1
+         ORDER BY a.given_on ASC, a.id ASC",
+    )?;
+    let rows = stmt.query_map(params![staff_id, month], |row| {
+        Ok(AdvanceDue {
+            advance_id: row.get(0)?,
+            given_on: row.get(1)?,
+            amount: row.get(2)?,
+        })
+    })?;
+    let due = rows.collect::<Result<Vec<_>>>()?;
+    Ok(due.into_iter().filter(|d| d.amount > 0).collect())
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct OpenAdvances {
+    pub advances: Vec<SalaryAdvance>,
+    pub given: i32,
+    pub recovered: i32,
+    pub outstanding: i32,
+    pub overdue: i32,
+}
+
+// Every advance with a balance left, most owed first
+pub fn open_advances() -> Result<OpenAdvances> {
+    let mut advances = SalaryAdvance::get_all(None, true)?;
+    advances.sort_by_key(|a| std::cmp::Reverse(a.outstanding));
+    Ok(OpenAdvances {
+        given: advances.iter().map(|a| a.amount).sum(),
+        recovered: advances.iter().map(|a| a.recovered).sum(),
+        outstanding: advances.iter().map(|a| a.outstanding).sum(),
+        overdue: advances.iter().map(|a| a.overdue).sum(),
+        advances,
+    })
+}
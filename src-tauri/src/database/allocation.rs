@@ -0,0 +1,443 @@
+use super::conn;
+use super::fee::{FeeType, Payment};
+use super::history::History;
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+fn allocation_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+// The part of a payment that went towards one invoice line
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Allocation {
+    pub id: i32,
+    pub payment_id: i32,
+    pub invoice_line_id: i32,
+    pub fee_type: FeeType,
+    pub month: String,
+    pub description: String,
+    pub amount: i32,
+}
+
+// How a payment was spread; whatever is not allocated is held as credit
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PaymentAllocations {
+    pub payment: Payment,
+    pub allocations: Vec<Allocation>,
+    pub unallocated: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AllocationInput {
+    pub invoice_line_id: i32,
+    pub amount: i32,
+}
+
+impl Allocation {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let exists: bool = db.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master
+             WHERE type = 'table' AND name = 'payment_allocations'",
+            [],
+            |row| row.get(0),
+        )?;
+        // Removing a payment or a line frees the money for the other side:
+        // owed again, or back to credit.
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS payment_allocations (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                payment_id INTEGER NOT NULL,
+                invoice_line_id INTEGER NOT NULL,
+                amount INTEGER NOT NULL CHECK (amount > 0),
+                FOREIGN KEY (payment_id) REFERENCES payments(id) ON DELETE CASCADE,
+                FOREIGN KEY (invoice_line_id) REFERENCES invoice_lines(id) ON DELETE CASCADE,
+                UNIQUE (payment_id, invoice_line_id)
+            )",
+            [],
+        )?;
+        db.execute(
+            "CREATE INDEX IF NOT EXISTS idx_payment_allocations_line
+             ON payment_allocations (invoice_line_id)",
+            [],
+        )?;
+
+        // Payments recorded before allocations existed settled their own fee
+        if !exists {
+            let students: Vec<i32> = {
+                let mut stmt = db.prepare("SELECT DISTINCT student_id FROM payments")?;
+                let rows = stmt.query_map([], |row| row.get(0))?;
+                rows.collect::<Result<_>>()?
+            };
+            for student_id in students {
+                allocate_with(&db, student_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn for_payment_with(db: &Connection, payment_id: i32) -> Result<Vec<Self>> {
+        let mut stmt = db.prepare(
+            "SELECT a.id, a.payment_id, a.invoice_line_id, l.fee_type, l.month, l.description,
+                    a.amount
+             FROM payment_allocations a
+             JOIN invoice_lines l ON l.id = a.invoice_line_id
+             WHERE a.payment_id = ?1
+             ORDER BY l.charge_date ASC, l.id ASC",
+        )?;
+        let rows = stmt.query_map(params![payment_id], |row| {
+            Ok(Self {
+                id: row.get(0)?,
+                payment_id: row.get(1)?,
+                invoice_line_id: row.get(2)?,
+                fee_type: row.get(3)?,
+                month: row.get(4)?,
+                description: row.get(5)?,
+                amount: row.get(6)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+impl PaymentAllocations {
+    pub fn get_with(db: &Connection, payment_id: i32) -> Result<Self> {
+        let payment = Payment::get_with(db, payment_id)?;
+        let allocations = Allocation::for_payment_with(db, payment_id)?;
+        let unallocated = payment.amount - allocations.iter().map(|a| a.amount).sum::<i32>();
+        Ok(Self {
+            payment,
+            allocations,
+            unallocated,
+        })
+    }
+
+    pub fn get(payment_id: i32) -> Result<Self> {
+        let db = conn()?;
+        Self::get_with(&db, payment_id)
+    }
+
+    // Every payment on a receipt; empty once the receipt is cancelled
+    pub fn get_by_receipt(receipt_id: i32) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let payments: Vec<i32> = {
+            let mut stmt =
+                db.prepare("SELECT id FROM payments WHERE receipt_id = ?1 ORDER BY id ASC")?;
+            let rows = stmt.query_map(params![receipt_id], |row| row.get(0))?;
+            rows.collect::<Result<_>>()?
+        };
+        payments
+            .into_iter()
+            .map(|id| Self::get_with(&db, id))
+            .collect()
+    }
+}
+
+struct OpenLine {
+    id: i32,
+    fee_type: FeeType,
+    month: String,
+    outstanding: i32,
+}
+
+struct OpenPayment {
+    id: i32,
+    fee_type: Option<FeeType>,
+    month: Option<String>,
+    remaining: i32,
+}
+
+fn open_lines(db: &Connection, student_id: i32) -> Result<Vec<OpenLine>> {
+    let mut stmt = db.prepare(
+        "SELECT l.id, l.fee_type, l.month,
+                l.amount - l.discount - COALESCE((SELECT SUM(a.amount) FROM payment_allocations a
+                                                  WHERE a.invoice_line_id = l.id), 0)
+         FROM invoice_lines l
+         WHERE l.student_id = ?1
+         ORDER BY l.charge_date ASC, l.id ASC",
+    )?;
+    let rows = stmt.query_map(params![student_id], |row| {
+        Ok(OpenLine {
+            id: row.get(0)?,
+            fee_type: row.get(1)?,
+            month: row.get(2)?,
+            outstanding: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn open_payments(db: &Connection, student_id: i32) -> Result<Vec<OpenPayment>> {
+    let mut stmt = db.prepare(
+        "SELECT p.id, p.fee_type, p.month,
+                p.amount - COALESCE((SELECT SUM(a.amount) FROM payment_allocations a
+                                     WHERE a.payment_id = p.id), 0)
+         FROM payments p
+         WHERE p.student_id = ?1
+         ORDER BY p.payment_date ASC, p.id ASC",
+    )?;
+    let rows = stmt.query_map(params![student_id], |row| {
+        Ok(OpenPayment {
+            id: row.get(0)?,
+            fee_type: row.get(1)?,
+            month: row.get(2)?,
+            remaining: row.get(3)?,
+        })
+    })?;
+    Ok(rows
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|p| p.remaining > 0)
+        .collect())
+}
+
+fn add(db: &Connection, payment_id: i32, invoice_line_id: i32, amount: i32) -> Result<()> {
+    db.execute(
+        "INSERT INTO payment_allocations (payment_id, invoice_line_id, amount)
+         VALUES (?1, ?2, ?3)
+         ON CONFLICT (payment_id, invoice_line_id) DO UPDATE SET amount = amount + excluded.amount",
+        params![payment_id, invoice_line_id, amount],
+    )?;
+    Ok(())
+}
+
+// Spreads a payment over `lines` in order, as far as it goes
+fn spread(
+    db: &Connection,
+    payment: &mut OpenPayment,
+    lines: &mut [OpenLine],
+    order: &[usize],
+) -> Result<()> {
+    for &index in order {
+        if payment.remaining <= 0 {
+            break;
+        }
+        let line = &mut lines[index];
+        let amount = payment.remaining.min(line.outstanding);
+        if amount <= 0 {
+            continue;
+        }
+        add(db, payment.id, line.id, amount)?;
+        payment.remaining -= amount;
+        line.outstanding -= amount;
+    }
+    Ok(())
+}
+
+// Puts a student's unallocated money to work, oldest payment first. A
+// payment made for a particular fee goes to that fee first and waits as
+// credit until it is billed; a lump sum, and whatever a fee leaves over,
+// goes to the oldest lines still owed. Runs whenever payments come in or
+// fees are billed.
+pub(super) fn allocate_with(db: &Connection, student_id: i32) -> Result<()> {
+    allocate_except(db, student_id, None)
+}
+
+fn allocate_except(db: &Connection, student_id: i32, except: Option<i32>) -> Result<()> {
+    let mut payments: Vec<OpenPayment> = open_payments(db, student_id)?
+        .into_iter()
+        .filter(|p| Some(p.id) != except)
+        .collect();
+    if payments.is_empty() {
+        return Ok(());
+    }
+    let mut lines = open_lines(db, student_id)?;
+    let oldest_first: Vec<usize> = (0..lines.len()).collect();
+
+    for payment in &mut payments {
+        if let Some(fee_type) = payment.fee_type {
+            let own: Vec<usize> = oldest_first
+                .iter()
+                .copied()
+                .filter(|&i| {
+                    lines[i].fee_type == fee_type
+                        && payment
+                            .month
+                            .as_ref()
+                            .map_or(true, |m| *m == lines[i].month)
+                })
+                .collect();
+            if own.is_empty() {
+                continue;
+            }
+            spread(db, payment, &mut lines, &own)?;
+        }
+        spread(db, payment, &mut lines, &oldest_first)?;
+    }
+    Ok(())
+}
+
+// Applies a lump sum to the chosen lines first, in the order given, then
+// treats the rest like any other lump sum.
+pub(super) fn allocate_selected(
+    db: &Connection,
+    payment_id: i32,
+    invoice_line_ids: &[i32],
+) -> Result<()> {
+    let payment = Payment::get_with(db, payment_id)?;
+    let mut lines = open_lines(db, payment.student_id)?;
+    let mut order = Vec::new();
+    for id in invoice_line_ids {
+        let index = lines
+            .iter()
+            .position(|l| l.id == *id)
+            .ok_or_else(|| allocation_error("Invoice line is not billed to this student"))?;
+        if order.contains(&index) {
+            return Err(allocation_error("The same invoice line is selected twice"));
+        }
+        if lines[index].outstanding <= 0 {
+            return Err(allocation_error(&format!(
+                "Invoice line {} is already paid",
+                id
+            )));
+        }
+        order.push(index);
+    }
+
+    let mut open = OpenPayment {
+        id: payment.id,
+        fee_type: payment.fee_type,
+        month: payment.month,
+        remaining: payment.amount,
+    };
+    spread(db, &mut open, &mut lines, &order)?;
+    allocate_with(db, payment.student_id)
+}
+
+// Money paid but not yet set against any fee
+pub fn credit_with(db: &Connection, student_id: i32) -> Result<i32> {
+    db.query_row(
+        "SELECT COALESCE(SUM(p.amount), 0)
+                - COALESCE((SELECT SUM(a.amount) FROM payment_allocations a
+                            JOIN payments q ON q.id = a.payment_id
+                            WHERE q.student_id = ?1), 0)
+         FROM payments p WHERE p.student_id = ?1",
+        params![student_id],
+        |row| row.get(0),
+    )
+}
+
+pub fn get_credit(student_id: i32) -> Result<i32> {
+    let db = conn()?;
+    credit_with(&db, student_id)
+}
+
+// The day a line was paid in full, if it has been; allocations are taken
+// in payment order.
+pub(super) fn settled_on(
+    db: &Connection,
+    invoice_line_id: i32,
+    due: i32,
+) -> Result<Option<NaiveDate>> {
+    let mut stmt = db.prepare(
+        "SELECT p.payment_date, a.amount
+         FROM payment_allocations a JOIN payments p ON p.id = a.payment_id
+         WHERE a.invoice_line_id = ?1
+         ORDER BY p.payment_date ASC, p.id ASC",
+    )?;
+    let rows = stmt.query_map(params![invoice_line_id], |row| {
+        Ok((row.get::<_, NaiveDate>(0)?, row.get::<_, i32>(1)?))
+    })?;
+    let mut paid = 0;
+    for row in rows {
+        let (date, amount) = row?;
+        paid += amount;
+        if paid >= due {
+            return Ok(Some(date));
+        }
+    }
+    Ok(None)
+}
+
+// Corrects how a payment was spread. With `allocations` the payment is set
+// against exactly those lines and anything left is held as credit; without,
+// it is spread again automatically. Lines freed by the change are covered
+// from other credit where there is some.
+pub fn reallocate(
+    payment_id: i32,
+    allocations: Option<Vec<AllocationInput>>,
+) -> Result<PaymentAllocations> {
+    let mut db = conn()?;
+    db.execute("PRAGMA foreign_keys = ON", [])?;
+    let tx = db.transaction()?;
+
+    let payment = Payment::get_with(&tx, payment_id)?;
+    let before = PaymentAllocations::get_with(&tx, payment_id)?;
+    tx.execute(
+        "DELETE FROM payment_allocations WHERE payment_id = ?1",
+        params![payment_id],
+    )?;
+
+    match &allocations {
+        Some(allocations) => {
+            let lines = open_lines(&tx, payment.student_id)?;
+            let mut total = 0;
+            for (index, input) in allocations.iter().enumerate() {
+                if allocations[..index]
+                    .iter()
+                    .any(|a| a.invoice_line_id == input.invoice_line_id)
+                {
+                    return Err(allocation_error("The same invoice line is listed twice"));
+                }
+                if input.amount <= 0 {
+                    return Err(allocation_error("Allocated amounts must be positive"));
+                }
+                let line = lines
+                    .iter()
+                    .find(|l| l.id == input.invoice_line_id)
+                    .ok_or_else(|| {
+                        allocation_error("Invoice line is not billed to this student")
+                    })?;
+                if input.amount > line.outstanding {
+                    return Err(allocation_error(&format!(
+                        "Only {} Tk is owed on {} {}",
+                        line.outstanding.max(0),
+                        line.fee_type.label(),
+                        line.month
+                    )));
+                }
+                total += input.amount;
+                if total > payment.amount {
+                    return Err(allocation_error("More is allocated than was paid"));
+                }
+                add(&tx, payment_id, input.invoice_line_id, input.amount)?;
+            }
+            // Other credit may fill what this payment gave up, but what it
+            // holds back stays with it until the next allocation.
+            allocate_except(&tx, payment.student_id, Some(payment_id))?;
+        }
+        None => allocate_with(&tx, payment.student_id)?,
+    }
+
+    let after = PaymentAllocations::get_with(&tx, payment_id)?;
+    let describe = |p: &PaymentAllocations| {
+        let mut parts: Vec<String> = p
+            .allocations
+            .iter()
+            .map(|a| format!("{} {}", a.description, a.amount))
+            .collect();
+        if p.unallocated > 0 {
+            parts.push(format!("credit {}", p.unallocated));
+        }
+        parts.join(", ")
+    };
+    History::record(
+        &tx,
+        "payment",
+        payment_id,
+        "reallocate",
+        Some(format!("{} -> {}", describe(&before), describe(&after))),
+    )?;
+
+    tx.commit()?;
+    Ok(after)
+}
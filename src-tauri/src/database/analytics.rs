@@ -0,0 +1,428 @@
+use super::attendance::{AttendanceStatus, AttendanceSummary};
+use super::calendar::SchoolCalendar;
+use super::conn;
+use super::settings::Setting;
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use rusqlite::{params, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const MIN_PERCENTAGE_KEY: &str = "chronic_absence_min_percentage";
+pub const CONSECUTIVE_ABSENCES_KEY: &str = "chronic_absence_consecutive_days";
+pub const LOOKBACK_DAYS_KEY: &str = "chronic_absence_lookback_days";
+
+// A student is flagged when attendance over the lookback window drops below
+// `min_percentage` or the current absence streak reaches
+// `consecutive_absences`. Either check is disabled by setting it to 0.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ChronicAbsenceRule {
+    pub min_percentage: f64,
+    pub consecutive_absences: i32,
+    pub lookback_days: i64,
+}
+
+impl Default for ChronicAbsenceRule {
+    fn default() -> Self {
+        Self {
+            min_percentage: 75.0,
+            consecutive_absences: 3,
+            lookback_days: 30,
+        }
+    }
+}
+
+impl ChronicAbsenceRule {
+    pub fn load() -> Result<Self> {
+        let default = Self::default();
+        let min_percentage = Setting::get(MIN_PERCENTAGE_KEY)?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default.min_percentage);
+        let consecutive_absences = Setting::get(CONSECUTIVE_ABSENCES_KEY)?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default.consecutive_absences);
+        let lookback_days = Setting::get(LOOKBACK_DAYS_KEY)?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default.lookback_days);
+
+        Ok(Self {
+            min_percentage,
+            consecutive_absences,
+            lookback_days,
+        })
+    }
+
+    pub fn save(&self) -> Result<Self> {
+        Setting::set(MIN_PERCENTAGE_KEY, &self.min_percentage.to_string())?;
+        Setting::set(
+            CONSECUTIVE_ABSENCES_KEY,
+            &self.consecutive_absences.to_string(),
+        )?;
+        Setting::set(LOOKBACK_DAYS_KEY, &self.lookback_days.to_string())?;
+        Ok(self.clone())
+    }
+
+    // First day of the window ending on `as_of`
+    pub fn window_start(&self, as_of: NaiveDate) -> NaiveDate {
+        as_of - Duration::days(self.lookback_days.max(1) - 1)
+    }
+
+    pub fn reasons(&self, analytics: &StudentAnalytics) -> Vec<String> {
+        let mut reasons = Vec::new();
+
+        if self.min_percentage > 0.0 {
+            if let Some(percentage) = analytics.summary.percentage {
+                if percentage < self.min_percentage {
+                    reasons.push(format!(
+                        "Attendance {:.1}% is below {}%",
+                        percentage, self.min_percentage
+                    ));
+                }
+            }
+        }
+
+        if self.consecutive_absences > 0 && analytics.current_streak >= self.consecutive_absences {
+            reasons.push(format!(
+                "Absent {} working days in a row",
+                analytics.current_streak
+            ));
+        }
+
+        reasons
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AbsenceStreak {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub days: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WeekdayPattern {
+    pub weekday: Weekday,
+    pub working_days: i32,
+    pub absences: i32,
+    pub percentage: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StudentAnalytics {
+    pub student_id: i32,
+    pub name: String,
+    pub roll: i32,
+    pub class_id: i32,
+    pub class_name: String,
+    pub section_id: Option<i32>,
+    pub section_name: Option<String>,
+    pub summary: AttendanceSummary,
+    pub longest_streak: Option<AbsenceStreak>,
+    // Absences in a row up to the latest marked working day
+    pub current_streak: i32,
+    pub weekdays: Vec<WeekdayPattern>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SectionAnalytics {
+    pub session_id: i32,
+    pub class_id: Option<i32>,
+    pub section_id: Option<i32>,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub summary: AttendanceSummary,
+    pub weekdays: Vec<WeekdayPattern>,
+    pub students: Vec<StudentAnalytics>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ChronicAbsenceAlert {
+    pub student_id: i32,
+    pub name: String,
+    pub roll: i32,
+    pub class_name: String,
+    pub section_name: Option<String>,
+    pub percentage: Option<f64>,
+    pub current_streak: i32,
+    pub reasons: Vec<String>,
+}
+
+struct Enrolled {
+    id: i32,
+    name: String,
+    roll: i32,
+    class_id: i32,
+    class_name: String,
+    section_id: Option<i32>,
+    section_name: Option<String>,
+}
+
+type Marks = Vec<(NaiveDate, AttendanceStatus)>;
+
+fn load(
+    session_id: i32,
+    class_id: Option<i32>,
+    section_id: Option<i32>,
+    student_id: Option<i32>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<(Vec<Enrolled>, HashMap<i32, Marks>)> {
+    let db = conn()?;
+
+    let mut filter = String::from(" WHERE s.session_id = ?");
+    let mut params_c: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(session_id)];
+
+    if let Some(class_id) = class_id {
+        filter.push_str(" AND s.class_id = ?");
+        params_c.push(Box::new(class_id));
+    }
+
+    if let Some(section_id) = section_id {
+        filter.push_str(" AND s.section_id = ?");
+        params_c.push(Box::new(section_id));
+    }
+
+    if let Some(student_id) = student_id {
+        filter.push_str(" AND s.id = ?");
+        params_c.push(Box::new(student_id));
+    }
+
+    let param_refs: Vec<&dyn rusqlite::ToSql> = params_c.iter().map(|p| p.as_ref()).collect();
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT s.id, s.name, s.roll, s.class_id, c.name, s.section_id, sec.name
+         FROM students s
+         JOIN classes c ON s.class_id = c.id
+         LEFT JOIN sections sec ON s.section_id = sec.id
+         {}
+         ORDER BY c.level ASC, sec.name ASC, s.roll ASC",
+        filter
+    ))?;
+    let students = stmt
+        .query_map(param_refs.as_slice(), |row| {
+            Ok(Enrolled {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                roll: row.get(2)?,
+                class_id: row.get(3)?,
+                class_name: row.get(4)?,
+                section_id: row.get(5)?,
+                section_name: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut marks: HashMap<i32, Marks> = HashMap::new();
+    let mut params_m = params_c;
+    params_m.push(Box::new(from));
+    params_m.push(Box::new(to));
+    let param_refs: Vec<&dyn rusqlite::ToSql> = params_m.iter().map(|p| p.as_ref()).collect();
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT a.student_id, a.date, a.status
+         FROM attendance a
+         JOIN students s ON a.student_id = s.id
+         {} AND a.date >= ? AND a.date <= ?
+         ORDER BY a.date ASC",
+        filter
+    ))?;
+    let rows = stmt.query_map(param_refs.as_slice(), |row| {
+        Ok((row.get::<_, i32>(0)?, row.get(1)?, row.get(2)?))
+    })?;
+    for row in rows {
+        let (student_id, date, status) = row?;
+        marks.entry(student_id).or_default().push((date, status));
+    }
+
+    Ok((students, marks))
+}
+
+// Streaks run over working days only; an unmarked working day or any other
+// status ends a streak, except that unmarked days at the end of the range
+// (today's register not taken yet) do not reset the current one.
+fn streaks(marks: &Marks, working_days: &[NaiveDate]) -> (Option<AbsenceStreak>, i32) {
+    let by_date: HashMap<NaiveDate, AttendanceStatus> = marks.iter().copied().collect();
+    let days: Vec<(NaiveDate, Option<AttendanceStatus>)> = working_days
+        .iter()
+        .map(|d| (*d, by_date.get(d).copied()))
+        .collect();
+    let last_marked = days.iter().rposition(|(_, s)| s.is_some());
+
+    let mut longest: Option<AbsenceStreak> = None;
+    let mut run: Option<AbsenceStreak> = None;
+
+    for (date, status) in &days {
+        if *status == Some(AttendanceStatus::Absent) {
+            let streak = run.get_or_insert(AbsenceStreak {
+                start: *date,
+                end: *date,
+                days: 0,
+            });
+            streak.end = *date;
+            streak.days += 1;
+            if longest.as_ref().map_or(true, |l| streak.days > l.days) {
+                longest = Some(streak.clone());
+            }
+        } else {
+            run = None;
+        }
+    }
+
+    let current = match last_marked {
+        Some(last) => days[..=last]
+            .iter()
+            .rev()
+            .take_while(|(_, s)| *s == Some(AttendanceStatus::Absent))
+            .count() as i32,
+        None => 0,
+    };
+
+    (longest, current)
+}
+
+fn weekday_patterns(marks: &Marks, working_days: &[NaiveDate]) -> Vec<WeekdayPattern> {
+    let mut weekdays: Vec<Weekday> = working_days.iter().map(|d| d.weekday()).collect();
+    weekdays.sort_by_key(|w| w.num_days_from_monday());
+    weekdays.dedup();
+
+    weekdays
+        .into_iter()
+        .map(|weekday| {
+            let days: Vec<NaiveDate> = working_days
+                .iter()
+                .copied()
+                .filter(|d| d.weekday() == weekday)
+                .collect();
+            let summary = AttendanceSummary::from_marks(marks, &days);
+            WeekdayPattern {
+                weekday,
+                working_days: summary.working_days,
+                absences: summary
+                    .counts
+                    .get(AttendanceStatus::Absent.as_str())
+                    .copied()
+                    .unwrap_or(0),
+                percentage: summary.percentage,
+            }
+        })
+        .collect()
+}
+
+fn analyse(student: Enrolled, marks: &Marks, working_days: &[NaiveDate]) -> StudentAnalytics {
+    let (longest_streak, current_streak) = streaks(marks, working_days);
+    StudentAnalytics {
+        student_id: student.id,
+        name: student.name,
+        roll: student.roll,
+        class_id: student.class_id,
+        class_name: student.class_name,
+        section_id: student.section_id,
+        section_name: student.section_name,
+        summary: AttendanceSummary::from_marks(marks, working_days),
+        longest_streak,
+        current_streak,
+        weekdays: weekday_patterns(marks, working_days),
+    }
+}
+
+impl StudentAnalytics {
+    pub fn get(student_id: i32, from: NaiveDate, to: NaiveDate) -> Result<Self> {
+        let session_id: i32 = {
+            let db = conn()?;
+            db.query_row(
+                "SELECT session_id FROM students WHERE id = ?1",
+                params![student_id],
+                |row| row.get(0),
+            )?
+        };
+
+        let working_days = SchoolCalendar::load(session_id)?.working_days(from, to);
+        let (students, marks) = load(session_id, None, None, Some(student_id), from, to)?;
+        let student = students
+            .into_iter()
+            .next()
+            .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        let marks = marks.get(&student_id).cloned().unwrap_or_default();
+
+        Ok(analyse(student, &marks, &working_days))
+    }
+}
+
+impl SectionAnalytics {
+    pub fn get(
+        session_id: i32,
+        class_id: Option<i32>,
+        section_id: Option<i32>,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Self> {
+        let working_days = SchoolCalendar::load(session_id)?.working_days(from, to);
+        let (students, marks) = load(session_id, class_id, section_id, None, from, to)?;
+
+        let empty = Vec::new();
+        let students: Vec<StudentAnalytics> = students
+            .into_iter()
+            .map(|s| {
+                let student_marks = marks.get(&s.id).unwrap_or(&empty);
+                analyse(s, student_marks, &working_days)
+            })
+            .collect();
+
+        let all: Marks = marks.into_values().flatten().collect();
+        let mut summary = AttendanceSummary::from_marks(&all, &working_days);
+        // `from_marks` counts one mark per working day; for a whole section
+        // the unmarked figure is the number of empty register cells instead.
+        summary.working_days = working_days.len() as i32;
+        summary.unmarked_days = students.iter().map(|s| s.summary.unmarked_days).sum();
+
+        let weekdays = weekday_patterns(&all, &working_days);
+
+        Ok(Self {
+            session_id,
+            class_id,
+            section_id,
+            from,
+            to,
+            summary,
+            weekdays,
+            students,
+        })
+    }
+}
+
+// Students of the session matching the chronic-absence rule over the window
+// ending on `as_of`, worst attendance first.
+pub fn chronic_absentees(session_id: i32, as_of: NaiveDate) -> Result<Vec<ChronicAbsenceAlert>> {
+    let rule = ChronicAbsenceRule::load()?;
+    let from = rule.window_start(as_of);
+    let section = SectionAnalytics::get(session_id, None, None, from, as_of)?;
+
+    let mut alerts: Vec<ChronicAbsenceAlert> = section
+        .students
+        .into_iter()
+        .filter_map(|student| {
+            let reasons = rule.reasons(&student);
+            if reasons.is_empty() {
+                return None;
+            }
+            Some(ChronicAbsenceAlert {
+                student_id: student.student_id,
+                name: student.name,
+                roll: student.roll,
+                class_name: student.class_name,
+                section_name: student.section_name,
+                percentage: student.summary.percentage,
+                current_streak: student.current_streak,
+                reasons,
+            })
+        })
+        .collect();
+
+    alerts.sort_by(|a, b| {
+        a.percentage
+            .unwrap_or(100.0)
+            .total_cmp(&b.percentage.unwrap_or(100.0))
+            .then(b.current_streak.cmp(&a.current_streak))
+    });
+
+    Ok(alerts)
+}
@@ -0,0 +1,616 @@
+use super::calendar::{working_days_in, SchoolCalendar};
+use super::conn;
+use super::settings::Setting;
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const LOCK_DAYS_KEY: &str = "attendance_lock_days";
+pub const DEFAULT_LOCK_DAYS: i64 = 7;
+
+// Shared by student and staff attendance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AttendanceStatus {
+    Present,
+    Absent,
+    Late,
+    Excused,
+    OnLeave,
+    HalfDay,
+}
+
+impl AttendanceStatus {
+    pub const ALL: [Self; 6] = [
+        Self::Present,
+        Self::Absent,
+        Self::Late,
+        Self::Excused,
+        Self::OnLeave,
+        Self::HalfDay,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Present => "PRESENT",
+            Self::Absent => "ABSENT",
+            Self::Late => "LATE",
+            Self::Excused => "EXCUSED",
+            Self::OnLeave => "ON_LEAVE",
+            Self::HalfDay => "HALF_DAY",
+        }
+    }
+
+    // Short code used in registers
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Present => "P",
+            Self::Absent => "A",
+            Self::Late => "L",
+            Self::Excused => "E",
+            Self::OnLeave => "LV",
+            Self::HalfDay => "H",
+        }
+    }
+
+    // Share of a day counted as attended. Excused and leave days are left out
+    // of the percentage altogether instead of counting against the student.
+    pub fn weight(&self) -> Option<f64> {
+        match self {
+            Self::Present | Self::Late => Some(1.0),
+            Self::HalfDay => Some(0.5),
+            Self::Absent => Some(0.0),
+            Self::Excused | Self::OnLeave => None,
+        }
+    }
+
+    // Accepts the canonical names as well as the older free-text values such
+    // as "present" or "LATE-10m"; anything after the name becomes the remark.
+    pub fn parse(value: &str) -> Option<(Self, Option<String>)> {
+        let value = value.trim();
+        let normalized = value.to_uppercase().replace([' ', '-'], "_");
+
+        let short = match normalized.as_str() {
+            "P" => Some(Self::Present),
+            "A" => Some(Self::Absent),
+            "L" => Some(Self::Late),
+            "E" => Some(Self::Excused),
+            "LV" => Some(Self::OnLeave),
+            "H" => Some(Self::HalfDay),
+            _ => None,
+        };
+        if let Some(status) = short {
+            return Some((status, None));
+        }
+
+        let names = [
+            ("HALF_DAY", Self::HalfDay),
+            ("ON_LEAVE", Self::OnLeave),
+            ("PRESENT", Self::Present),
+            ("ABSENT", Self::Absent),
+            ("EXCUSED", Self::Excused),
+            ("LATE", Self::Late),
+            ("LEAVE", Self::OnLeave),
+        ];
+        for (name, status) in names {
+            if normalized == name {
+                return Some((status, None));
+            }
+            if normalized.starts_with(&format!("{}_", name)) {
+                let remark = value[name.len() + 1..].trim();
+                return Some((status, Some(remark.to_string()).filter(|r| !r.is_empty())));
+            }
+        }
+
+        None
+    }
+
+    pub fn check_constraint() -> String {
+        let values: Vec<String> = Self::ALL
+            .iter()
+            .map(|s| format!("'{}'", s.as_str()))
+            .collect();
+        format!("CHECK (status IN ({}))", values.join(", "))
+    }
+}
+
+impl ToSql for AttendanceStatus {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for AttendanceStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|s| s.as_str() == text)
+            .ok_or_else(|| {
+                FromSqlError::Other(format!("Unknown attendance status: {}", text).into())
+            })
+    }
+}
+
+// Turns the command-level status string into a status and remark. An explicit
+// remark wins over one embedded in the status.
+pub fn parse_status(
+    status: &str,
+    remark: Option<String>,
+) -> std::result::Result<(AttendanceStatus, Option<String>), String> {
+    let (status, embedded) =
+        AttendanceStatus::parse(status).ok_or_else(|| format!("Invalid status: {}", status))?;
+    let remark = remark.filter(|r| !r.trim().is_empty()).or(embedded);
+    Ok((status, remark))
+}
+
+pub fn parse_time(value: Option<String>) -> std::result::Result<Option<NaiveTime>, String> {
+    match value {
+        Some(value) if !value.trim().is_empty() => NaiveTime::parse_from_str(value.trim(), "%H:%M")
+            .or_else(|_| NaiveTime::parse_from_str(value.trim(), "%H:%M:%S"))
+            .map(Some)
+            .map_err(|e| format!("Invalid time: {}", e)),
+        _ => Ok(None),
+    }
+}
+
+// Rebuilds an attendance table created before statuses were typed: the old
+// rows are copied into the new schema with their status parsed. Text that
+// cannot be understood becomes EXCUSED, which counts neither way, with the
+// original kept as the remark for someone to correct.
+pub fn migrate_legacy_table(
+    db: &mut Connection,
+    table: &str,
+    owner_column: &str,
+    create_sql: &str,
+) -> Result<()> {
+    let sql: String = db.query_row(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        params![table],
+        |row| row.get(0),
+    )?;
+    if sql.contains("CHECK (status") {
+        return Ok(());
+    }
+
+    let legacy = format!("{}_legacy", table);
+    let tx = db.transaction()?;
+    tx.execute(&format!("ALTER TABLE {} RENAME TO {}", table, legacy), [])?;
+    tx.execute(create_sql, [])?;
+
+    let rows: Vec<(i32, i32, NaiveDate, String)> = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT id, {}, date, status FROM {}",
+            owner_column, legacy
+        ))?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        rows.collect::<Result<_>>()?
+    };
+
+    {
+        let mut insert = tx.prepare(&format!(
+            "INSERT INTO {} (id, {}, date, status, remark) VALUES (?1, ?2, ?3, ?4, ?5)",
+            table, owner_column
+        ))?;
+        for (id, owner, date, status) in rows {
+            let (status, remark) = AttendanceStatus::parse(&status).unwrap_or_else(|| {
+                log::warn!(
+                    "Unknown attendance status {:?} in {} row {}",
+                    status,
+                    table,
+                    id
+                );
+                (AttendanceStatus::Excused, Some(status.clone()))
+            });
+            insert.execute(params![id, owner, date, status, remark])?;
+        }
+    }
+
+    tx.execute(&format!("DROP TABLE {}", legacy), [])?;
+    tx.commit()
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AttendanceSummary {
+    pub counts: BTreeMap<String, i32>,
+    pub working_days: i32,
+    pub marked_days: i32,
+    pub unmarked_days: i32,
+    pub counted_days: i32,
+    pub attended_days: f64,
+    pub percentage: Option<f64>,
+}
+
+impl AttendanceSummary {
+    pub fn from_statuses<I: IntoIterator<Item = AttendanceStatus>>(statuses: I) -> Self {
+        let mut summary = Self::default();
+        for status in statuses {
+            *summary
+                .counts
+                .entry(status.as_str().to_string())
+                .or_insert(0) += 1;
+            summary.marked_days += 1;
+            if let Some(weight) = status.weight() {
+                summary.counted_days += 1;
+                summary.attended_days += weight;
+            }
+        }
+        if summary.counted_days > 0 {
+            let percentage = summary.attended_days * 100.0 / summary.counted_days as f64;
+            summary.percentage = Some((percentage * 100.0).round() / 100.0);
+        }
+        summary
+    }
+
+    // Only marks on working days of the school calendar are counted
+    pub fn from_marks(marks: &[(NaiveDate, AttendanceStatus)], working_days: &[NaiveDate]) -> Self {
+        let statuses = marks
+            .iter()
+            .filter(|(date, _)| working_days.binary_search(date).is_ok())
+            .map(|(_, status)| *status);
+        let mut summary = Self::from_statuses(statuses);
+        summary.working_days = working_days.len() as i32;
+        summary.unmarked_days = (summary.working_days - summary.marked_days).max(0);
+        summary
+    }
+
+    pub fn for_student(student_id: i32, from: NaiveDate, to: NaiveDate) -> Result<Self> {
+        let session_id: i32 = {
+            let db = conn()?;
+            db.query_row(
+                "SELECT session_id FROM students WHERE id = ?1",
+                params![student_id],
+                |row| row.get(0),
+            )?
+        };
+        let working_days = SchoolCalendar::load(session_id)?.working_days(from, to);
+        let marks = get_marks("attendance", "student_id", student_id, from, to)?;
+        Ok(Self::from_marks(&marks, &working_days))
+    }
+
+    pub fn for_staff(staff_id: i32, from: NaiveDate, to: NaiveDate) -> Result<Self> {
+        let calendars = SchoolCalendar::load_overlapping(from, to)?;
+        let working_days = working_days_in(&calendars, from, to);
+        let marks = get_marks("attendance_staff", "staff_id", staff_id, from, to)?;
+        Ok(Self::from_marks(&marks, &working_days))
+    }
+}
+
+pub fn get_marks(
+    table: &str,
+    owner_column: &str,
+    owner_id: i32,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<(NaiveDate, AttendanceStatus)>> {
+    let db = conn()?;
+    let mut stmt = db.prepare(&format!(
+        "SELECT date, status FROM {} WHERE {} = ?1 AND date >= ?2 AND date <= ?3 ORDER BY date ASC",
+        table, owner_column
+    ))?;
+    let rows = stmt.query_map(params![owner_id, from, to], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?;
+    rows.collect()
+}
+
+// Attendance dated before the returned day is read-only and can only be
+// changed through `AttendanceOverride::apply`. A lock window of 0 days turns
+// the lock off.
+pub fn locked_before(db: &Connection) -> Result<Option<NaiveDate>> {
+    let days = Setting::read(db, LOCK_DAYS_KEY)?
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_LOCK_DAYS);
+    if days <= 0 {
+        return Ok(None);
+    }
+    Ok(Some(
+        Local::now().naive_local().date() - Duration::days(days),
+    ))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AttendanceLock {
+    pub days: i64,
+    pub locked_before: Option<NaiveDate>,
+}
+
+impl AttendanceLock {
+    pub fn load() -> Result<Self> {
+        let db = conn()?;
+        let days = Setting::read(&db, LOCK_DAYS_KEY)?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_LOCK_DAYS);
+        Ok(Self {
+            days,
+            locked_before: locked_before(&db)?,
+        })
+    }
+
+    pub fn set(days: u32) -> Result<Self> {
+        Setting::set(LOCK_DAYS_KEY, &days.to_string())?;
+        Self::load()
+    }
+}
+
+pub fn ensure_unlocked(db: &Connection, date: NaiveDate) -> Result<()> {
+    match locked_before(db)? {
+        Some(limit) if date < limit => Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error {
+                code: rusqlite::ffi::ErrorCode::PermissionDenied,
+                extended_code: 0,
+            },
+            Some(format!(
+                "Attendance for {} is locked; an override with a reason is required",
+                date
+            )),
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AttendanceKind {
+    Student,
+    Staff,
+}
+
+impl AttendanceKind {
+    pub const ALL: [Self; 2] = [Self::Student, Self::Staff];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Student => "STUDENT",
+            Self::Staff => "STAFF",
+        }
+    }
+
+    fn table(&self) -> &'static str {
+        match self {
+            Self::Student => "attendance",
+            Self::Staff => "attendance_staff",
+        }
+    }
+
+    fn owner_column(&self) -> &'static str {
+        match self {
+            Self::Student => "student_id",
+            Self::Staff => "staff_id",
+        }
+    }
+}
+
+impl ToSql for AttendanceKind {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for AttendanceKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == text)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown attendance kind: {}", text).into()))
+    }
+}
+
+// One change made to locked attendance. A missing status means the mark did
+// not exist before, or was removed by the override.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AttendanceOverride {
+    pub id: i32,
+    pub kind: AttendanceKind,
+    pub owner_id: i32,
+    pub owner_name: Option<String>,
+    pub date: NaiveDate,
+    pub old_status: Option<AttendanceStatus>,
+    pub new_status: Option<AttendanceStatus>,
+    pub old_remark: Option<String>,
+    pub new_remark: Option<String>,
+    pub reason: String,
+    pub changed_by: String,
+    pub changed_at: NaiveDateTime,
+}
+
+impl AttendanceOverride {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS attendance_overrides (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    kind TEXT NOT NULL CHECK (kind IN ('STUDENT', 'STAFF')),
+                    owner_id INTEGER NOT NULL,
+                    date DATE NOT NULL,
+                    old_status TEXT,
+                    new_status TEXT,
+                    old_remark TEXT,
+                    new_remark TEXT,
+                    reason TEXT NOT NULL,
+                    changed_by TEXT NOT NULL,
+                    changed_at DATETIME NOT NULL,
+                    {}
+                )",
+                AttendanceStatus::check_constraint().replace("status", "new_status")
+            ),
+            [],
+        )?;
+        Ok(())
+    }
+
+    // Sets (or with `status` of None, removes) a mark regardless of the
+    // lock, keeping the previous value in the override trail.
+    #[allow(clippy::too_many_arguments)]
+    pub fn apply(
+        kind: AttendanceKind,
+        owner_id: i32,
+        date: NaiveDate,
+        status: Option<AttendanceStatus>,
+        remark: Option<String>,
+        arrival_time: Option<NaiveTime>,
+        reason: &str,
+        changed_by: &str,
+    ) -> Result<Self> {
+        let reason = reason.trim();
+        let changed_by = changed_by.trim();
+        if reason.is_empty() || changed_by.is_empty() {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error {
+                    code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+                    extended_code: 0,
+                },
+                Some("An override needs a reason and the name of who made it".to_string()),
+            ));
+        }
+
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        let table = kind.table();
+        let owner_column = kind.owner_column();
+
+        let old: Option<(AttendanceStatus, Option<String>)> = tx
+            .query_row(
+                &format!(
+                    "SELECT status, remark FROM {} WHERE {} = ?1 AND date = ?2",
+                    table, owner_column
+                ),
+                params![owner_id, date],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        match status {
+            Some(status) => {
+                tx.execute(
+                    &format!(
+                        "INSERT INTO {} ({}, date, status, remark, arrival_time)
+                         VALUES (?1, ?2, ?3, ?4, ?5)
+                         ON CONFLICT({}, date) DO UPDATE SET
+                            status = excluded.status,
+                            remark = excluded.remark,
+                            arrival_time = COALESCE(excluded.arrival_time, arrival_time)",
+                        table, owner_column, owner_column
+                    ),
+                    params![owner_id, date, status, remark, arrival_time],
+                )?;
+            }
+            None => {
+                tx.execute(
+                    &format!(
+                        "DELETE FROM {} WHERE {} = ?1 AND date = ?2",
+                        table, owner_column
+                    ),
+                    params![owner_id, date],
+                )?;
+            }
+        }
+
+        if kind == AttendanceKind::Staff && status.is_some() {
+            let id: i32 = tx.query_row(
+                "SELECT id FROM attendance_staff WHERE staff_id = ?1 AND date = ?2",
+                params![owner_id, date],
+                |row| row.get(0),
+            )?;
+            super::shift::apply_timings(&tx, id)?;
+        }
+
+        let changed_at = Local::now().naive_local();
+        let (old_status, old_remark) = match old {
+            Some((status, remark)) => (Some(status), remark),
+            None => (None, None),
+        };
+        let new_remark = status.and(remark);
+        tx.execute(
+            "INSERT INTO attendance_overrides (kind, owner_id, date, old_status, new_status,
+                old_remark, new_remark, reason, changed_by, changed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                kind, owner_id, date, old_status, status, old_remark, new_remark, reason,
+                changed_by, changed_at
+            ],
+        )?;
+        let id = tx.last_insert_rowid() as i32;
+        tx.commit()?;
+
+        Ok(Self {
+            id,
+            kind,
+            owner_id,
+            owner_name: None,
+            date,
+            old_status,
+            new_status: status,
+            old_remark,
+            new_remark,
+            reason: reason.to_string(),
+            changed_by: changed_by.to_string(),
+            changed_at,
+        })
+    }
+
+    // Overrides made between `from` and `to` (by when they were made), newest
+    // first
+    pub fn get(
+        kind: Option<AttendanceKind>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<Self>> {
+        let db = conn()?;
+
+        let mut query = String::from(
+            "SELECT o.id, o.kind, o.owner_id, COALESCE(s.name, st.name), o.date, o.old_status,
+                    o.new_status, o.old_remark, o.new_remark, o.reason, o.changed_by, o.changed_at
+             FROM attendance_overrides o
+             LEFT JOIN students s ON o.kind = 'STUDENT' AND s.id = o.owner_id
+             LEFT JOIN staffs st ON o.kind = 'STAFF' AND st.id = o.owner_id
+             WHERE 1 = 1",
+        );
+        let mut params_c: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+        if let Some(kind) = kind {
+            query.push_str(" AND o.kind = ?");
+            params_c.push(Box::new(kind));
+        }
+
+        if let Some(from) = from {
+            query.push_str(" AND date(o.changed_at) >= ?");
+            params_c.push(Box::new(from));
+        }
+
+        if let Some(to) = to {
+            query.push_str(" AND date(o.changed_at) <= ?");
+            params_c.push(Box::new(to));
+        }
+
+        query.push_str(" ORDER BY o.changed_at DESC, o.id DESC");
+
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params_c.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = db.prepare(&query)?;
+        let rows = stmt.query_map(param_refs.as_slice(), |row| {
+            Ok(Self {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                owner_id: row.get(2)?,
+                owner_name: row.get(3)?,
+                date: row.get(4)?,
+                old_status: row.get(5)?,
+                new_status: row.get(6)?,
+                old_remark: row.get(7)?,
+                new_remark: row.get(8)?,
+                reason: row.get(9)?,
+                changed_by: row.get(10)?,
+                changed_at: row.get(11)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
@@ -0,0 +1,434 @@
+use super::allocation::{allocate_with, credit_with};
+use super::discount::{apply_discounts, DiscountRule, StudentEligibility};
+use super::fee::{charges_for, month_key, parse_month, FeeType};
+use super::{add_column_if_missing, conn};
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// One billed fee. A student is billed at most once per fee type and month,
+// which is what makes re-running a month safe.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InvoiceLine {
+    pub id: i32,
+    pub student_id: i32,
+    pub session_id: i32,
+    pub class_id: i32,
+    pub fee_type: FeeType,
+    pub month: String,
+    // When the fee applies from: the admission date, or the start of the month
+    pub charge_date: NaiveDate,
+    pub description: String,
+    // The fee before discounts
+    pub amount: i32,
+    pub discount: i32,
+    // Names of the discount rules applied
+    pub discount_note: Option<String>,
+    pub created_at: NaiveDateTime,
+    // Late fees only
+    pub waived_at: Option<NaiveDateTime>,
+    pub waive_reason: Option<String>,
+}
+
+const LINE_COLUMNS: &str = "id, student_id, session_id, class_id, fee_type, month, charge_date,
+     description, amount, discount, discount_note, created_at, waived_at, waive_reason";
+
+impl InvoiceLine {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS invoice_lines (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    student_id INTEGER NOT NULL,
+                    session_id INTEGER NOT NULL,
+                    class_id INTEGER NOT NULL,
+                    fee_type TEXT NOT NULL {},
+                    month TEXT NOT NULL,
+                    charge_date DATE NOT NULL,
+                    description TEXT NOT NULL,
+                    amount INTEGER NOT NULL CHECK (amount >= 0),
+                    created_at DATETIME NOT NULL,
+                    FOREIGN KEY (student_id) REFERENCES students(id),
+                    UNIQUE (student_id, fee_type, month)
+                )",
+                FeeType::check_constraint("fee_type")
+            ),
+            [],
+        )?;
+        add_column_if_missing(
+            &db,
+            "invoice_lines",
+            "discount",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        add_column_if_missing(&db, "invoice_lines", "discount_note", "TEXT")?;
+        add_column_if_missing(&db, "invoice_lines", "waived_at", "DATETIME")?;
+        add_column_if_missing(&db, "invoice_lines", "waive_reason", "TEXT")?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            student_id: row.get(1)?,
+            session_id: row.get(2)?,
+            class_id: row.get(3)?,
+            fee_type: row.get(4)?,
+            month: row.get(5)?,
+            charge_date: row.get(6)?,
+            description: row.get(7)?,
+            amount: row.get(8)?,
+            discount: row.get(9)?,
+            discount_note: row.get(10)?,
+            created_at: row.get(11)?,
+            waived_at: row.get(12)?,
+            waive_reason: row.get(13)?,
+        })
+    }
+
+    pub fn get_by_student_with(db: &Connection, student_id: i32) -> Result<Vec<Self>> {
+        let mut stmt = db.prepare(&format!(
+            "SELECT {} FROM invoice_lines WHERE student_id = ?1 ORDER BY month ASC, id ASC",
+            LINE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![student_id], Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn get_by_student(student_id: i32) -> Result<Vec<Self>> {
+        let db = conn()?;
+        Self::get_by_student_with(&db, student_id)
+    }
+
+    // For correcting a wrong charge; the next billing run of that month
+    // bills it again.
+    pub fn delete(id: i32) -> Result<()> {
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        let student_id: i32 = tx.query_row(
+            "SELECT student_id FROM invoice_lines WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+        tx.execute("DELETE FROM invoice_lines WHERE id = ?1", params![id])?;
+        // Whatever was paid towards the line goes to what is still owed
+        allocate_with(&tx, student_id)?;
+
+        tx.commit()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct BillingRun {
+    pub session_id: i32,
+    pub month: String,
+    pub students: i32,
+    // Lines added by this run
+    pub created: i32,
+    // Billed after discounts
+    pub amount: i32,
+    pub discount: i32,
+    // Lines that were already billed
+    pub existing: i32,
+}
+
+// Bills every student of the session enrolled by the end of `month` for the
+// fees falling due in it: the monthly fee, plus the admission or readmission
+// fee in the month it applies. Discounts the student qualifies for on the
+// day the fee applies are taken off as the lines are written.
+pub fn run_billing(session_id: i32, month: &str) -> Result<BillingRun> {
+    let first = parse_month(month).ok_or(rusqlite::Error::InvalidQuery)?;
+    let last = first
+        .checked_add_months(chrono::Months::new(1))
+        .and_then(|d| d.pred_opt())
+        .ok_or(rusqlite::Error::InvalidQuery)?;
+    let month = month_key(first);
+
+    let mut db = conn()?;
+    db.execute("PRAGMA foreign_keys = ON", [])?;
+    let tx = db.transaction()?;
+
+    let students: Vec<(i32, i32)> = {
+        let mut stmt = tx.prepare(
+            "SELECT id, class_id FROM students
+             WHERE session_id = ?1 AND admission_date <= ?2
+             ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map(params![session_id, last], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        rows.collect::<Result<_>>()?
+    };
+
+    let mut run = BillingRun {
+        session_id,
+        month: month.clone(),
+        students: students.len() as i32,
+        ..Default::default()
+    };
+    let now = Local::now().naive_local();
+    let rules = DiscountRule::get_all_with(&tx)?;
+
+    for (student_id, class_id) in students {
+        let eligibility = StudentEligibility::get_with(&tx, student_id)?;
+        for charge in charges_for(&tx, student_id, last)? {
+            if month_key(charge.date) != month {
+                continue;
+            }
+            let mut description = if charge.fee_type.is_monthly() {
+                format!("{} {}", charge.fee_type.label(), month)
+            } else {
+                charge.fee_type.label().to_string()
+            };
+            if let Some(note) = &charge.note {
+                description = format!("{} ({})", description, note);
+            }
+            let applied = apply_discounts(
+                &rules,
+                &eligibility,
+                charge.fee_type,
+                charge.date,
+                charge.amount,
+            );
+            let discount: i32 = applied.iter().map(|d| d.amount).sum();
+            let discount_note = (!applied.is_empty()).then(|| {
+                applied
+                    .iter()
+                    .map(|d| d.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            });
+            let inserted = tx.execute(
+                "INSERT OR IGNORE INTO invoice_lines
+                    (student_id, session_id, class_id, fee_type, month, charge_date, description,
+                     amount, discount, discount_note, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    student_id,
+                    session_id,
+                    class_id,
+                    charge.fee_type,
+                    month,
+                    charge.date,
+                    description,
+                    charge.amount,
+                    discount,
+                    discount_note,
+                    now
+                ],
+            )?;
+            if inserted > 0 {
+                run.created += 1;
+                run.amount += charge.amount - discount;
+                run.discount += discount;
+            } else {
+                run.existing += 1;
+            }
+        }
+        // Credit held from earlier payments goes to the new lines
+        allocate_with(&tx, student_id)?;
+    }
+
+    tx.commit()?;
+    Ok(run)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DueLine {
+    pub invoice_line_id: i32,
+    pub month: String,
+    pub fee_type: FeeType,
+    pub description: String,
+    pub amount: i32,
+    pub discount: i32,
+    pub paid: i32,
+    pub outstanding: i32,
+}
+
+// What has been paid on each line is what payments were allocated to it
+pub fn due_lines(db: &Connection, student_id: i32) -> Result<Vec<DueLine>> {
+    let mut stmt = db.prepare(
+        "SELECT l.id, l.month, l.fee_type, l.description, l.amount, l.discount,
+                COALESCE((SELECT SUM(a.amount) FROM payment_allocations a
+                          WHERE a.invoice_line_id = l.id), 0)
+         FROM invoice_lines l
+         WHERE l.student_id = ?1
+         ORDER BY l.month ASC, l.id ASC",
+    )?;
+    let rows = stmt.query_map(params![student_id], |row| {
+        let amount: i32 = row.get(4)?;
+        let discount: i32 = row.get(5)?;
+        let paid: i32 = row.get(6)?;
+        Ok(DueLine {
+            invoice_line_id: row.get(0)?,
+            month: row.get(1)?,
+            fee_type: row.get(2)?,
+            description: row.get(3)?,
+            amount,
+            discount,
+            paid,
+            outstanding: (amount - discount - paid).max(0),
+        })
+    })?;
+    rows.collect()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StudentDues {
+    pub student_id: i32,
+    pub name: String,
+    pub roll: i32,
+    pub class_id: i32,
+    pub class_name: String,
+    pub section_id: Option<i32>,
+    pub section_name: Option<String>,
+    // Unpaid lines only
+    pub lines: Vec<DueLine>,
+    pub by_month: BTreeMap<String, i32>,
+    pub by_fee_type: BTreeMap<FeeType, i32>,
+    pub outstanding: i32,
+    // The outstanding split between hostel fees and everything else
+    pub tuition: i32,
+    pub resident: i32,
+    pub is_resident: bool,
+    // Paid in advance and not yet set against a fee
+    pub credit: i32,
+}
+
+// Students of the session with anything outstanding, most owed first
+pub fn get_dues(
+    session_id: i32,
+    class_id: Option<i32>,
+    section_id: Option<i32>,
+) -> Result<Vec<StudentDues>> {
+    let db = conn()?;
+    let students: Vec<StudentDues> = {
+        let mut stmt = db.prepare(
+            "SELECT s.id, s.name, s.roll, s.class_id, c.name, s.section_id, sec.name,
+                    s.is_resident
+             FROM students s
+             JOIN classes c ON c.id = s.class_id
+             LEFT JOIN sections sec ON sec.id = s.section_id
+             WHERE s.session_id = ?1
+               AND (?2 IS NULL OR s.class_id = ?2)
+               AND (?3 IS NULL OR s.section_id = ?3)
+             ORDER BY c.level ASC, s.roll ASC",
+        )?;
+        let rows = stmt.query_map(params![session_id, class_id, section_id], |row| {
+            Ok(StudentDues {
+                student_id: row.get(0)?,
+                name: row.get(1)?,
+                roll: row.get(2)?,
+                class_id: row.get(3)?,
+                class_name: row.get(4)?,
+                section_id: row.get(5)?,
+                section_name: row.get(6)?,
+                lines: Vec::new(),
+                by_month: BTreeMap::new(),
+                by_fee_type: BTreeMap::new(),
+                outstanding: 0,
+                tuition: 0,
+                resident: 0,
+                is_resident: row.get(7)?,
+                credit: 0,
+            })
+        })?;
+        rows.collect::<Result<_>>()?
+    };
+
+    let mut dues = Vec::new();
+    for mut student in students {
+        student.lines = due_lines(&db, student.student_id)?
+            .into_iter()
+            .filter(|l| l.outstanding > 0)
+            .collect();
+        if student.lines.is_empty() {
+            continue;
+        }
+        for line in &student.lines {
+            *student.by_month.entry(line.month.clone()).or_insert(0) += line.outstanding;
+            *student.by_fee_type.entry(line.fee_type).or_insert(0) += line.outstanding;
+        }
+        student.outstanding = student.lines.iter().map(|l| l.outstanding).sum();
+        student.resident = student
+            .by_fee_type
+            .get(&FeeType::Resident)
+            .copied()
+            .unwrap_or(0);
+        student.tuition = student.outstanding - student.resident;
+        student.credit = credit_with(&db, student.student_id)?;
+        dues.push(student);
+    }
+
+    dues.sort_by_key(|d| std::cmp::Reverse(d.outstanding));
+    Ok(dues)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Defaulter {
+    pub student_id: i32,
+    pub name: String,
+    pub roll: i32,
+    pub section_name: Option<String>,
+    pub months_due: i32,
+    pub oldest_month: String,
+    pub outstanding: i32,
+}
+
+// Students of a class owing monthly fees for at least `min_months` months
+// before `as_of`'s month; the current month is not yet counted as late.
+pub fn defaulters(
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+    min_months: i32,
+    as_of: NaiveDate,
+) -> Result<Vec<Defaulter>> {
+    let current = month_key(as_of);
+
+    let mut list: Vec<Defaulter> = get_dues(session_id, Some(class_id), section_id)?
+        .into_iter()
+        .filter_map(|dues| {
+            let late: Vec<&DueLine> = dues
+                .lines
+                .iter()
+                .filter(|l| {
+                    !matches!(l.fee_type, FeeType::Monthly | FeeType::Resident) || l.month < current
+                })
+                .collect();
+            let months: Vec<&String> = {
+                let mut months: Vec<&String> = late
+                    .iter()
+                    .filter(|l| l.fee_type == FeeType::Monthly)
+                    .map(|l| &l.month)
+                    .collect();
+                months.dedup();
+                months
+            };
+            if (months.len() as i32) < min_months.max(1) {
+                return None;
+            }
+            Some(Defaulter {
+                student_id: dues.student_id,
+                name: dues.name,
+                roll: dues.roll,
+                section_name: dues.section_name,
+                months_due: months.len() as i32,
+                oldest_month: months[0].clone(),
+                outstanding: late.iter().map(|l| l.outstanding).sum(),
+            })
+        })
+        .collect();
+
+    list.sort_by(|a, b| {
+        b.months_due
+            .cmp(&a.months_due)
+            .then(b.outstanding.cmp(&a.outstanding))
+    });
+    Ok(list)
+}
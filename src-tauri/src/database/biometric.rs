@@ -0,0 +1,371 @@
+use super::attendance::{locked_before, AttendanceStatus};
+use super::calendar::{is_working_day_in, SchoolCalendar};
+use super::conn;
+use super::shift::{apply_timings, Shift};
+use super::staff::Attendance;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+// Timestamp layouts seen in terminal exports
+const TIMESTAMP_FORMATS: [&str; 8] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y/%m/%d %H:%M:%S",
+    "%Y/%m/%d %H:%M",
+    "%d/%m/%Y %H:%M:%S",
+    "%d/%m/%Y %H:%M",
+    "%d-%m-%Y %H:%M:%S",
+];
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeviceMapping {
+    pub device_user_id: String,
+    pub staff_id: i32,
+    pub staff_name: String,
+}
+
+// Terminals pad ids differently ("0007" and "7"), so digits are compared
+// without leading zeros.
+pub fn normalize_device_id(id: &str) -> String {
+    let id = id.trim().trim_matches('"').trim();
+    if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) {
+        let trimmed = id.trim_start_matches('0');
+        return if trimmed.is_empty() { "0" } else { trimmed }.to_string();
+    }
+    id.to_string()
+}
+
+impl DeviceMapping {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS staff_device_ids (
+                device_user_id TEXT PRIMARY KEY,
+                staff_id INTEGER NOT NULL,
+                FOREIGN KEY (staff_id) REFERENCES staffs(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    pub fn set(staff_id: i32, device_user_id: &str) -> Result<Self> {
+        let device_user_id = normalize_device_id(device_user_id);
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "INSERT INTO staff_device_ids (device_user_id, staff_id) VALUES (?1, ?2)
+             ON CONFLICT(device_user_id) DO UPDATE SET staff_id = excluded.staff_id",
+            params![device_user_id, staff_id],
+        )?;
+        let staff_name = db.query_row(
+            "SELECT name FROM staffs WHERE id = ?1",
+            params![staff_id],
+            |row| row.get(0),
+        )?;
+
+        Ok(Self {
+            device_user_id,
+            staff_id,
+            staff_name,
+        })
+    }
+
+    pub fn get_all() -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(
+            "SELECT d.device_user_id, d.staff_id, s.name
+             FROM staff_device_ids d
+             JOIN staffs s ON d.staff_id = s.id
+             ORDER BY s.name ASC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Self {
+                device_user_id: row.get(0)?,
+                staff_id: row.get(1)?,
+                staff_name: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn delete(device_user_id: &str) -> Result<()> {
+        let db = conn()?;
+        let affected = db.execute(
+            "DELETE FROM staff_device_ids WHERE device_user_id = ?1",
+            params![normalize_device_id(device_user_id)],
+        )?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Punch {
+    pub device_user_id: String,
+    pub time: NaiveDateTime,
+}
+
+fn parse_timestamp(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim().trim_matches('"');
+    TIMESTAMP_FORMATS
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(value, f).ok())
+}
+
+// Reads "user id, timestamp, ..." lines from CSV, semicolon, tab or space
+// separated exports. The date and time may be one field or two. Returns the
+// punches and the numbers of the lines that could not be read; a header on
+// the first line is skipped silently.
+pub fn parse_log(content: &str) -> (Vec<Punch>, Vec<usize>) {
+    let mut punches = Vec::new();
+    let mut invalid = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = if line.contains([',', ';', '\t']) {
+            line.split([',', ';', '\t'])
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .collect()
+        } else {
+            line.split_whitespace().collect()
+        };
+
+        let time = fields.get(1).and_then(|f| parse_timestamp(f)).or_else(|| {
+            let date = fields.get(1)?;
+            let time = fields.get(2)?;
+            parse_timestamp(&format!("{} {}", date, time))
+        });
+
+        match (fields.first(), time) {
+            (Some(id), Some(time)) => punches.push(Punch {
+                device_user_id: normalize_device_id(id),
+                time,
+            }),
+            _ if index == 0 => {}
+            _ => invalid.push(index + 1),
+        }
+    }
+
+    (punches, invalid)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UnmatchedId {
+    pub device_user_id: String,
+    pub punches: i32,
+}
+
+struct DayMark {
+    staff_id: i32,
+    date: NaiveDate,
+    status: AttendanceStatus,
+    check_in: Option<NaiveTime>,
+    check_out: Option<NaiveTime>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct BiometricImport {
+    pub punches: i32,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub records: Vec<Attendance>,
+    pub present: i32,
+    pub late: i32,
+    pub absent: i32,
+    // Days that already had a mark and were left alone
+    pub skipped: i32,
+    // Days inside the attendance lock window
+    pub locked: i32,
+    pub unmatched: Vec<UnmatchedId>,
+    pub invalid_lines: Vec<usize>,
+}
+
+fn on_approved_leave(db: &Connection, staff_id: i32, date: NaiveDate) -> Result<bool> {
+    db.query_row(
+        "SELECT COUNT(*) > 0 FROM leave_applications
+         WHERE staff_id = ?1 AND status = 'APPROVED' AND ?2 BETWEEN from_date AND to_date",
+        params![staff_id, date],
+        |row| row.get(0),
+    )
+}
+
+// Turns a device log into staff attendance. Each staff member's punches on a
+// day collapse into the first (check-in) and last (check-out) time; check-in
+// after the shift start plus the grace period is LATE. With `mark_absent`,
+// mapped staff without punches on a working day in the log's range are marked
+// ABSENT, unless on approved leave. Existing marks are kept unless `overwrite`
+// is set; ON_LEAVE is never replaced by ABSENT.
+pub fn import_log(content: &str, overwrite: bool, mark_absent: bool) -> Result<BiometricImport> {
+    let (punches, invalid_lines) = parse_log(content);
+    let mut report = BiometricImport {
+        punches: punches.len() as i32,
+        invalid_lines,
+        ..Default::default()
+    };
+
+    let mappings: HashMap<String, i32> = DeviceMapping::get_all()?
+        .into_iter()
+        .map(|m| (m.device_user_id, m.staff_id))
+        .collect();
+
+    let mut days: BTreeMap<(i32, NaiveDate), Vec<NaiveTime>> = BTreeMap::new();
+    let mut unmatched: BTreeMap<String, i32> = BTreeMap::new();
+    for punch in punches {
+        match mappings.get(&punch.device_user_id) {
+            Some(staff_id) => days
+                .entry((*staff_id, punch.time.date()))
+                .or_default()
+                .push(punch.time.time()),
+            None => *unmatched.entry(punch.device_user_id).or_insert(0) += 1,
+        }
+    }
+    report.unmatched = unmatched
+        .into_iter()
+        .map(|(device_user_id, punches)| UnmatchedId {
+            device_user_id,
+            punches,
+        })
+        .collect();
+
+    let (Some(from), Some(to)) = (
+        days.keys().map(|(_, d)| *d).min(),
+        days.keys().map(|(_, d)| *d).max(),
+    ) else {
+        return Ok(report);
+    };
+    report.from = Some(from);
+    report.to = Some(to);
+
+    let calendars = SchoolCalendar::load_overlapping(from, to)?;
+
+    let mut db = conn()?;
+    db.execute("PRAGMA foreign_keys = ON", [])?;
+    let tx = db.transaction()?;
+
+    let locked = locked_before(&tx)?;
+    let mut late_after: HashMap<i32, NaiveTime> = HashMap::new();
+
+    let mut marks: Vec<DayMark> = Vec::new();
+    for ((staff_id, date), times) in &days {
+        let check_in = times.iter().min().copied();
+        let check_out = times.iter().max().copied().filter(|t| Some(*t) != check_in);
+        let late_after = match late_after.get(staff_id) {
+            Some(time) => *time,
+            None => {
+                let time = Shift::for_staff(&tx, *staff_id)?.late_after();
+                late_after.insert(*staff_id, time);
+                time
+            }
+        };
+        let status = match check_in {
+            Some(time) if time > late_after => AttendanceStatus::Late,
+            _ => AttendanceStatus::Present,
+        };
+        marks.push(DayMark {
+            staff_id: *staff_id,
+            date: *date,
+            status,
+            check_in,
+            check_out,
+        });
+    }
+
+    if mark_absent {
+        let mut staff: Vec<(i32, NaiveDate)> = {
+            let mut stmt = tx.prepare(
+                "SELECT DISTINCT s.id, s.hire_date FROM staffs s
+                 JOIN staff_device_ids d ON d.staff_id = s.id",
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_>>()?
+        };
+        staff.sort();
+
+        for date in from.iter_days().take_while(|d| *d <= to) {
+            if !is_working_day_in(&calendars, date) {
+                continue;
+            }
+            for (staff_id, hire_date) in &staff {
+                if date >= *hire_date
+                    && !days.contains_key(&(*staff_id, date))
+                    && !on_approved_leave(&tx, *staff_id, date)?
+                {
+                    marks.push(DayMark {
+                        staff_id: *staff_id,
+                        date,
+                        status: AttendanceStatus::Absent,
+                        check_in: None,
+                        check_out: None,
+                    });
+                }
+            }
+        }
+    }
+
+    for mark in marks {
+        if locked.is_some_and(|limit| mark.date < limit) {
+            report.locked += 1;
+            continue;
+        }
+
+        let existing: Option<AttendanceStatus> = tx
+            .query_row(
+                "SELECT status FROM attendance_staff WHERE staff_id = ?1 AND date = ?2",
+                params![mark.staff_id, mark.date],
+                |row| row.get(0),
+            )
+            .optional()?;
+        // A missing punch does not cancel a leave
+        let keep = match existing {
+            Some(AttendanceStatus::OnLeave) => mark.status == AttendanceStatus::Absent,
+            Some(_) => !overwrite,
+            None => false,
+        };
+        if keep {
+            report.skipped += 1;
+            continue;
+        }
+
+        let id: i32 = tx.query_row(
+            "INSERT INTO attendance_staff (staff_id, date, status, arrival_time, departure_time)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(staff_id, date) DO UPDATE SET
+                status = excluded.status,
+                arrival_time = excluded.arrival_time,
+                departure_time = excluded.departure_time
+             RETURNING id",
+            params![
+                mark.staff_id,
+                mark.date,
+                mark.status,
+                mark.check_in,
+                mark.check_out
+            ],
+            |row| row.get(0),
+        )?;
+        let record = apply_timings(&tx, id)?;
+
+        match mark.status {
+            AttendanceStatus::Late => report.late += 1,
+            AttendanceStatus::Absent => report.absent += 1,
+            _ => report.present += 1,
+        }
+        report.records.push(record);
+    }
+
+    tx.commit()?;
+    Ok(report)
+}
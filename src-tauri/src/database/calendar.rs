@@ -0,0 +1,402 @@
+use super::conn;
+use chrono::{Datelike, NaiveDate, Weekday};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, Type, ValueRef};
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+// Used for sessions whose weekly off-days were never configured
+pub const DEFAULT_WEEKLY_OFF_DAYS: [Weekday; 1] = [Weekday::Fri];
+// Stored in place of a weekday when a session is set to have none, so it is
+// not mistaken for one never configured
+const NO_WEEKLY_OFF: u8 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CalendarEventKind {
+    Holiday,
+    Exam,
+    WorkingDay,
+    Event,
+}
+
+impl CalendarEventKind {
+    pub const ALL: [Self; 4] = [Self::Holiday, Self::Exam, Self::WorkingDay, Self::Event];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Holiday => "HOLIDAY",
+            Self::Exam => "EXAM",
+            Self::WorkingDay => "WORKING_DAY",
+            Self::Event => "EVENT",
+        }
+    }
+}
+
+impl ToSql for CalendarEventKind {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for CalendarEventKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == text)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown event kind: {}", text).into()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CalendarEvent {
+    pub id: i32,
+    pub session_id: i32,
+    pub title: String,
+    pub kind: CalendarEventKind,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub description: Option<String>,
+}
+
+impl CalendarEvent {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS calendar_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id INTEGER NOT NULL,
+                title TEXT NOT NULL,
+                kind TEXT NOT NULL CHECK (kind IN ('HOLIDAY', 'EXAM', 'WORKING_DAY', 'EVENT')),
+                start_date DATE NOT NULL,
+                end_date DATE NOT NULL,
+                description TEXT,
+                FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE,
+                CHECK (end_date >= start_date)
+            )",
+            [],
+        )?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS calendar_weekly_offs (
+                session_id INTEGER NOT NULL,
+                weekday INTEGER NOT NULL CHECK (weekday BETWEEN 0 AND 7),
+                PRIMARY KEY (session_id, weekday),
+                FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    pub fn create(
+        session_id: i32,
+        title: &str,
+        kind: CalendarEventKind,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        description: Option<String>,
+    ) -> Result<Self> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "INSERT INTO calendar_events (session_id, title, kind, start_date, end_date, description)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![session_id, title, kind, start_date, end_date, description],
+        )?;
+
+        let id = db.last_insert_rowid() as i32;
+        Ok(Self {
+            id,
+            session_id,
+            title: title.to_string(),
+            kind,
+            start_date,
+            end_date,
+            description,
+        })
+    }
+
+    fn query(
+        db: &Connection,
+        session_id: i32,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<Self>> {
+        let mut stmt = db.prepare(
+            "SELECT id, session_id, title, kind, start_date, end_date, description
+             FROM calendar_events
+             WHERE session_id = ?1
+             AND (?2 IS NULL OR end_date >= ?2)
+             AND (?3 IS NULL OR start_date <= ?3)
+             ORDER BY start_date ASC, id ASC",
+        )?;
+        let rows = stmt.query_map(params![session_id, from, to], |row| {
+            Ok(Self {
+                id: row.get(0)?,
+                session_id: row.get(1)?,
+                title: row.get(2)?,
+                kind: row.get(3)?,
+                start_date: row.get(4)?,
+                end_date: row.get(5)?,
+                description: row.get(6)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn get(
+        session_id: i32,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<Self>> {
+        let db = conn()?;
+        Self::query(&db, session_id, from, to)
+    }
+
+    pub fn edit(
+        id: i32,
+        title: &str,
+        kind: CalendarEventKind,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        description: Option<String>,
+    ) -> Result<Self> {
+        let db = conn()?;
+        let affected = db.execute(
+            "UPDATE calendar_events
+             SET title = ?1, kind = ?2, start_date = ?3, end_date = ?4, description = ?5
+             WHERE id = ?6",
+            params![title, kind, start_date, end_date, description, id],
+        )?;
+
+        if affected == 0 {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error {
+                    code: rusqlite::ffi::ErrorCode::OperationAborted,
+                    extended_code: 0,
+                },
+                Some("No calendar event found with the given ID".to_string()),
+            ));
+        }
+
+        let session_id = db.query_row(
+            "SELECT session_id FROM calendar_events WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+
+        Ok(Self {
+            id,
+            session_id,
+            title: title.to_string(),
+            kind,
+            start_date,
+            end_date,
+            description,
+        })
+    }
+
+    pub fn delete(id: i32) -> Result<()> {
+        let db = conn()?;
+        let affected = db.execute("DELETE FROM calendar_events WHERE id = ?1", params![id])?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn covers(&self, date: NaiveDate) -> bool {
+        self.start_date <= date && date <= self.end_date
+    }
+}
+
+fn get_weekly_off_days(db: &Connection, session_id: i32) -> Result<Vec<Weekday>> {
+    let mut stmt = db.prepare(
+        "SELECT weekday FROM calendar_weekly_offs WHERE session_id = ?1 ORDER BY weekday ASC",
+    )?;
+    let days: Vec<u8> = stmt
+        .query_map(params![session_id], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+
+    if days.is_empty() {
+        return Ok(DEFAULT_WEEKLY_OFF_DAYS.to_vec());
+    }
+
+    Ok(days
+        .into_iter()
+        .filter(|d| *d != NO_WEEKLY_OFF)
+        .filter_map(|d| Weekday::try_from(d).ok())
+        .collect())
+}
+
+pub fn set_weekly_off_days(session_id: i32, days: Vec<Weekday>) -> Result<Vec<Weekday>> {
+    let mut db = conn()?;
+    db.execute("PRAGMA foreign_keys = ON", [])?;
+    let tx = db.transaction()?;
+    tx.execute(
+        "DELETE FROM calendar_weekly_offs WHERE session_id = ?1",
+        params![session_id],
+    )?;
+    let weekdays: Vec<u8> = if days.is_empty() {
+        vec![NO_WEEKLY_OFF]
+    } else {
+        days.iter()
+            .map(|day| day.num_days_from_monday() as u8)
+            .collect()
+    };
+    for weekday in weekdays {
+        tx.execute(
+            "INSERT OR IGNORE INTO calendar_weekly_offs (session_id, weekday) VALUES (?1, ?2)",
+            params![session_id, weekday],
+        )?;
+    }
+    tx.commit()?;
+    drop(db);
+
+    SchoolCalendar::load(session_id).map(|c| c.weekly_off_days)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CalendarDay {
+    pub date: NaiveDate,
+    pub working: bool,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SchoolCalendar {
+    pub session_id: i32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub weekly_off_days: Vec<Weekday>,
+    pub events: Vec<CalendarEvent>,
+}
+
+// Session dates are read as text
+fn parse_session_date(value: &str, column: usize) -> Result<NaiveDate> {
+    value
+        .parse()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(column, Type::Text, Box::new(e)))
+}
+
+impl SchoolCalendar {
+    fn load_with(db: &Connection, session_id: i32) -> Result<Self> {
+        let (start_date, end_date): (String, String) = db.query_row(
+            "SELECT start_date, end_date FROM sessions WHERE id = ?1",
+            params![session_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        Ok(Self {
+            session_id,
+            start_date: parse_session_date(&start_date, 0)?,
+            end_date: parse_session_date(&end_date, 1)?,
+            weekly_off_days: get_weekly_off_days(db, session_id)?,
+            events: CalendarEvent::query(db, session_id, None, None)?,
+        })
+    }
+
+    pub fn load(session_id: i32) -> Result<Self> {
+        let db = conn()?;
+        Self::load_with(&db, session_id)
+    }
+
+    // Calendars of every session overlapping the range, for records such as
+    // staff attendance that are not tied to a session.
+    pub fn load_overlapping(from: NaiveDate, to: NaiveDate) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt =
+            db.prepare("SELECT id FROM sessions WHERE start_date <= ?2 AND end_date >= ?1")?;
+        let ids: Vec<i32> = stmt
+            .query_map(params![from, to], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+
+        ids.into_iter().map(|id| Self::load_with(&db, id)).collect()
+    }
+
+    pub fn day(&self, date: NaiveDate) -> CalendarDay {
+        let not_working = |reason: String| CalendarDay {
+            date,
+            working: false,
+            reason: Some(reason),
+        };
+
+        if date < self.start_date || date > self.end_date {
+            return not_working("Outside session".to_string());
+        }
+
+        let covering: Vec<&CalendarEvent> = self.events.iter().filter(|e| e.covers(date)).collect();
+
+        // Make-up days override both holidays and weekly off-days
+        if let Some(event) = covering
+            .iter()
+            .find(|e| e.kind == CalendarEventKind::WorkingDay)
+        {
+            return CalendarDay {
+                date,
+                working: true,
+                reason: Some(event.title.clone()),
+            };
+        }
+
+        if let Some(event) = covering
+            .iter()
+            .find(|e| e.kind == CalendarEventKind::Holiday)
+        {
+            return not_working(event.title.clone());
+        }
+
+        if self.weekly_off_days.contains(&date.weekday()) {
+            return not_working("Weekly off-day".to_string());
+        }
+
+        CalendarDay {
+            date,
+            working: true,
+            reason: covering.first().map(|e| e.title.clone()),
+        }
+    }
+
+    pub fn is_working_day(&self, date: NaiveDate) -> bool {
+        self.day(date).working
+    }
+
+    pub fn days(&self, from: NaiveDate, to: NaiveDate) -> Vec<CalendarDay> {
+        from.iter_days()
+            .take_while(|d| *d <= to)
+            .map(|d| self.day(d))
+            .collect()
+    }
+
+    pub fn working_days(&self, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        from.iter_days()
+            .take_while(|d| *d <= to)
+            .filter(|d| self.is_working_day(*d))
+            .collect()
+    }
+}
+
+// Working-day check across several session calendars. Dates outside every
+// session only skip the default weekly off-days.
+pub fn is_working_day_in(calendars: &[SchoolCalendar], date: NaiveDate) -> bool {
+    match calendars
+        .iter()
+        .find(|c| c.start_date <= date && date <= c.end_date)
+    {
+        Some(calendar) => calendar.is_working_day(date),
+        None => !DEFAULT_WEEKLY_OFF_DAYS.contains(&date.weekday()),
+    }
+}
+
+pub fn working_days_in(
+    calendars: &[SchoolCalendar],
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Vec<NaiveDate> {
+    from.iter_days()
+        .take_while(|d| *d <= to)
+        .filter(|d| is_working_day_in(calendars, *d))
+        .collect()
+}
@@ -0,0 +1,659 @@
+use super::attendance::AttendanceStatus;
+use super::calendar::SchoolCalendar;
+use super::conn;
+use super::settings::{Setting, SCHOOL_ADDRESS_KEY, SCHOOL_LOGO_KEY, SCHOOL_NAME_KEY};
+use super::student::Student;
+use crate::report::{decode_image, fit, hex_color, place_image};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use hmac::{Hmac, Mac};
+use printpdf::image_crate::DynamicImage;
+use printpdf::path::PaintMode;
+use printpdf::{
+    BuiltinFont, Color, Greyscale, IndirectFontRef, Mm, PdfDocument, PdfLayerReference, Rect,
+};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+// Scans after this time of day mark the student LATE
+pub const SCAN_LATE_AFTER_KEY: &str = "scan_late_after";
+pub const DEFAULT_SCAN_LATE_AFTER: &str = "09:00";
+pub const CARD_TEMPLATE_KEY: &str = "id_card_template";
+
+const TOKEN_PREFIX: &str = "STU";
+// Signature bytes kept in the token; enough against guessing while keeping
+// the QR code small enough for cheap scanners.
+const SIGNATURE_BYTES: usize = 12;
+
+fn card_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::PermissionDenied,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+pub fn init() -> Result<()> {
+    let db = conn()?;
+    // Kept out of the settings table so it is never sent to the UI
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS card_signing_key (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            key BLOB NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn signing_key(db: &Connection) -> Result<Vec<u8>> {
+    let existing: Option<Vec<u8>> = db
+        .query_row("SELECT key FROM card_signing_key WHERE id = 1", [], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    if let Some(key) = existing {
+        return Ok(key);
+    }
+
+    let key = rand::random::<[u8; 32]>().to_vec();
+    db.execute(
+        "INSERT INTO card_signing_key (id, key) VALUES (1, ?1)",
+        params![key],
+    )?;
+    Ok(key)
+}
+
+fn mac(key: &[u8], student_id: i32, session_id: i32) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(format!("{}:{}", student_id, session_id).as_bytes());
+    mac
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn signature_from_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() != SIGNATURE_BYTES * 2 {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// Upper-case hex and dashes only, so keyboard-wedge scanners type it the
+// same way whatever the caps lock or layout state.
+fn sign(db: &Connection, student_id: i32, session_id: i32) -> Result<String> {
+    let key = signing_key(db)?;
+    let signature = mac(&key, student_id, session_id).finalize().into_bytes();
+    Ok(format!(
+        "{}-{}-{}-{}",
+        TOKEN_PREFIX,
+        student_id,
+        session_id,
+        to_hex(&signature[..SIGNATURE_BYTES])
+    ))
+}
+
+// Returns the student and session ids of a genuine token
+pub fn verify(token: &str) -> Result<(i32, i32)> {
+    let token = token.trim().to_uppercase();
+    let parts: Vec<&str> = token.split('-').collect();
+    let [prefix, student_id, session_id, signature] = parts.as_slice() else {
+        return Err(card_error("Not a student card"));
+    };
+    let (Ok(student_id), Ok(session_id), Some(signature)) = (
+        student_id.parse::<i32>(),
+        session_id.parse::<i32>(),
+        signature_from_hex(signature),
+    ) else {
+        return Err(card_error("Not a student card"));
+    };
+    if *prefix != TOKEN_PREFIX {
+        return Err(card_error("Not a student card"));
+    }
+
+    let db = conn()?;
+    let key = signing_key(&db)?;
+    mac(&key, student_id, session_id)
+        .verify_truncated_left(&signature)
+        .map_err(|_| card_error("Card signature is not valid"))?;
+    Ok((student_id, session_id))
+}
+
+pub fn qr_svg(token: &str) -> Result<String> {
+    let code = QrCode::new(token.as_bytes())
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(160, 160)
+        .quiet_zone(true)
+        .build())
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CardToken {
+    pub student_id: i32,
+    pub session_id: i32,
+    pub token: String,
+    pub qr_svg: String,
+}
+
+impl CardToken {
+    fn new(db: &Connection, student_id: i32, session_id: i32) -> Result<Self> {
+        let token = sign(db, student_id, session_id)?;
+        Ok(Self {
+            student_id,
+            session_id,
+            qr_svg: qr_svg(&token)?,
+            token,
+        })
+    }
+
+    // Cards are tied to the student's current session, so a promoted or
+    // re-admitted student needs a new card.
+    pub fn for_student(student_id: i32) -> Result<Self> {
+        let session_id = Student::get_by_id(student_id)?.session_id;
+        let db = conn()?;
+        Self::new(&db, student_id, session_id)
+    }
+
+    pub fn for_class(session_id: i32, class_id: i32, section_id: Option<i32>) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let ids: Vec<i32> = {
+            let mut stmt = db.prepare(
+                "SELECT id FROM students
+                 WHERE session_id = ?1 AND class_id = ?2 AND (?3 IS NULL OR section_id = ?3)
+                 ORDER BY roll ASC, name ASC",
+            )?;
+            let rows =
+                stmt.query_map(params![session_id, class_id, section_id], |row| row.get(0))?;
+            rows.collect::<Result<_>>()?
+        };
+
+        ids.into_iter()
+            .map(|id| Self::new(&db, id, session_id))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScanResult {
+    pub student_id: i32,
+    pub name: String,
+    pub roll: i32,
+    pub class_name: String,
+    pub section_name: Option<String>,
+    pub date: NaiveDate,
+    pub status: AttendanceStatus,
+    pub arrival_time: Option<NaiveTime>,
+    // The student was already marked today; the earlier mark is kept
+    pub already_marked: bool,
+}
+
+// Marks the card holder PRESENT, or LATE after the cutoff, for the day of
+// `now`. Repeated scans on the same day leave the first mark alone.
+pub fn scan(token: &str, now: NaiveDateTime) -> Result<ScanResult> {
+    let (student_id, session_id) = verify(token)?;
+    let student = Student::get_by_id(student_id)?;
+    if student.session_id != session_id {
+        return Err(card_error("Card is from another session; print a new card"));
+    }
+
+    let date = now.date();
+    let day = SchoolCalendar::load(session_id)?.day(date);
+    if !day.working {
+        return Err(card_error(&format!(
+            "{} is not a working day ({})",
+            date,
+            day.reason.unwrap_or_default()
+        )));
+    }
+
+    let late_after = NaiveTime::parse_from_str(
+        &Setting::get_or(SCAN_LATE_AFTER_KEY, DEFAULT_SCAN_LATE_AFTER)?,
+        "%H:%M",
+    )
+    .unwrap_or_else(|_| NaiveTime::parse_from_str(DEFAULT_SCAN_LATE_AFTER, "%H:%M").unwrap());
+
+    let db = conn()?;
+    let (class_name, section_name): (String, Option<String>) = db.query_row(
+        "SELECT c.name, s.name FROM classes c
+         LEFT JOIN sections s ON s.id = ?2
+         WHERE c.id = ?1",
+        params![student.class_id, student.section_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let existing: Option<(AttendanceStatus, Option<NaiveTime>)> = db
+        .query_row(
+            "SELECT status, arrival_time FROM attendance WHERE student_id = ?1 AND date = ?2",
+            params![student_id, date],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    let (status, arrival_time, already_marked) = match existing {
+        Some((status, arrival_time)) => (status, arrival_time, true),
+        None => {
+            let time = now.time();
+            let status = if time > late_after {
+                AttendanceStatus::Late
+            } else {
+                AttendanceStatus::Present
+            };
+            db.execute(
+                "INSERT INTO attendance (student_id, date, status, remark, arrival_time)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![student_id, date, status, "Card scan", time],
+            )?;
+            (status, Some(time), false)
+        }
+    };
+
+    Ok(ScanResult {
+        student_id,
+        name: student.name,
+        roll: student.roll,
+        class_name,
+        section_name,
+        date,
+        status,
+        arrival_time,
+        already_marked,
+    })
+}
+
+// Layout options for printed ID cards, stored as JSON in the settings table.
+// Sizes are in millimetres; the default is the CR80 bank card size.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CardTemplate {
+    pub title: String,
+    pub width: f32,
+    pub height: f32,
+    // "#RRGGBB" of the header band
+    pub accent_color: String,
+    pub show_photo: bool,
+    pub show_dob: bool,
+    pub show_guardian_phone: bool,
+    pub show_session: bool,
+    pub show_qr: bool,
+    // Printed along the bottom edge; the school address when empty
+    pub footer: Option<String>,
+}
+
+impl Default for CardTemplate {
+    fn default() -> Self {
+        Self {
+            title: "STUDENT ID CARD".to_string(),
+            width: 85.6,
+            height: 54.0,
+            accent_color: "#1F4E79".to_string(),
+            show_photo: true,
+            show_dob: true,
+            show_guardian_phone: true,
+            show_session: true,
+            show_qr: true,
+            footer: None,
+        }
+    }
+}
+
+impl CardTemplate {
+    pub fn load() -> Result<Self> {
+        Ok(Setting::get(CARD_TEMPLATE_KEY)?
+            .and_then(|v| serde_json::from_str(&v).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn save(&self) -> Result<Self> {
+        let value = serde_json::to_string(self)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        Setting::set(CARD_TEMPLATE_KEY, &value)?;
+        Ok(self.clone())
+    }
+
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if !(40.0..=SHEET_WIDTH - 2.0 * SHEET_MARGIN).contains(&self.width)
+            || !(30.0..=SHEET_HEIGHT - 2.0 * SHEET_MARGIN).contains(&self.height)
+        {
+            return Err("Card size does not fit an A4 sheet".to_string());
+        }
+        if hex_color(&self.accent_color).is_none() {
+            return Err(format!("Invalid colour: {}", self.accent_color));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IdCard {
+    pub student_id: i32,
+    pub name: String,
+    pub roll: i32,
+    pub class_name: String,
+    pub section_name: Option<String>,
+    pub session_name: String,
+    pub dob: NaiveDate,
+    pub photo: Option<String>,
+    // Of the first guardian linked to the student
+    pub guardian_phone: Option<String>,
+    pub token: String,
+}
+
+impl IdCard {
+    pub fn get(session_id: i32, class_id: i32, section_id: Option<i32>) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(
+            "SELECT s.id, s.name, s.roll, c.name, sec.name, ses.name, s.dob, s.photo,
+                    (SELECT g.phone FROM student_relationships r
+                     JOIN guardians g ON g.id = r.related_id
+                     WHERE r.student_id = s.id
+                     ORDER BY r.id ASC LIMIT 1)
+             FROM students s
+             JOIN classes c ON c.id = s.class_id
+             JOIN sessions ses ON ses.id = s.session_id
+             LEFT JOIN sections sec ON sec.id = s.section_id
+             WHERE s.session_id = ?1 AND s.class_id = ?2 AND (?3 IS NULL OR s.section_id = ?3)
+             ORDER BY s.roll ASC, s.name ASC",
+        )?;
+        let rows = stmt.query_map(params![session_id, class_id, section_id], |row| {
+            Ok(Self {
+                student_id: row.get(0)?,
+                name: row.get(1)?,
+                roll: row.get(2)?,
+                class_name: row.get(3)?,
+                section_name: row.get(4)?,
+                session_name: row.get(5)?,
+                dob: row.get(6)?,
+                photo: row.get(7)?,
+                guardian_phone: row.get(8)?,
+                token: String::new(),
+            })
+        })?;
+        let mut cards = rows.collect::<Result<Vec<Self>>>()?;
+
+        for card in &mut cards {
+            card.token = sign(&db, card.student_id, session_id)?;
+        }
+        Ok(cards)
+    }
+}
+
+// A4 portrait sheet the cards are tiled on
+const SHEET_WIDTH: f32 = 210.0;
+const SHEET_HEIGHT: f32 = 297.0;
+const SHEET_MARGIN: f32 = 10.0;
+const CARD_GAP: f32 = 4.0;
+const PADDING: f32 = 2.5;
+// Pixels kept from photos and logos, about 300 dpi at card size
+const PHOTO_PIXELS: u32 = 260;
+const LOGO_PIXELS: u32 = 160;
+
+struct School {
+    name: String,
+    address: Option<String>,
+    logo: Option<DynamicImage>,
+}
+
+struct CardSheet {
+    template: CardTemplate,
+    school: School,
+    accent: Color,
+    font: IndirectFontRef,
+    bold: IndirectFontRef,
+}
+
+fn grey(level: f32) -> Color {
+    Color::Greyscale(Greyscale::new(level, None))
+}
+
+fn fill_rect(layer: &PdfLayerReference, x: f32, y: f32, width: f32, height: f32) {
+    layer.add_rect(Rect::new(Mm(x), Mm(y), Mm(x + width), Mm(y + height)));
+}
+
+// Dark modules are drawn as filled runs, one rectangle per run in a row
+fn draw_qr(layer: &PdfLayerReference, token: &str, x: f32, y: f32, size: f32) {
+    let Ok(code) = QrCode::new(token.as_bytes()) else {
+        return;
+    };
+    let width = code.width();
+    let colors = code.to_colors();
+    let module = size / width as f32;
+
+    layer.set_fill_color(grey(0.0));
+    for row in 0..width {
+        let top = y + size - row as f32 * module;
+        let mut column = 0;
+        while column < width {
+            if colors[row * width + column] != qrcode::Color::Dark {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < width && colors[row * width + column] == qrcode::Color::Dark {
+                column += 1;
+            }
+            fill_rect(
+                layer,
+                x + start as f32 * module,
+                top - module,
+                (column - start) as f32 * module,
+                module,
+            );
+        }
+    }
+}
+
+impl CardSheet {
+    // Draws one card with its lower-left corner at (x, y)
+    fn draw(&self, layer: &PdfLayerReference, card: &IdCard, x: f32, y: f32) {
+        let t = &self.template;
+        let (w, h) = (t.width, t.height);
+
+        layer.set_outline_color(grey(0.6));
+        layer.set_outline_thickness(0.2);
+        layer.add_rect(Rect::new(Mm(x), Mm(y), Mm(x + w), Mm(y + h)).with_mode(PaintMode::Stroke));
+
+        // Header band with logo, school name and card title
+        let band = (h * 0.22).max(9.0);
+        let band_y = y + h - band;
+        layer.set_fill_color(self.accent.clone());
+        fill_rect(layer, x, band_y, w, band);
+
+        let mut text_x = x + PADDING;
+        if let Some(logo) = &self.school.logo {
+            let size = band - 2.0;
+            place_image(layer, logo, x + 1.0, band_y + 1.0, size, size);
+            text_x = x + size + 2.5;
+        }
+        let header_width = x + w - PADDING - text_x;
+        layer.set_fill_color(grey(1.0));
+        layer.use_text(
+            fit(&self.school.name, header_width, 8.5),
+            8.5,
+            Mm(text_x),
+            Mm(band_y + band * 0.52),
+            &self.bold,
+        );
+        layer.use_text(
+            fit(&t.title, header_width, 6.0),
+            6.0,
+            Mm(text_x),
+            Mm(band_y + band * 0.18),
+            &self.font,
+        );
+
+        // Footer line
+        let footer_height = 4.0;
+        let footer = t
+            .footer
+            .clone()
+            .filter(|f| !f.trim().is_empty())
+            .or_else(|| self.school.address.clone());
+        layer.set_fill_color(grey(0.35));
+        if let Some(footer) = footer {
+            layer.use_text(
+                fit(&footer, w - 2.0 * PADDING, 5.0),
+                5.0,
+                Mm(x + PADDING),
+                Mm(y + 1.5),
+                &self.font,
+            );
+        }
+
+        let body_top = band_y - PADDING;
+        let body_bottom = y + footer_height;
+
+        let mut text_left = x + PADDING;
+        if t.show_photo {
+            let photo_w = w * 0.24;
+            let photo_h = (photo_w * 1.25).min(body_top - body_bottom);
+            let photo_y = body_top - photo_h;
+            match card
+                .photo
+                .as_deref()
+                .and_then(|p| decode_image(p, PHOTO_PIXELS))
+            {
+                Some(photo) => place_image(layer, &photo, x + PADDING, photo_y, photo_w, photo_h),
+                None => {
+                    layer.set_outline_color(grey(0.75));
+                    layer.add_rect(
+                        Rect::new(
+                            Mm(x + PADDING),
+                            Mm(photo_y),
+                            Mm(x + PADDING + photo_w),
+                            Mm(body_top),
+                        )
+                        .with_mode(PaintMode::Stroke),
+                    );
+                }
+            }
+            text_left += photo_w + 2.5;
+        }
+
+        let mut text_right = x + w - PADDING;
+        if t.show_qr {
+            let size = (h * 0.38).min(body_top - body_bottom).min(22.0);
+            draw_qr(
+                layer,
+                &card.token,
+                x + w - PADDING - size,
+                body_bottom,
+                size,
+            );
+            text_right -= size + 1.5;
+        }
+        let text_width = text_right - text_left;
+
+        layer.set_fill_color(grey(0.0));
+        let mut line_y = body_top - 3.0;
+        layer.use_text(
+            fit(&card.name, x + w - PADDING - text_left, 8.0),
+            8.0,
+            Mm(text_left),
+            Mm(line_y),
+            &self.bold,
+        );
+
+        let mut lines = vec![match &card.section_name {
+            Some(section) => format!("Class: {}  Section: {}", card.class_name, section),
+            None => format!("Class: {}", card.class_name),
+        }];
+        lines.push(format!("Roll: {}", card.roll));
+        if t.show_dob {
+            lines.push(format!("Date of birth: {}", card.dob.format("%d %b %Y")));
+        }
+        if t.show_guardian_phone {
+            if let Some(phone) = &card.guardian_phone {
+                lines.push(format!("Guardian: {}", phone));
+            }
+        }
+        if t.show_session {
+            lines.push(format!("Session: {}", card.session_name));
+        }
+
+        for text in lines {
+            line_y -= 3.6;
+            if line_y < body_bottom {
+                break;
+            }
+            layer.use_text(
+                fit(&text, text_width, 6.5),
+                6.5,
+                Mm(text_left),
+                Mm(line_y),
+                &self.font,
+            );
+        }
+    }
+}
+
+// Tiles the cards over as many A4 pages as needed, with thin borders to cut
+// along.
+pub fn render_cards(
+    cards: &[IdCard],
+    template: &CardTemplate,
+) -> std::result::Result<Vec<u8>, String> {
+    template.validate()?;
+    if cards.is_empty() {
+        return Err("No students to print cards for".to_string());
+    }
+
+    let school = School {
+        name: Setting::get(SCHOOL_NAME_KEY)
+            .map_err(|e| e.to_string())?
+            .unwrap_or_default(),
+        address: Setting::get(SCHOOL_ADDRESS_KEY).map_err(|e| e.to_string())?,
+        logo: Setting::get(SCHOOL_LOGO_KEY)
+            .map_err(|e| e.to_string())?
+            .and_then(|logo| decode_image(&logo, LOGO_PIXELS)),
+    };
+
+    let (doc, page, layer) =
+        PdfDocument::new("ID cards", Mm(SHEET_WIDTH), Mm(SHEET_HEIGHT), "Layer 1");
+    let sheet = CardSheet {
+        accent: hex_color(&template.accent_color).unwrap_or_else(|| grey(0.2)),
+        template: template.clone(),
+        school,
+        font: doc
+            .add_builtin_font(BuiltinFont::Helvetica)
+            .map_err(|e| e.to_string())?,
+        bold: doc
+            .add_builtin_font(BuiltinFont::HelveticaBold)
+            .map_err(|e| e.to_string())?,
+    };
+
+    let usable_w = SHEET_WIDTH - 2.0 * SHEET_MARGIN;
+    let usable_h = SHEET_HEIGHT - 2.0 * SHEET_MARGIN;
+    let columns = (((usable_w + CARD_GAP) / (template.width + CARD_GAP)) as usize).max(1);
+    let rows = (((usable_h + CARD_GAP) / (template.height + CARD_GAP)) as usize).max(1);
+    let left =
+        (SHEET_WIDTH - columns as f32 * template.width - (columns - 1) as f32 * CARD_GAP) / 2.0;
+
+    let mut layer = doc.get_page(page).get_layer(layer);
+    for (index, card) in cards.iter().enumerate() {
+        let slot = index % (columns * rows);
+        if index > 0 && slot == 0 {
+            let (page, layer_index) = doc.add_page(Mm(SHEET_WIDTH), Mm(SHEET_HEIGHT), "Layer 1");
+            layer = doc.get_page(page).get_layer(layer_index);
+        }
+        let (row, column) = (slot / columns, slot % columns);
+        let x = left + column as f32 * (template.width + CARD_GAP);
+        let y = SHEET_HEIGHT
+            - SHEET_MARGIN
+            - row as f32 * (template.height + CARD_GAP)
+            - template.height;
+        sheet.draw(&layer, card, x, y);
+    }
+
+    doc.save_to_bytes().map_err(|e| e.to_string())
+}
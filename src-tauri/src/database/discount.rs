@@ -0,0 +1,565 @@
+use super::conn;
+use super::fee::{FeeSchedule, FeeType};
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+fn discount_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DiscountKind {
+    // `value` percent of the fee
+    Percentage,
+    // `value` taka off the fee
+    Fixed,
+}
+
+impl DiscountKind {
+    pub const ALL: [Self; 2] = [Self::Percentage, Self::Fixed];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Percentage => "PERCENTAGE",
+            Self::Fixed => "FIXED",
+        }
+    }
+}
+
+impl ToSql for DiscountKind {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for DiscountKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == text)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown discount kind: {}", text).into()))
+    }
+}
+
+// Who a rule applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Eligibility {
+    // Younger siblings, counted by date of birth among the students of the
+    // session sharing a guardian
+    Sibling,
+    // Students linked to a staff member as their child or ward
+    StaffChild,
+    // Only students the rule has been granted to, e.g. orphans or merit
+    // scholars
+    Manual,
+}
+
+impl Eligibility {
+    pub const ALL: [Self; 3] = [Self::Sibling, Self::StaffChild, Self::Manual];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sibling => "SIBLING",
+            Self::StaffChild => "STAFF_CHILD",
+            Self::Manual => "MANUAL",
+        }
+    }
+}
+
+impl ToSql for Eligibility {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for Eligibility {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|e| e.as_str() == text)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown eligibility: {}", text).into()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DiscountRule {
+    pub id: i32,
+    pub name: String,
+    pub kind: DiscountKind,
+    pub value: i32,
+    // None applies to every fee
+    pub fee_type: Option<FeeType>,
+    pub eligibility: Eligibility,
+    // For sibling rules: 2 for the second child onwards, and so on
+    pub min_sibling_order: Option<i32>,
+    pub valid_from: Option<NaiveDate>,
+    pub valid_to: Option<NaiveDate>,
+    // Stackable rules add up; any other rule is used on its own
+    pub stackable: bool,
+}
+
+const RULE_COLUMNS: &str = "id, name, kind, value, fee_type, eligibility, min_sibling_order,
+     valid_from, valid_to, stackable";
+
+impl DiscountRule {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS discount_rules (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                    kind TEXT NOT NULL CHECK (kind IN ('PERCENTAGE', 'FIXED')),
+                    value INTEGER NOT NULL CHECK (value > 0),
+                    fee_type TEXT {},
+                    eligibility TEXT NOT NULL
+                        CHECK (eligibility IN ('SIBLING', 'STAFF_CHILD', 'MANUAL')),
+                    min_sibling_order INTEGER,
+                    valid_from DATE,
+                    valid_to DATE,
+                    stackable BOOLEAN NOT NULL DEFAULT 0
+                )",
+                FeeType::check_constraint("fee_type")
+            ),
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            kind: row.get(2)?,
+            value: row.get(3)?,
+            fee_type: row.get(4)?,
+            eligibility: row.get(5)?,
+            min_sibling_order: row.get(6)?,
+            valid_from: row.get(7)?,
+            valid_to: row.get(8)?,
+            stackable: row.get(9)?,
+        })
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(discount_error("Discount name is required"));
+        }
+        if self.value <= 0 {
+            return Err(discount_error("Discount must be positive"));
+        }
+        if self.kind == DiscountKind::Percentage && self.value > 100 {
+            return Err(discount_error("A percentage cannot be over 100"));
+        }
+        if self.eligibility == Eligibility::Sibling && self.min_sibling_order.unwrap_or(0) < 2 {
+            return Err(discount_error(
+                "Sibling discounts start from the second child",
+            ));
+        }
+        if self.fee_type == Some(FeeType::LateFee) {
+            return Err(discount_error(
+                "Late fees are waived rather than discounted",
+            ));
+        }
+        if let (Some(from), Some(to)) = (self.valid_from, self.valid_to) {
+            if from > to {
+                return Err(discount_error("Validity ends before it starts"));
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        name: &str,
+        kind: DiscountKind,
+        value: i32,
+        fee_type: Option<FeeType>,
+        eligibility: Eligibility,
+        min_sibling_order: Option<i32>,
+        valid_from: Option<NaiveDate>,
+        valid_to: Option<NaiveDate>,
+        stackable: bool,
+    ) -> Result<Self> {
+        let mut rule = Self {
+            id: 0,
+            name: name.trim().to_string(),
+            kind,
+            value,
+            fee_type,
+            eligibility,
+            min_sibling_order: min_sibling_order.filter(|_| eligibility == Eligibility::Sibling),
+            valid_from,
+            valid_to,
+            stackable,
+        };
+        rule.validate()?;
+
+        let db = conn()?;
+        db.execute(
+            "INSERT INTO discount_rules
+                (name, kind, value, fee_type, eligibility, min_sibling_order, valid_from, valid_to,
+                 stackable)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                rule.name,
+                rule.kind,
+                rule.value,
+                rule.fee_type,
+                rule.eligibility,
+                rule.min_sibling_order,
+                rule.valid_from,
+                rule.valid_to,
+                rule.stackable
+            ],
+        )?;
+        rule.id = db.last_insert_rowid() as i32;
+        Ok(rule)
+    }
+
+    pub fn get_all() -> Result<Vec<Self>> {
+        let db = conn()?;
+        Self::get_all_with(&db)
+    }
+
+    pub fn get_all_with(db: &Connection) -> Result<Vec<Self>> {
+        let mut stmt = db.prepare(&format!(
+            "SELECT {} FROM discount_rules ORDER BY name ASC",
+            RULE_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], Self::from_row)?;
+        rows.collect()
+    }
+
+    // Fees already billed keep the discount they were given
+    #[allow(clippy::too_many_arguments)]
+    pub fn edit(
+        id: i32,
+        name: &str,
+        kind: DiscountKind,
+        value: i32,
+        fee_type: Option<FeeType>,
+        eligibility: Eligibility,
+        min_sibling_order: Option<i32>,
+        valid_from: Option<NaiveDate>,
+        valid_to: Option<NaiveDate>,
+        stackable: bool,
+    ) -> Result<Self> {
+        let rule = Self {
+            id,
+            name: name.trim().to_string(),
+            kind,
+            value,
+            fee_type,
+            eligibility,
+            min_sibling_order: min_sibling_order.filter(|_| eligibility == Eligibility::Sibling),
+            valid_from,
+            valid_to,
+            stackable,
+        };
+        rule.validate()?;
+
+        let db = conn()?;
+        let affected = db.execute(
+            "UPDATE discount_rules
+             SET name = ?1, kind = ?2, value = ?3, fee_type = ?4, eligibility = ?5,
+                 min_sibling_order = ?6, valid_from = ?7, valid_to = ?8, stackable = ?9
+             WHERE id = ?10",
+            params![
+                rule.name,
+                rule.kind,
+                rule.value,
+                rule.fee_type,
+                rule.eligibility,
+                rule.min_sibling_order,
+                rule.valid_from,
+                rule.valid_to,
+                rule.stackable,
+                id
+            ],
+        )?;
+        if affected == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(rule)
+    }
+
+    pub fn delete(id: i32) -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let affected = db.execute("DELETE FROM discount_rules WHERE id = ?1", params![id])?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_valid_on(&self, date: NaiveDate) -> bool {
+        self.valid_from.map_or(true, |from| from <= date)
+            && self.valid_to.map_or(true, |to| date <= to)
+    }
+
+    // Taka off `amount`; percentages round down
+    fn amount_off(&self, amount: i32) -> i32 {
+        match self.kind {
+            DiscountKind::Percentage => amount * self.value / 100,
+            DiscountKind::Fixed => self.value,
+        }
+        .min(amount)
+    }
+}
+
+// A manual rule given to one student
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DiscountGrant {
+    pub id: i32,
+    pub rule_id: i32,
+    pub rule_name: String,
+    pub student_id: i32,
+    pub student_name: String,
+    pub note: Option<String>,
+    pub granted_at: NaiveDateTime,
+}
+
+impl DiscountGrant {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS discount_grants (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                rule_id INTEGER NOT NULL,
+                student_id INTEGER NOT NULL,
+                note TEXT,
+                granted_at DATETIME NOT NULL,
+                FOREIGN KEY (rule_id) REFERENCES discount_rules(id) ON DELETE CASCADE,
+                FOREIGN KEY (student_id) REFERENCES students(id) ON DELETE CASCADE,
+                UNIQUE (rule_id, student_id)
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    pub fn grant(rule_id: i32, student_id: i32, note: Option<String>) -> Result<Self> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let eligibility: Eligibility = db.query_row(
+            "SELECT eligibility FROM discount_rules WHERE id = ?1",
+            params![rule_id],
+            |row| row.get(0),
+        )?;
+        if eligibility != Eligibility::Manual {
+            return Err(discount_error(
+                "Only manual discounts are granted to students",
+            ));
+        }
+        let note = note.filter(|n| !n.trim().is_empty());
+        let id: i32 = db.query_row(
+            "INSERT INTO discount_grants (rule_id, student_id, note, granted_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(rule_id, student_id) DO UPDATE SET note = excluded.note
+             RETURNING id",
+            params![rule_id, student_id, note, Local::now().naive_local()],
+            |row| row.get(0),
+        )?;
+        Self::get_with(&db, id)
+    }
+
+    fn get_with(db: &Connection, id: i32) -> Result<Self> {
+        db.query_row(
+            &format!("{} WHERE g.id = ?1", GRANT_SELECT),
+            params![id],
+            Self::from_row,
+        )
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            rule_id: row.get(1)?,
+            rule_name: row.get(2)?,
+            student_id: row.get(3)?,
+            student_name: row.get(4)?,
+            note: row.get(5)?,
+            granted_at: row.get(6)?,
+        })
+    }
+
+    pub fn get(rule_id: Option<i32>, student_id: Option<i32>) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(&format!(
+            "{} WHERE (?1 IS NULL OR g.rule_id = ?1) AND (?2 IS NULL OR g.student_id = ?2)
+             ORDER BY r.name ASC, s.name ASC",
+            GRANT_SELECT
+        ))?;
+        let rows = stmt.query_map(params![rule_id, student_id], Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn revoke(id: i32) -> Result<()> {
+        let db = conn()?;
+        let affected = db.execute("DELETE FROM discount_grants WHERE id = ?1", params![id])?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+const GRANT_SELECT: &str = "SELECT g.id, g.rule_id, r.name, g.student_id, s.name, g.note,
+        g.granted_at
+     FROM discount_grants g
+     JOIN discount_rules r ON r.id = g.rule_id
+     JOIN students s ON s.id = g.student_id";
+
+// What a student qualifies for, worked out once per billing
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct StudentEligibility {
+    pub sibling_order: i32,
+    pub staff_child: bool,
+    pub granted: HashSet<i32>,
+}
+
+impl StudentEligibility {
+    pub fn get_with(db: &Connection, student_id: i32) -> Result<Self> {
+        let older: i32 = db.query_row(
+            "SELECT COUNT(DISTINCT o.id)
+             FROM students s
+             JOIN student_relationships r ON r.student_id = s.id
+             JOIN student_relationships ro ON ro.related_id = r.related_id
+             JOIN students o ON o.id = ro.student_id
+             WHERE s.id = ?1 AND o.id != s.id AND o.session_id = s.session_id
+               AND (o.dob < s.dob OR (o.dob = s.dob AND o.id < s.id))",
+            params![student_id],
+            |row| row.get(0),
+        )?;
+
+        let staff_child: bool = db.query_row(
+            "SELECT EXISTS (SELECT 1 FROM student_staff_links WHERE student_id = ?1)",
+            params![student_id],
+            |row| row.get(0),
+        )?;
+
+        let granted: HashSet<i32> = {
+            let mut stmt =
+                db.prepare("SELECT rule_id FROM discount_grants WHERE student_id = ?1")?;
+            let rows = stmt.query_map(params![student_id], |row| row.get(0))?;
+            rows.collect::<Result<_>>()?
+        };
+
+        Ok(Self {
+            sibling_order: older + 1,
+            staff_child,
+            granted,
+        })
+    }
+
+    fn qualifies(&self, rule: &DiscountRule) -> bool {
+        match rule.eligibility {
+            Eligibility::Sibling => self.sibling_order >= rule.min_sibling_order.unwrap_or(2),
+            Eligibility::StaffChild => self.staff_child,
+            Eligibility::Manual => self.granted.contains(&rule.id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppliedDiscount {
+    pub rule_id: i32,
+    pub name: String,
+    pub amount: i32,
+}
+
+// Discounts on one fee. All stackable rules the student qualifies for add
+// up; a rule that does not stack is used alone. Whichever of the two gives
+// more off wins, and the fee never goes below zero.
+pub fn apply_discounts(
+    rules: &[DiscountRule],
+    student: &StudentEligibility,
+    fee_type: FeeType,
+    date: NaiveDate,
+    amount: i32,
+) -> Vec<AppliedDiscount> {
+    let eligible: Vec<&DiscountRule> = rules
+        .iter()
+        .filter(|r| r.fee_type.map_or(true, |f| f == fee_type))
+        .filter(|r| r.is_valid_on(date) && student.qualifies(r))
+        .collect();
+
+    let mut stacked = Vec::new();
+    let mut remaining = amount;
+    for rule in eligible.iter().filter(|r| r.stackable) {
+        let off = rule.amount_off(amount).min(remaining);
+        if off > 0 {
+            remaining -= off;
+            stacked.push(AppliedDiscount {
+                rule_id: rule.id,
+                name: rule.name.clone(),
+                amount: off,
+            });
+        }
+    }
+
+    let single = eligible
+        .iter()
+        .filter(|r| !r.stackable)
+        .map(|r| (r, r.amount_off(amount)))
+        .filter(|(_, off)| *off > 0)
+        .max_by_key(|(_, off)| *off);
+
+    match single {
+        Some((rule, off)) if off > amount - remaining => vec![AppliedDiscount {
+            rule_id: rule.id,
+            name: rule.name.clone(),
+            amount: off,
+        }],
+        _ => stacked,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FeeDiscounts {
+    pub fee_type: FeeType,
+    pub fee: i32,
+    pub discounts: Vec<AppliedDiscount>,
+    pub net: i32,
+}
+
+// What a student would pay for each fee on `date` once discounts are taken off
+pub fn preview(student_id: i32, date: NaiveDate) -> Result<Vec<FeeDiscounts>> {
+    let db = conn()?;
+    let rules = DiscountRule::get_all_with(&db)?;
+    let student = StudentEligibility::get_with(&db, student_id)?;
+    let fees = FeeSchedule::for_student(&db, student_id)?;
+
+    Ok(FeeType::ALL
+        .into_iter()
+        .filter(|f| *f != FeeType::LateFee)
+        .map(|fee_type| {
+            let fee = fees.amount(fee_type);
+            let discounts = apply_discounts(&rules, &student, fee_type, date, fee);
+            FeeDiscounts {
+                fee_type,
+                fee,
+                net: fee - discounts.iter().map(|d| d.amount).sum::<i32>(),
+                discounts,
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,486 @@
+use super::allocation::allocate_with;
+use super::conn;
+use super::history::History;
+use super::residency::ResidentPeriod;
+use chrono::{Datelike, Local, NaiveDate};
+use rusqlite::{params, Result};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_THRESHOLD: f64 = 0.75;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DuplicateCandidate {
+    pub first_id: i32,
+    pub first_name: String,
+    pub second_id: i32,
+    pub second_name: String,
+    pub score: f64,
+    pub reasons: Vec<String>,
+}
+
+fn normalize_name(name: &str) -> String {
+    let cleaned: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let mut tokens: Vec<&str> = cleaned.split_whitespace().collect();
+    // "Md Rahim" and "Rahim Md" should compare equal
+    tokens.sort_unstable();
+    tokens.join(" ")
+}
+
+fn phone_digits(phone: &str) -> String {
+    let digits: String = phone.chars().filter(|c| c.is_ascii_digit()).collect();
+    digits[digits.len().saturating_sub(10)..].to_string()
+}
+
+fn jaro_winkler(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut a_matched = vec![false; a.len()];
+    let mut b_matched = vec![false; b.len()];
+    let mut matches = 0usize;
+
+    for (i, ca) in a.iter().enumerate() {
+        let start = i.saturating_sub(window);
+        let end = (i + window + 1).min(b.len());
+        for j in start..end {
+            if !b_matched[j] && b[j] == *ca {
+                a_matched[i] = true;
+                b_matched[j] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+
+    if matches == 0 {
+        return 0.0;
+    }
+
+    let a_seq = a
+        .iter()
+        .zip(&a_matched)
+        .filter(|(_, m)| **m)
+        .map(|(c, _)| c);
+    let b_seq = b
+        .iter()
+        .zip(&b_matched)
+        .filter(|(_, m)| **m)
+        .map(|(c, _)| c);
+    let transpositions = a_seq.zip(b_seq).filter(|(x, y)| x != y).count() / 2;
+
+    let m = matches as f64;
+    let jaro = (m / a.len() as f64 + m / b.len() as f64 + (m - transpositions as f64) / m) / 3.0;
+
+    let prefix = a.iter().zip(&b).take(4).take_while(|(x, y)| x == y).count() as f64;
+
+    jaro + prefix * 0.1 * (1.0 - jaro)
+}
+
+// Digit strings that differ in at most one position count as a likely typo
+fn phones_similar(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.chars().zip(b.chars()).filter(|(x, y)| x != y).count() <= 1
+}
+
+struct StudentRow {
+    id: i32,
+    name: String,
+    dob: NaiveDate,
+    phone: Option<String>,
+}
+
+struct GuardianRow {
+    id: i32,
+    name: String,
+    phone: String,
+    address: Option<String>,
+}
+
+impl DuplicateCandidate {
+    pub fn find_students(session_id: Option<i32>, threshold: Option<f64>) -> Result<Vec<Self>> {
+        let threshold = threshold.unwrap_or(DEFAULT_THRESHOLD);
+        let db = conn()?;
+
+        let mut stmt = db.prepare(
+            "SELECT id, name, dob, phone FROM students
+             WHERE ?1 IS NULL OR session_id = ?1
+             ORDER BY id ASC",
+        )?;
+        let students: Vec<StudentRow> = stmt
+            .query_map(params![session_id], |row| {
+                Ok(StudentRow {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    dob: row.get(2)?,
+                    phone: row.get(3)?,
+                })
+            })?
+            .collect::<Result<_>>()?;
+
+        let names: Vec<String> = students.iter().map(|s| normalize_name(&s.name)).collect();
+        let phones: Vec<Option<String>> = students
+            .iter()
+            .map(|s| {
+                s.phone
+                    .as_deref()
+                    .map(phone_digits)
+                    .filter(|p| p.len() >= 7)
+            })
+            .collect();
+
+        let mut candidates = Vec::new();
+        for i in 0..students.len() {
+            for j in i + 1..students.len() {
+                let (a, b) = (&students[i], &students[j]);
+                let name_score = jaro_winkler(&names[i], &names[j]);
+                if name_score < 0.7 {
+                    continue;
+                }
+
+                let mut reasons = vec![format!("Name similarity {:.0}%", name_score * 100.0)];
+                let mut score = name_score * 0.6;
+
+                if a.dob == b.dob {
+                    score += 0.25;
+                    reasons.push("Same date of birth".to_string());
+                } else if a.dob.year() == b.dob.year() && a.dob.month() == b.dob.month() {
+                    score += 0.1;
+                    reasons.push("Date of birth in the same month".to_string());
+                }
+
+                if let (Some(pa), Some(pb)) = (&phones[i], &phones[j]) {
+                    if pa == pb {
+                        score += 0.15;
+                        reasons.push("Same phone number".to_string());
+                    } else if phones_similar(pa, pb) {
+                        score += 0.1;
+                        reasons.push("Similar phone number".to_string());
+                    }
+                }
+
+                if score >= threshold {
+                    candidates.push(DuplicateCandidate {
+                        first_id: a.id,
+                        first_name: a.name.clone(),
+                        second_id: b.id,
+                        second_name: b.name.clone(),
+                        score: (score * 100.0).round() / 100.0,
+                        reasons,
+                    });
+                }
+            }
+        }
+
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        Ok(candidates)
+    }
+
+    pub fn find_guardians(threshold: Option<f64>) -> Result<Vec<Self>> {
+        let threshold = threshold.unwrap_or(DEFAULT_THRESHOLD);
+        let db = conn()?;
+
+        let mut stmt =
+            db.prepare("SELECT id, name, phone, address FROM guardians ORDER BY id ASC")?;
+        let guardians: Vec<GuardianRow> = stmt
+            .query_map([], |row| {
+                Ok(GuardianRow {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    phone: row.get(2)?,
+                    address: row.get(3)?,
+                })
+            })?
+            .collect::<Result<_>>()?;
+
+        let names: Vec<String> = guardians.iter().map(|g| normalize_name(&g.name)).collect();
+        let phones: Vec<String> = guardians.iter().map(|g| phone_digits(&g.phone)).collect();
+        let addresses: Vec<Option<String>> = guardians
+            .iter()
+            .map(|g| {
+                g.address
+                    .as_deref()
+                    .map(normalize_name)
+                    .filter(|a| !a.is_empty())
+            })
+            .collect();
+
+        let mut candidates = Vec::new();
+        for i in 0..guardians.len() {
+            for j in i + 1..guardians.len() {
+                let (a, b) = (&guardians[i], &guardians[j]);
+                let name_score = jaro_winkler(&names[i], &names[j]);
+
+                let mut reasons = Vec::new();
+                let mut score = name_score * 0.5;
+                if name_score >= 0.7 {
+                    reasons.push(format!("Name similarity {:.0}%", name_score * 100.0));
+                }
+
+                if phones[i] == phones[j] {
+                    score += 0.4;
+                    reasons.push("Same phone number".to_string());
+                } else if phones_similar(&phones[i], &phones[j]) {
+                    score += 0.25;
+                    reasons.push("Similar phone number".to_string());
+                }
+
+                if addresses[i].is_some() && addresses[i] == addresses[j] {
+                    score += 0.1;
+                    reasons.push("Same address".to_string());
+                }
+
+                if score >= threshold {
+                    candidates.push(DuplicateCandidate {
+                        first_id: a.id,
+                        first_name: a.name.clone(),
+                        second_id: b.id,
+                        second_name: b.name.clone(),
+                        score: (score * 100.0).round() / 100.0,
+                        reasons,
+                    });
+                }
+            }
+        }
+
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        Ok(candidates)
+    }
+}
+
+fn merge_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+// Moves everything recorded against `merge_id` onto `keep_id` and removes the duplicate
+pub fn merge_students(keep_id: i32, merge_id: i32) -> Result<()> {
+    if keep_id == merge_id {
+        return Err(merge_error("Cannot merge a student into itself."));
+    }
+
+    let mut db = conn()?;
+    db.execute("PRAGMA foreign_keys = ON", [])?;
+    let tx = db.transaction()?;
+
+    let merged = tx.query_row(
+        "SELECT json_object(
+            'id', id, 'name', name, 'class_id', class_id, 'section_id', section_id,
+            'session_id', session_id, 'dob', dob, 'phone', phone, 'address', address, 'roll', roll
+         ), session_id, class_id, section_id, roll
+         FROM students WHERE id = ?1",
+        params![merge_id],
+        |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i32>(1)?,
+                row.get::<_, i32>(2)?,
+                row.get::<_, Option<i32>>(3)?,
+                row.get::<_, i32>(4)?,
+            ))
+        },
+    )?;
+    let (details, session_id, class_id, section_id, roll) = merged;
+
+    tx.query_row(
+        "SELECT id FROM students WHERE id = ?1",
+        params![keep_id],
+        |row| row.get::<_, i32>(0),
+    )?;
+
+    // Where both records have a mark for the same day the surviving one wins
+    tx.execute(
+        "UPDATE OR IGNORE attendance SET student_id = ?1 WHERE student_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "DELETE FROM attendance WHERE student_id = ?1",
+        params![merge_id],
+    )?;
+    tx.execute(
+        "UPDATE OR IGNORE period_attendance SET student_id = ?1 WHERE student_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "DELETE FROM period_attendance WHERE student_id = ?1",
+        params![merge_id],
+    )?;
+    // The override log has no foreign key to follow the student
+    tx.execute(
+        "UPDATE attendance_overrides SET owner_id = ?1
+         WHERE kind = 'STUDENT' AND owner_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+
+    // Payments, receipts and statement matches follow the student; the surviving fee override wins
+    tx.execute(
+        "UPDATE payments SET student_id = ?1 WHERE student_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "UPDATE receipts SET student_id = ?1 WHERE student_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "UPDATE statement_lines SET student_id = ?1 WHERE student_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "UPDATE OR IGNORE student_fee_overrides SET student_id = ?1 WHERE student_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "UPDATE OR IGNORE discount_grants SET student_id = ?1 WHERE student_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    // Both may have been billed for the same month; keep one set of lines
+    tx.execute(
+        "UPDATE OR IGNORE invoice_lines SET student_id = ?1 WHERE student_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "DELETE FROM invoice_lines WHERE student_id = ?1",
+        params![merge_id],
+    )?;
+    // Payments freed from the dropped lines go to what is still owed
+    allocate_with(&tx, keep_id)?;
+
+    // Hostel stays of both records become one history
+    let is_resident: bool = tx.query_row(
+        "SELECT MAX(is_resident) FROM students WHERE id IN (?1, ?2)",
+        params![keep_id, merge_id],
+        |row| row.get(0),
+    )?;
+    tx.execute(
+        "UPDATE resident_periods SET student_id = ?1 WHERE student_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    ResidentPeriod::coalesce_with(&tx, keep_id)?;
+    ResidentPeriod::sync(&tx, keep_id, is_resident, Local::now().date_naive())?;
+    tx.execute(
+        "UPDATE students SET is_resident = ?2 WHERE id = ?1",
+        params![keep_id, is_resident],
+    )?;
+
+    tx.execute(
+        "DELETE FROM student_relationships
+         WHERE student_id = ?2
+         AND related_id IN (SELECT related_id FROM student_relationships WHERE student_id = ?1)",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "UPDATE student_relationships SET student_id = ?1 WHERE student_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "DELETE FROM student_staff_links
+         WHERE student_id = ?2
+         AND staff_id IN (SELECT staff_id FROM student_staff_links WHERE student_id = ?1)",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "UPDATE student_staff_links SET student_id = ?1 WHERE student_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+
+    // Fill gaps in the surviving record from the duplicate
+    tx.execute(
+        "UPDATE students SET
+            phone = COALESCE(phone, (SELECT phone FROM students WHERE id = ?2)),
+            photo = COALESCE(photo, (SELECT photo FROM students WHERE id = ?2)),
+            health_notes = COALESCE(health_notes, (SELECT health_notes FROM students WHERE id = ?2)),
+            general_notes = COALESCE(general_notes, (SELECT general_notes FROM students WHERE id = ?2))
+         WHERE id = ?1",
+        params![keep_id, merge_id],
+    )?;
+
+    tx.execute("DELETE FROM students WHERE id = ?1", params![merge_id])?;
+    tx.execute(
+        "UPDATE students
+         SET roll = roll - 1
+         WHERE session_id = ?1
+         AND class_id = ?2
+         AND section_id IS ?3
+         AND roll > ?4",
+        params![session_id, class_id, section_id, roll],
+    )?;
+
+    History::record(&tx, "student", keep_id, "merge", Some(details))?;
+
+    tx.commit()
+}
+
+pub fn merge_guardians(keep_id: i32, merge_id: i32) -> Result<()> {
+    if keep_id == merge_id {
+        return Err(merge_error("Cannot merge a guardian into itself."));
+    }
+
+    let mut db = conn()?;
+    let tx = db.transaction()?;
+
+    let details = tx.query_row(
+        "SELECT json_object('id', id, 'name', name, 'phone', phone, 'address', address)
+         FROM guardians WHERE id = ?1",
+        params![merge_id],
+        |row| row.get::<_, String>(0),
+    )?;
+
+    tx.query_row(
+        "SELECT id FROM guardians WHERE id = ?1",
+        params![keep_id],
+        |row| row.get::<_, i32>(0),
+    )?;
+
+    tx.execute(
+        "DELETE FROM student_relationships
+         WHERE related_id = ?2
+         AND student_id IN (SELECT student_id FROM student_relationships WHERE related_id = ?1)",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "UPDATE student_relationships SET related_id = ?1 WHERE related_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+
+    // Payments and deposits made by the merged record
+    tx.execute(
+        "UPDATE payments SET payer_id = ?1 WHERE payer_type = 'GUARDIAN' AND payer_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "UPDATE receipts SET payer_id = ?1 WHERE payer_type = 'GUARDIAN' AND payer_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+    tx.execute(
+        "UPDATE statement_lines SET guardian_id = ?1 WHERE guardian_id = ?2",
+        params![keep_id, merge_id],
+    )?;
+
+    tx.execute(
+        "UPDATE guardians SET
+            address = COALESCE(address, (SELECT address FROM guardians WHERE id = ?2)),
+            photo = COALESCE(photo, (SELECT photo FROM guardians WHERE id = ?2))
+         WHERE id = ?1",
+        params![keep_id, merge_id],
+    )?;
+
+    tx.execute("DELETE FROM guardians WHERE id = ?1", params![merge_id])?;
+
+    History::record(&tx, "guardian", keep_id, "merge", Some(details))?;
+
+    tx.commit()
+}
@@ -0,0 +1,373 @@
+use super::analytics::{ChronicAbsenceRule, StudentAnalytics};
+use super::attendance::AttendanceStatus;
+use super::billing::due_lines;
+use super::conn;
+use super::guardian::Guardian;
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FamilyMember {
+    pub student_id: i32,
+    pub name: String,
+    pub session_id: i32,
+    pub class_id: i32,
+    pub class_name: String,
+    pub section_id: Option<i32>,
+    pub section_name: Option<String>,
+    pub roll: i32,
+    pub relationship: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SiblingSuggestion {
+    pub student_id: i32,
+    pub name: String,
+    pub class_name: String,
+    pub sibling_id: Option<i32>,
+    pub sibling_name: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Family {
+    pub guardian: Guardian,
+    pub children: Vec<FamilyMember>,
+    pub suggested: Vec<SiblingSuggestion>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AttendanceAlert {
+    pub student_id: i32,
+    pub name: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FamilySummary {
+    pub guardian: Guardian,
+    pub children: Vec<FamilyMember>,
+    pub classes: Vec<String>,
+    pub alerts: Vec<AttendanceAlert>,
+    // Fees billed to the children and not yet paid
+    pub dues: i32,
+}
+
+// Phone numbers are compared on their last 10 digits so "01711-000000" and
+// "+8801711000000" end up in the same bucket.
+pub(super) fn phone_key(phone: &str) -> Option<String> {
+    let digits: String = phone.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.len() < 7 {
+        return None;
+    }
+    Some(digits[digits.len().saturating_sub(10)..].to_string())
+}
+
+fn address_key(address: &str) -> Option<String> {
+    let key = address
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    if key.is_empty() {
+        None
+    } else {
+        Some(key)
+    }
+}
+
+const MEMBER_COLUMNS: &str =
+    "s.id, s.name, s.session_id, s.class_id, c.name, s.section_id, sec.name, s.roll,
+     s.phone, s.address";
+
+const MEMBER_FROM: &str = "FROM students s
+     JOIN classes c ON c.id = s.class_id
+     LEFT JOIN sections sec ON sec.id = s.section_id";
+
+fn member_from_row(row: &rusqlite::Row, relationship: Option<String>) -> Result<FamilyMember> {
+    Ok(FamilyMember {
+        student_id: row.get(0)?,
+        name: row.get(1)?,
+        session_id: row.get(2)?,
+        class_id: row.get(3)?,
+        class_name: row.get(4)?,
+        section_id: row.get(5)?,
+        section_name: row.get(6)?,
+        roll: row.get(7)?,
+        relationship,
+    })
+}
+
+fn get_guardian(db: &Connection, guardian_id: i32) -> Result<Guardian> {
+    db.query_row(
+        "SELECT id, name, phone, address, photo FROM guardians WHERE id = ?1",
+        params![guardian_id],
+        |row| {
+            Ok(Guardian {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                phone: row.get(2)?,
+                address: row.get(3)?,
+                photo: row.get(4)?,
+            })
+        },
+    )
+}
+
+fn get_children(db: &Connection, guardian_id: i32) -> Result<Vec<FamilyMember>> {
+    let mut stmt = db.prepare(&format!(
+        "SELECT {MEMBER_COLUMNS}, r.relationship {MEMBER_FROM}
+         JOIN student_relationships r ON r.student_id = s.id
+         WHERE r.related_id = ?1
+         ORDER BY s.session_id DESC, c.level ASC, s.roll ASC"
+    ))?;
+    let rows = stmt.query_map(params![guardian_id], |row| {
+        let relationship: Option<String> = row.get(10)?;
+        member_from_row(row, relationship)
+    })?;
+    rows.collect()
+}
+
+// Students together with their own phone number and address
+fn contact_from_row(row: &rusqlite::Row) -> Result<(FamilyMember, String, String)> {
+    Ok((
+        member_from_row(row, None)?,
+        row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+        row.get::<_, String>(9)?,
+    ))
+}
+
+fn get_unlinked_students(
+    db: &Connection,
+    guardian_id: i32,
+) -> Result<Vec<(FamilyMember, String, String)>> {
+    let mut stmt = db.prepare(&format!(
+        "SELECT {MEMBER_COLUMNS} {MEMBER_FROM}
+         WHERE s.id NOT IN (SELECT student_id FROM student_relationships WHERE related_id = ?1)"
+    ))?;
+    let rows = stmt.query_map(params![guardian_id], contact_from_row)?;
+    rows.collect()
+}
+
+fn get_alerts(children: &[FamilyMember]) -> Result<Vec<AttendanceAlert>> {
+    let today = Local::now().naive_local().date();
+    let rule = ChronicAbsenceRule::load()?;
+    let mut alerts = Vec::new();
+
+    for child in children {
+        let absent_today = {
+            let db = conn()?;
+            db.query_row(
+                "SELECT status FROM attendance WHERE student_id = ?1 AND date = ?2",
+                params![child.student_id, today],
+                |row| row.get::<_, AttendanceStatus>(0),
+            )
+            .optional()?
+                == Some(AttendanceStatus::Absent)
+        };
+
+        if absent_today {
+            alerts.push(AttendanceAlert {
+                student_id: child.student_id,
+                name: child.name.clone(),
+                message: "Absent today".to_string(),
+            });
+        }
+
+        let analytics = StudentAnalytics::get(child.student_id, rule.window_start(today), today)?;
+        for reason in rule.reasons(&analytics) {
+            alerts.push(AttendanceAlert {
+                student_id: child.student_id,
+                name: child.name.clone(),
+                message: reason,
+            });
+        }
+    }
+
+    Ok(alerts)
+}
+
+impl Family {
+    pub fn get(guardian_id: i32) -> Result<Self> {
+        let db = conn()?;
+        let guardian = get_guardian(&db, guardian_id)?;
+        let children = get_children(&db, guardian_id)?;
+
+        let mut phones: HashSet<String> = phone_key(&guardian.phone).into_iter().collect();
+        let mut addresses: HashSet<String> = guardian
+            .address
+            .as_deref()
+            .and_then(address_key)
+            .into_iter()
+            .collect();
+
+        // Contact details of the linked children widen the net as well
+        let mut stmt = db.prepare("SELECT phone, address FROM students WHERE id = ?1")?;
+        for child in &children {
+            let (phone, address): (Option<String>, String) = stmt
+                .query_row(params![child.student_id], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?;
+            phones.extend(phone.as_deref().and_then(phone_key));
+            addresses.extend(address_key(&address));
+        }
+
+        let mut suggested = Vec::new();
+        for (student, phone, address) in get_unlinked_students(&db, guardian_id)? {
+            let reason = if phone_key(&phone).is_some_and(|k| phones.contains(&k)) {
+                "Matching phone number"
+            } else if address_key(&address).is_some_and(|k| addresses.contains(&k)) {
+                "Matching address"
+            } else {
+                continue;
+            };
+            suggested.push(SiblingSuggestion {
+                student_id: student.student_id,
+                name: student.name,
+                class_name: student.class_name,
+                sibling_id: children.first().map(|c| c.student_id),
+                sibling_name: children.first().map(|c| c.name.clone()),
+                reason: reason.to_string(),
+            });
+        }
+
+        Ok(Self {
+            guardian,
+            children,
+            suggested,
+        })
+    }
+
+    pub fn get_by_student(student_id: i32) -> Result<Vec<Self>> {
+        let guardian_ids: Vec<i32> = {
+            let db = conn()?;
+            let mut stmt = db.prepare(
+                "SELECT DISTINCT related_id FROM student_relationships WHERE student_id = ?1",
+            )?;
+            let rows = stmt.query_map(params![student_id], |row| row.get(0))?;
+            rows.collect::<Result<_>>()?
+        };
+
+        guardian_ids.into_iter().map(Self::get).collect()
+    }
+
+    // Guardians with more than one child linked in the given session
+    pub fn get_all(session_id: i32) -> Result<Vec<Self>> {
+        let guardian_ids: Vec<i32> = {
+            let db = conn()?;
+            let mut stmt = db.prepare(
+                "SELECT r.related_id
+                 FROM student_relationships r
+                 JOIN students s ON s.id = r.student_id
+                 WHERE s.session_id = ?1
+                 GROUP BY r.related_id
+                 HAVING COUNT(DISTINCT r.student_id) > 1",
+            )?;
+            let rows = stmt.query_map(params![session_id], |row| row.get(0))?;
+            rows.collect::<Result<_>>()?
+        };
+
+        guardian_ids.into_iter().map(Self::get).collect()
+    }
+
+    // Students of a session sharing a phone number or address without sharing a guardian
+    pub fn detect_siblings(session_id: i32) -> Result<Vec<SiblingSuggestion>> {
+        let db = conn()?;
+
+        let mut stmt = db.prepare(&format!(
+            "SELECT {MEMBER_COLUMNS} {MEMBER_FROM} WHERE s.session_id = ?1 ORDER BY s.id ASC"
+        ))?;
+        let students: Vec<(FamilyMember, String, String)> = stmt
+            .query_map(params![session_id], contact_from_row)?
+            .collect::<Result<_>>()?;
+
+        let mut stmt = db.prepare(
+            "SELECT a.student_id, b.student_id
+             FROM student_relationships a
+             JOIN student_relationships b ON a.related_id = b.related_id",
+        )?;
+        let linked: HashSet<(i32, i32)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_>>()?;
+
+        let mut groups: BTreeMap<(String, String), Vec<usize>> = BTreeMap::new();
+        for (idx, (_, phone, address)) in students.iter().enumerate() {
+            if let Some(key) = phone_key(phone) {
+                groups
+                    .entry(("Matching phone number".to_string(), key))
+                    .or_default()
+                    .push(idx);
+            }
+            if let Some(key) = address_key(address) {
+                groups
+                    .entry(("Matching address".to_string(), key))
+                    .or_default()
+                    .push(idx);
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut suggestions = Vec::new();
+        for ((reason, _), members) in groups {
+            for (i, &a) in members.iter().enumerate() {
+                for &b in &members[i + 1..] {
+                    let (first, second) = (&students[a].0, &students[b].0);
+                    let pair = (first.student_id, second.student_id);
+                    if linked.contains(&pair) || !seen.insert(pair) {
+                        continue;
+                    }
+                    suggestions.push(SiblingSuggestion {
+                        student_id: second.student_id,
+                        name: second.name.clone(),
+                        class_name: second.class_name.clone(),
+                        sibling_id: Some(first.student_id),
+                        sibling_name: Some(first.name.clone()),
+                        reason: reason.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(suggestions)
+    }
+}
+
+impl FamilySummary {
+    pub fn get(guardian_id: i32) -> Result<Self> {
+        let db = conn()?;
+        let guardian = get_guardian(&db, guardian_id)?;
+        let children = get_children(&db, guardian_id)?;
+
+        let mut classes: Vec<String> = Vec::new();
+        for child in &children {
+            let class = match &child.section_name {
+                Some(section) => format!("{} ({})", child.class_name, section),
+                None => child.class_name.clone(),
+            };
+            if !classes.contains(&class) {
+                classes.push(class);
+            }
+        }
+
+        let mut dues = 0;
+        for child in &children {
+            dues += due_lines(&db, child.student_id)?
+                .iter()
+                .map(|l| l.outstanding)
+                .sum::<i32>();
+        }
+
+        drop(db);
+        let alerts = get_alerts(&children)?;
+
+        Ok(Self {
+            guardian,
+            children,
+            classes,
+            alerts,
+            dues,
+        })
+    }
+}
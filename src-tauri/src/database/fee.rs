@@ -0,0 +1,799 @@
+use super::billing::InvoiceLine;
+use super::receipt::{Receipt, ReceiptItemInput};
+use super::residency::{resident_days, ResidentPeriod};
+use super::settings::DEFAULT_RESIDENT_FEE_KEY;
+use super::{add_column_if_missing, conn};
+use chrono::{Datelike, Local, NaiveDate};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+fn fee_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+// Billing months are kept as "YYYY-MM"
+pub fn month_key(date: NaiveDate) -> String {
+    date.format("%Y-%m").to_string()
+}
+
+// First day of a "YYYY-MM" month
+pub fn parse_month(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{}-01", value.trim()), "%Y-%m-%d").ok()
+}
+
+// Who handed over the money
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RelatedType {
+    Guardian,
+    Teacher,
+    Staff,
+}
+
+impl RelatedType {
+    pub const ALL: [Self; 3] = [Self::Guardian, Self::Teacher, Self::Staff];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Guardian => "GUARDIAN",
+            Self::Teacher => "TEACHER",
+            Self::Staff => "STAFF",
+        }
+    }
+}
+
+impl ToSql for RelatedType {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for RelatedType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|t| t.as_str() == text)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown payer type: {}", text).into()))
+    }
+}
+
+// How the money came in. Wallet and bank payments carry the transaction id
+// from the statement they will show up on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PaymentMethod {
+    Cash,
+    Bkash,
+    Nagad,
+    Bank,
+}
+
+impl PaymentMethod {
+    pub const ALL: [Self; 4] = [Self::Cash, Self::Bkash, Self::Nagad, Self::Bank];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Cash => "CASH",
+            Self::Bkash => "BKASH",
+            Self::Nagad => "NAGAD",
+            Self::Bank => "BANK",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Cash => "Cash",
+            Self::Bkash => "bKash",
+            Self::Nagad => "Nagad",
+            Self::Bank => "Bank deposit",
+        }
+    }
+
+    pub fn is_wallet(&self) -> bool {
+        matches!(self, Self::Bkash | Self::Nagad)
+    }
+
+    pub fn check_constraint(column: &str) -> String {
+        let values: Vec<String> = Self::ALL
+            .iter()
+            .map(|m| format!("'{}'", m.as_str()))
+            .collect();
+        format!("CHECK ({} IN ({}))", column, values.join(", "))
+    }
+}
+
+impl ToSql for PaymentMethod {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for PaymentMethod {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|m| m.as_str() == text)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown payment method: {}", text).into()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FeeType {
+    Admission,
+    Monthly,
+    Readmission,
+    // Penalty on a monthly fee paid after its due date
+    LateFee,
+    // Hostel fee, charged monthly while the student is resident
+    Resident,
+}
+
+impl FeeType {
+    pub const ALL: [Self; 5] = [
+        Self::Admission,
+        Self::Monthly,
+        Self::Readmission,
+        Self::LateFee,
+        Self::Resident,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Admission => "ADMISSION",
+            Self::Monthly => "MONTHLY",
+            Self::Readmission => "READMISSION",
+            Self::LateFee => "LATE_FEE",
+            Self::Resident => "RESIDENT",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Admission => "Admission fee",
+            Self::Monthly => "Monthly fee",
+            Self::Readmission => "Readmission fee",
+            Self::LateFee => "Late fee",
+            Self::Resident => "Resident fee",
+        }
+    }
+
+    // Fees kept per month rather than once per session
+    pub fn is_monthly(&self) -> bool {
+        matches!(self, Self::Monthly | Self::LateFee | Self::Resident)
+    }
+
+    pub fn check_constraint(column: &str) -> String {
+        let values: Vec<String> = Self::ALL
+            .iter()
+            .map(|t| format!("'{}'", t.as_str()))
+            .collect();
+        format!("CHECK ({} IN ({}))", column, values.join(", "))
+    }
+}
+
+impl ToSql for FeeType {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for FeeType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|t| t.as_str() == text)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown fee type: {}", text).into()))
+    }
+}
+
+// Per-student replacement of the class fees; None keeps the class amount
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StudentFeeOverride {
+    pub id: i32,
+    pub student_id: i32,
+    pub admission_fee: Option<i32>,
+    pub monthly_fee: Option<i32>,
+    pub readmission_fee: Option<i32>,
+    pub resident_fee: Option<i32>,
+}
+
+impl StudentFeeOverride {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS student_fee_overrides (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                student_id INTEGER NOT NULL UNIQUE,
+                admission_fee INTEGER CHECK (admission_fee >= 0),
+                monthly_fee INTEGER CHECK (monthly_fee >= 0),
+                readmission_fee INTEGER CHECK (readmission_fee >= 0),
+                FOREIGN KEY (student_id) REFERENCES students(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        add_column_if_missing(
+            &db,
+            "student_fee_overrides",
+            "resident_fee",
+            "INTEGER CHECK (resident_fee >= 0)",
+        )?;
+        Ok(())
+    }
+
+    pub fn set(
+        student_id: i32,
+        admission_fee: Option<i32>,
+        monthly_fee: Option<i32>,
+        readmission_fee: Option<i32>,
+        resident_fee: Option<i32>,
+    ) -> Result<Self> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let id: i32 = db.query_row(
+            "INSERT INTO student_fee_overrides
+                (student_id, admission_fee, monthly_fee, readmission_fee, resident_fee)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(student_id) DO UPDATE SET
+                admission_fee = excluded.admission_fee,
+                monthly_fee = excluded.monthly_fee,
+                readmission_fee = excluded.readmission_fee,
+                resident_fee = excluded.resident_fee
+             RETURNING id",
+            params![
+                student_id,
+                admission_fee,
+                monthly_fee,
+                readmission_fee,
+                resident_fee
+            ],
+            |row| row.get(0),
+        )?;
+
+        Ok(Self {
+            id,
+            student_id,
+            admission_fee,
+            monthly_fee,
+            readmission_fee,
+            resident_fee,
+        })
+    }
+
+    pub fn get(student_id: i32) -> Result<Option<Self>> {
+        let db = conn()?;
+        db.query_row(
+            "SELECT id, student_id, admission_fee, monthly_fee, readmission_fee, resident_fee
+             FROM student_fee_overrides WHERE student_id = ?1",
+            params![student_id],
+            |row| {
+                Ok(Self {
+                    id: row.get(0)?,
+                    student_id: row.get(1)?,
+                    admission_fee: row.get(2)?,
+                    monthly_fee: row.get(3)?,
+                    readmission_fee: row.get(4)?,
+                    resident_fee: row.get(5)?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    pub fn delete(student_id: i32) -> Result<()> {
+        let db = conn()?;
+        let affected = db.execute(
+            "DELETE FROM student_fee_overrides WHERE student_id = ?1",
+            params![student_id],
+        )?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// The fees a student is actually charged: the class fees with any override
+// applied. The resident fee is the school's default unless overridden, and
+// is only charged while the student is resident.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FeeSchedule {
+    pub admission_fee: i32,
+    pub monthly_fee: i32,
+    pub readmission_fee: i32,
+    pub resident_fee: i32,
+    pub overridden: bool,
+}
+
+impl FeeSchedule {
+    pub fn for_student(db: &Connection, student_id: i32) -> Result<Self> {
+        db.query_row(
+            "SELECT COALESCE(o.admission_fee, c.admission_fee),
+                    COALESCE(o.monthly_fee, c.monthly_fee),
+                    COALESCE(o.readmission_fee, c.readmission_fee),
+                    COALESCE(o.resident_fee,
+                             (SELECT CAST(value AS INTEGER) FROM settings WHERE key = ?2), 0),
+                    o.id IS NOT NULL
+             FROM students s
+             JOIN classes c ON c.id = s.class_id
+             LEFT JOIN student_fee_overrides o ON o.student_id = s.id
+             WHERE s.id = ?1",
+            params![student_id, DEFAULT_RESIDENT_FEE_KEY],
+            |row| {
+                Ok(Self {
+                    admission_fee: row.get(0)?,
+                    monthly_fee: row.get(1)?,
+                    readmission_fee: row.get(2)?,
+                    resident_fee: row.get(3)?,
+                    overridden: row.get(4)?,
+                })
+            },
+        )
+    }
+
+    pub fn amount(&self, fee_type: FeeType) -> i32 {
+        match fee_type {
+            FeeType::Admission => self.admission_fee,
+            FeeType::Monthly => self.monthly_fee,
+            FeeType::Readmission => self.readmission_fee,
+            // Worked out from the late fee policy instead
+            FeeType::LateFee => 0,
+            FeeType::Resident => self.resident_fee,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Payment {
+    pub id: i32,
+    pub student_id: i32,
+    // Class at the time of payment, kept when the student moves on
+    pub class_id: i32,
+    pub amount: i32,
+    pub payment_date: NaiveDate,
+    // None for a lump sum, spread over whatever is owed
+    pub fee_type: Option<FeeType>,
+    // "YYYY-MM" for monthly fees
+    pub month: Option<String>,
+    pub payer_id: i32,
+    pub payer_type: RelatedType,
+    pub payer_name: String,
+    pub remark: Option<String>,
+    pub receipt_id: Option<i32>,
+    // Taken from the receipt
+    pub method: PaymentMethod,
+    pub transaction_ref: Option<String>,
+}
+
+const PAYMENT_SELECT: &str = "SELECT p.id, p.student_id, p.class_id, p.amount, p.payment_date,
+        p.fee_type, p.month, p.payer_id, p.payer_type, COALESCE(g.name, st.name, ''), p.remark,
+        p.receipt_id, COALESCE(r.method, 'CASH'), r.transaction_ref
+     FROM payments p
+     LEFT JOIN receipts r ON r.id = p.receipt_id
+     LEFT JOIN guardians g ON p.payer_type = 'GUARDIAN' AND g.id = p.payer_id
+     LEFT JOIN staffs st ON p.payer_type IN ('TEACHER', 'STAFF') AND st.id = p.payer_id";
+
+impl Payment {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        // Students with payments cannot be deleted, so money is never lost
+        // silently.
+        db.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS payments (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    student_id INTEGER NOT NULL,
+                    class_id INTEGER NOT NULL,
+                    amount INTEGER NOT NULL CHECK (amount > 0),
+                    payment_date DATE NOT NULL,
+                    fee_type TEXT {},
+                    month TEXT,
+                    payer_id INTEGER NOT NULL,
+                    payer_type TEXT NOT NULL CHECK (payer_type IN ('GUARDIAN', 'TEACHER', 'STAFF')),
+                    remark TEXT,
+                    FOREIGN KEY (student_id) REFERENCES students(id)
+                )",
+                FeeType::check_constraint("fee_type")
+            ),
+            [],
+        )?;
+        db.execute(
+            "CREATE INDEX IF NOT EXISTS idx_payments_student ON payments (student_id, payment_date)",
+            [],
+        )?;
+        add_column_if_missing(
+            &db,
+            "payments",
+            "receipt_id",
+            "INTEGER REFERENCES receipts(id)",
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            student_id: row.get(1)?,
+            class_id: row.get(2)?,
+            amount: row.get(3)?,
+            payment_date: row.get(4)?,
+            fee_type: row.get(5)?,
+            month: row.get(6)?,
+            payer_id: row.get(7)?,
+            payer_type: row.get(8)?,
+            payer_name: row.get(9)?,
+            remark: row.get(10)?,
+            receipt_id: row.get(11)?,
+            method: row.get(12)?,
+            transaction_ref: row.get(13)?,
+        })
+    }
+
+    pub fn get_with(db: &Connection, id: i32) -> Result<Self> {
+        db.query_row(
+            &format!("{} WHERE p.id = ?1", PAYMENT_SELECT),
+            params![id],
+            Self::from_row,
+        )
+    }
+
+    // Guardians pay for their own children only; teachers and staff must be
+    // on the staff list.
+    pub(super) fn check_payer(
+        db: &Connection,
+        student_id: i32,
+        payer_id: i32,
+        payer_type: RelatedType,
+    ) -> Result<()> {
+        let valid: bool = match payer_type {
+            RelatedType::Guardian => db.query_row(
+                "SELECT COUNT(*) > 0 FROM student_relationships
+                 WHERE student_id = ?1 AND related_id = ?2",
+                params![student_id, payer_id],
+                |row| row.get(0),
+            )?,
+            RelatedType::Teacher | RelatedType::Staff => db.query_row(
+                "SELECT COUNT(*) > 0 FROM staffs WHERE id = ?1 AND (is_teacher OR ?2 = 0)",
+                params![payer_id, payer_type == RelatedType::Teacher],
+                |row| row.get(0),
+            )?,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(fee_error(&format!(
+                "Payer is not a {} of this student",
+                payer_type.as_str().to_lowercase()
+            )))
+        }
+    }
+
+    pub(super) fn normalize_month(
+        fee_type: FeeType,
+        month: Option<String>,
+    ) -> Result<Option<String>> {
+        match (fee_type, month) {
+            (fee_type, Some(month)) if fee_type.is_monthly() => parse_month(&month)
+                .map(|m| Some(month_key(m)))
+                .ok_or_else(|| fee_error(&format!("Invalid month: {}", month))),
+            (fee_type, None) if fee_type.is_monthly() => {
+                Err(fee_error(&format!("{}s need a month", fee_type.label())))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Payments always come with a receipt; this records a receipt for a
+    // single fee.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        student_id: i32,
+        amount: i32,
+        payment_date: NaiveDate,
+        fee_type: FeeType,
+        month: Option<String>,
+        payer_id: i32,
+        payer_type: RelatedType,
+        method: PaymentMethod,
+        transaction_ref: Option<String>,
+        remark: Option<String>,
+    ) -> Result<Self> {
+        let receipt = Receipt::create(
+            student_id,
+            payment_date,
+            payer_id,
+            payer_type,
+            method,
+            transaction_ref,
+            vec![ReceiptItemInput {
+                fee_type,
+                month,
+                amount,
+            }],
+            remark,
+        )?;
+        let payment_id = receipt.items[0]
+            .payment_id
+            .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+
+        let db = conn()?;
+        Self::get_with(&db, payment_id)
+    }
+
+    // Inserts a checked payment as part of a receipt
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn insert(
+        db: &Connection,
+        student_id: i32,
+        class_id: i32,
+        amount: i32,
+        payment_date: NaiveDate,
+        fee_type: Option<FeeType>,
+        month: Option<&str>,
+        payer_id: i32,
+        payer_type: RelatedType,
+        remark: Option<&str>,
+        receipt_id: i32,
+    ) -> Result<i32> {
+        db.execute(
+            "INSERT INTO payments
+                (student_id, class_id, amount, payment_date, fee_type, month, payer_id, payer_type,
+                 remark, receipt_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                student_id,
+                class_id,
+                amount,
+                payment_date,
+                fee_type,
+                month,
+                payer_id,
+                payer_type,
+                remark,
+                receipt_id
+            ],
+        )?;
+        Ok(db.last_insert_rowid() as i32)
+    }
+
+    pub fn get_by_student(student_id: i32) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(&format!(
+            "{} WHERE p.student_id = ?1 ORDER BY p.payment_date ASC, p.id ASC",
+            PAYMENT_SELECT
+        ))?;
+        let rows = stmt.query_map(params![student_id], Self::from_row)?;
+        rows.collect()
+    }
+
+    // Receipted payments are voided by cancelling the receipt, which keeps
+    // its number in the sequence.
+    pub fn delete(id: i32) -> Result<()> {
+        let db = conn()?;
+        let receipt: Option<Option<i32>> = db
+            .query_row(
+                "SELECT receipt_id FROM payments WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(Some(_)) = receipt {
+            return Err(fee_error(
+                "Payment has a receipt; cancel the receipt instead",
+            ));
+        }
+        let affected = db.execute("DELETE FROM payments WHERE id = ?1", params![id])?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// A fee falling due. Students admitted during the session pay the admission
+// fee; those carried over from an earlier session pay readmission at its
+// start. The monthly fee is due every month from enrolment until `until`.
+// Billing runs turn these into invoice lines.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Charge {
+    pub date: NaiveDate,
+    pub fee_type: FeeType,
+    pub month: Option<String>,
+    pub amount: i32,
+    // How a part-month charge was worked out
+    pub note: Option<String>,
+}
+
+pub fn charges_for(db: &Connection, student_id: i32, until: NaiveDate) -> Result<Vec<Charge>> {
+    let (admission_date, start_date, end_date): (NaiveDate, NaiveDate, NaiveDate) = db.query_row(
+        "SELECT s.admission_date, ses.start_date, ses.end_date
+             FROM students s JOIN sessions ses ON ses.id = s.session_id
+             WHERE s.id = ?1",
+        params![student_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let fees = FeeSchedule::for_student(db, student_id)?;
+
+    let mut charges = Vec::new();
+    let (fee_type, date) = if admission_date >= start_date {
+        (FeeType::Admission, admission_date)
+    } else {
+        (FeeType::Readmission, start_date)
+    };
+    if date <= until && fees.amount(fee_type) > 0 {
+        charges.push(Charge {
+            date,
+            fee_type,
+            month: None,
+            amount: fees.amount(fee_type),
+            note: None,
+        });
+    }
+
+    let first = admission_date.max(start_date);
+    let last = until.min(end_date);
+    let mut month = first.with_day(1).unwrap_or(first);
+    while month <= last && fees.monthly_fee > 0 {
+        charges.push(Charge {
+            date: month.max(first),
+            fee_type: FeeType::Monthly,
+            month: Some(month_key(month)),
+            amount: fees.monthly_fee,
+            note: None,
+        });
+        month = match month.checked_add_months(chrono::Months::new(1)) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    // The resident fee is for the share of each month spent in residence,
+    // charged from its first resident day
+    let periods = ResidentPeriod::get_with(db, student_id)?;
+    let mut month = first.with_day(1).unwrap_or(first);
+    while month <= last && fees.resident_fee > 0 && !periods.is_empty() {
+        let Some(next) = month.checked_add_months(chrono::Months::new(1)) else {
+            break;
+        };
+        let in_month = (next - month).num_days();
+        let (days, from) = resident_days(
+            &periods,
+            month.max(first),
+            next.min(last + chrono::Days::new(1)),
+        );
+        if let Some(from) = from {
+            let fee = fees.resident_fee as i64;
+            charges.push(Charge {
+                date: from,
+                fee_type: FeeType::Resident,
+                month: Some(month_key(month)),
+                amount: ((fee * days + in_month / 2) / in_month) as i32,
+                note: (days < in_month).then(|| format!("{} of {} days", days, in_month)),
+            });
+        }
+        month = next;
+    }
+
+    Ok(charges)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LedgerEntry {
+    pub date: NaiveDate,
+    pub description: String,
+    pub fee_type: Option<FeeType>,
+    pub month: Option<String>,
+    pub charge: i32,
+    pub payment: i32,
+    // Owed after this entry; negative when paid in advance
+    pub balance: i32,
+    pub payment_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StudentLedger {
+    pub student_id: i32,
+    pub name: String,
+    pub class_name: String,
+    pub fees: FeeSchedule,
+    pub entries: Vec<LedgerEntry>,
+    pub total_charged: i32,
+    pub total_paid: i32,
+    pub balance: i32,
+}
+
+impl StudentLedger {
+    // Billed fees and payments in date order, charges first on the same day
+    pub fn get(student_id: i32, as_of: Option<NaiveDate>) -> Result<Self> {
+        let as_of = as_of.unwrap_or_else(|| Local::now().naive_local().date());
+        let payments = Payment::get_by_student(student_id)?;
+
+        let db = conn()?;
+        let (name, class_name): (String, String) = db.query_row(
+            "SELECT s.name, c.name FROM students s JOIN classes c ON c.id = s.class_id
+             WHERE s.id = ?1",
+            params![student_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let fees = FeeSchedule::for_student(&db, student_id)?;
+
+        let describe = |fee_type: FeeType, month: &Option<String>| match month {
+            Some(month) => format!("{} {}", fee_type.label(), month),
+            None => fee_type.label().to_string(),
+        };
+
+        let mut entries: Vec<LedgerEntry> = InvoiceLine::get_by_student_with(&db, student_id)?
+            .into_iter()
+            .filter(|l| l.charge_date <= as_of)
+            .map(|l| LedgerEntry {
+                date: l.charge_date,
+                description: match &l.discount_note {
+                    Some(note) if l.discount > 0 => {
+                        format!("{} (less {} {})", l.description, l.discount, note)
+                    }
+                    _ => l.description,
+                },
+                fee_type: Some(l.fee_type),
+                month: Some(l.month),
+                charge: l.amount - l.discount,
+                payment: 0,
+                balance: 0,
+                payment_id: None,
+            })
+            .collect();
+        entries.extend(
+            payments
+                .into_iter()
+                .filter(|p| p.payment_date <= as_of)
+                .map(|p| LedgerEntry {
+                    date: p.payment_date,
+                    description: match p.fee_type {
+                        Some(fee_type) => format!(
+                            "Paid {} by {}",
+                            describe(fee_type, &p.month).to_lowercase(),
+                            p.payer_name
+                        ),
+                        None => format!("Paid by {}", p.payer_name),
+                    },
+                    fee_type: p.fee_type,
+                    month: p.month,
+                    charge: 0,
+                    payment: p.amount,
+                    balance: 0,
+                    payment_id: Some(p.id),
+                }),
+        );
+        entries.sort_by_key(|e| (e.date, e.payment_id.is_some()));
+
+        let mut balance = 0;
+        for entry in &mut entries {
+            balance += entry.charge - entry.payment;
+            entry.balance = balance;
+        }
+
+        Ok(Self {
+            student_id,
+            name,
+            class_name,
+            fees,
+            total_charged: entries.iter().map(|e| e.charge).sum(),
+            total_paid: entries.iter().map(|e| e.payment).sum(),
+            balance,
+            entries,
+        })
+    }
+}
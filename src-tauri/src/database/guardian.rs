@@ -1,4 +1,5 @@
 use super::conn;
+use crate::phone;
 use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
 
@@ -77,15 +78,18 @@ impl Guardian {
     }
 
     pub fn search(query: &str) -> Result<Vec<Self>> {
+        let digits = phone::search_digits(query, &phone::default_country());
+
         let db = conn()?;
         let like_query = format!("%{}%", query);
+        let like_digits = digits.map(|d| format!("%{}%", d));
         let mut stmt = db.prepare(
             "SELECT id, name, phone, address, photo 
              FROM guardians 
-             WHERE name LIKE ?1 OR phone LIKE ?1
+             WHERE name LIKE ?1 OR phone LIKE ?1 OR phone LIKE ?2
              LIMIT 15",
         )?;
-        let iter = stmt.query_map(params![like_query], |row| {
+        let iter = stmt.query_map(params![like_query, like_digits], |row| {
             Ok(Guardian {
                 id: row.get(0)?,
                 name: row.get(1)?,
@@ -97,6 +101,32 @@ impl Guardian {
         iter.collect()
     }
 
+    // Rewrites stored numbers to E.164, leaving values that cannot be parsed
+    // or that would collide with another guardian untouched.
+    pub fn normalize_phones(country: &str) -> Result<()> {
+        let db = conn()?;
+        let mut stmt = db.prepare("SELECT id, phone FROM guardians")?;
+        let rows: Vec<(i32, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_>>()?;
+
+        for (id, value) in rows {
+            match phone::normalize(&value, country) {
+                Ok(normalized) if normalized != value => {
+                    if let Err(e) = db.execute(
+                        "UPDATE guardians SET phone = ?1 WHERE id = ?2",
+                        params![normalized, id],
+                    ) {
+                        log::warn!("Guardian {} phone {} not normalized: {}", id, value, e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Guardian {} phone {} not normalized: {}", id, value, e),
+            }
+        }
+        Ok(())
+    }
+
     pub fn delete(id: i32) -> Result<()> {
         let db = conn()?;
         let affected = db.execute("DELETE FROM guardians WHERE id = ?1", params![id])?;
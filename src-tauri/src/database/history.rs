@@ -0,0 +1,92 @@
+use super::conn;
+use chrono::{Local, NaiveDateTime};
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct History {
+    pub id: i32,
+    pub entity: String,
+    pub entity_id: i32,
+    pub action: String,
+    pub details: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl History {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                entity TEXT NOT NULL,
+                entity_id INTEGER NOT NULL,
+                action TEXT NOT NULL,
+                details TEXT,
+                created_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    // Takes the connection so it can be written inside the caller's transaction
+    pub fn record(
+        db: &Connection,
+        entity: &str,
+        entity_id: i32,
+        action: &str,
+        details: Option<String>,
+    ) -> Result<Self> {
+        let created_at = Local::now().naive_local();
+        db.execute(
+            "INSERT INTO history (entity, entity_id, action, details, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![entity, entity_id, action, details, created_at],
+        )?;
+
+        Ok(Self {
+            id: db.last_insert_rowid() as i32,
+            entity: entity.to_string(),
+            entity_id,
+            action: action.to_string(),
+            details,
+            created_at,
+        })
+    }
+
+    pub fn get(entity: Option<String>, entity_id: Option<i32>) -> Result<Vec<Self>> {
+        let db = conn()?;
+
+        let mut query = String::from(
+            "SELECT id, entity, entity_id, action, details, created_at FROM history WHERE 1 = 1",
+        );
+        let mut params_c: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+        if let Some(entity) = entity {
+            query.push_str(" AND entity = ?");
+            params_c.push(Box::new(entity));
+        }
+
+        if let Some(entity_id) = entity_id {
+            query.push_str(" AND entity_id = ?");
+            params_c.push(Box::new(entity_id));
+        }
+
+        query.push_str(" ORDER BY created_at DESC, id DESC");
+
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params_c.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = db.prepare(&query)?;
+        let rows = stmt.query_map(param_refs.as_slice(), |row| {
+            Ok(Self {
+                id: row.get(0)?,
+                entity: row.get(1)?,
+                entity_id: row.get(2)?,
+                action: row.get(3)?,
+                details: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
@@ -0,0 +1,449 @@
+use super::allocation::{allocate_with, settled_on};
+use super::conn;
+use super::fee::{parse_month, FeeType};
+use super::history::History;
+use super::settings::Setting;
+use chrono::{Datelike, Local, NaiveDate};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+// Day the penalties were last brought up to date at startup
+const LATE_FEES_APPLIED_KEY: &str = "late_fees_applied_on";
+
+fn late_fee_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PenaltyKind {
+    // `amount` once the due date has passed
+    Flat,
+    // `amount` for every day past the due date
+    PerDay,
+}
+
+impl PenaltyKind {
+    pub const ALL: [Self; 2] = [Self::Flat, Self::PerDay];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Flat => "FLAT",
+            Self::PerDay => "PER_DAY",
+        }
+    }
+}
+
+impl ToSql for PenaltyKind {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for PenaltyKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == text)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown penalty kind: {}", text).into()))
+    }
+}
+
+// When monthly fees fall due and what paying late costs. A policy without a
+// class is the default for the whole session; a class policy replaces it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LateFeePolicy {
+    pub id: i32,
+    pub session_id: i32,
+    pub class_id: Option<i32>,
+    pub class_name: Option<String>,
+    // Day of the month the fee must be paid by; later months are clamped
+    // to their last day
+    pub due_day: i32,
+    pub kind: PenaltyKind,
+    pub amount: i32,
+    // Most a single month's penalty can reach
+    pub cap: Option<i32>,
+}
+
+const POLICY_SELECT: &str = "SELECT p.id, p.session_id, p.class_id, c.name, p.due_day, p.kind,
+        p.amount, p.cap
+     FROM late_fee_policies p
+     LEFT JOIN classes c ON c.id = p.class_id";
+
+impl LateFeePolicy {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS late_fee_policies (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id INTEGER NOT NULL,
+                class_id INTEGER,
+                due_day INTEGER NOT NULL CHECK (due_day BETWEEN 1 AND 31),
+                kind TEXT NOT NULL CHECK (kind IN ('FLAT', 'PER_DAY')),
+                amount INTEGER NOT NULL CHECK (amount > 0),
+                cap INTEGER,
+                FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE,
+                FOREIGN KEY (class_id) REFERENCES classes(id) ON DELETE CASCADE,
+                UNIQUE (session_id, class_id)
+            )",
+            [],
+        )?;
+        // NULLs never clash in a UNIQUE constraint, so the session default
+        // needs its own index
+        db.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_late_fee_policies_session
+             ON late_fee_policies (session_id) WHERE class_id IS NULL",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            class_id: row.get(2)?,
+            class_name: row.get(3)?,
+            due_day: row.get(4)?,
+            kind: row.get(5)?,
+            amount: row.get(6)?,
+            cap: row.get(7)?,
+        })
+    }
+
+    fn get_with(db: &Connection, id: i32) -> Result<Self> {
+        db.query_row(
+            &format!("{} WHERE p.id = ?1", POLICY_SELECT),
+            params![id],
+            Self::from_row,
+        )
+    }
+
+    pub fn set(
+        session_id: i32,
+        class_id: Option<i32>,
+        due_day: i32,
+        kind: PenaltyKind,
+        amount: i32,
+        cap: Option<i32>,
+    ) -> Result<Self> {
+        if !(1..=31).contains(&due_day) {
+            return Err(late_fee_error("Due day must be between 1 and 31"));
+        }
+        if amount <= 0 {
+            return Err(late_fee_error("Penalty must be positive"));
+        }
+        if cap.is_some_and(|cap| cap < amount) {
+            return Err(late_fee_error("The cap cannot be below the penalty"));
+        }
+
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        if let Some(class_id) = class_id {
+            let same_session: bool = db.query_row(
+                "SELECT session_id = ?1 FROM classes WHERE id = ?2",
+                params![session_id, class_id],
+                |row| row.get(0),
+            )?;
+            if !same_session {
+                return Err(late_fee_error("Class belongs to another session"));
+            }
+        }
+
+        let existing: Option<i32> = db
+            .query_row(
+                "SELECT id FROM late_fee_policies WHERE session_id = ?1 AND class_id IS ?2",
+                params![session_id, class_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let id = match existing {
+            Some(id) => {
+                db.execute(
+                    "UPDATE late_fee_policies SET due_day = ?1, kind = ?2, amount = ?3, cap = ?4
+                     WHERE id = ?5",
+                    params![due_day, kind, amount, cap, id],
+                )?;
+                id
+            }
+            None => {
+                db.execute(
+                    "INSERT INTO late_fee_policies
+                        (session_id, class_id, due_day, kind, amount, cap)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![session_id, class_id, due_day, kind, amount, cap],
+                )?;
+                db.last_insert_rowid() as i32
+            }
+        };
+        Self::get_with(&db, id)
+    }
+
+    pub fn get_all(session_id: i32) -> Result<Vec<Self>> {
+        let db = conn()?;
+        Self::get_all_with(&db, session_id)
+    }
+
+    fn get_all_with(db: &Connection, session_id: i32) -> Result<Vec<Self>> {
+        let mut stmt = db.prepare(&format!(
+            "{} WHERE p.session_id = ?1 ORDER BY p.class_id IS NOT NULL, c.level ASC",
+            POLICY_SELECT
+        ))?;
+        let rows = stmt.query_map(params![session_id], Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn delete(id: i32) -> Result<()> {
+        let db = conn()?;
+        let affected = db.execute("DELETE FROM late_fee_policies WHERE id = ?1", params![id])?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn due_date(&self, month: &str) -> Option<NaiveDate> {
+        let first = parse_month(month)?;
+        let last = first
+            .checked_add_months(chrono::Months::new(1))?
+            .pred_opt()?
+            .day() as i32;
+        first.with_day(self.due_day.min(last) as u32)
+    }
+
+    pub fn penalty(&self, days_late: i64) -> i32 {
+        if days_late <= 0 {
+            return 0;
+        }
+        let penalty = match self.kind {
+            PenaltyKind::Flat => self.amount as i64,
+            PenaltyKind::PerDay => self.amount as i64 * days_late,
+        };
+        match self.cap {
+            Some(cap) => penalty.min(cap as i64) as i32,
+            None => penalty.min(i32::MAX as i64) as i32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LateFeeRun {
+    pub session_id: i32,
+    pub as_of: Option<NaiveDate>,
+    pub created: i32,
+    pub updated: i32,
+    // Penalties standing after the run, waived ones excluded
+    pub amount: i32,
+}
+
+// Brings the penalties of every session up to `today`, once a day
+pub fn apply_daily(today: NaiveDate) -> Result<()> {
+    let today_key = today.format("%Y-%m-%d").to_string();
+    if Setting::get(LATE_FEES_APPLIED_KEY)?.as_deref() == Some(today_key.as_str()) {
+        return Ok(());
+    }
+    let sessions: Vec<i32> = {
+        let db = conn()?;
+        let mut stmt = db.prepare("SELECT DISTINCT session_id FROM late_fee_policies")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect::<Result<_>>()?
+    };
+    for session_id in sessions {
+        apply_late_fees(session_id, today)?;
+    }
+    Setting::set(LATE_FEES_APPLIED_KEY, &today_key)?;
+    Ok(())
+}
+
+// Adds or refreshes a penalty line for every monthly fee of the session paid
+// late or still unpaid after its due date. Days are counted up to the
+// payment that settled the fee, or up to `as_of` while it is owed, so
+// running this again only ever catches up. Waived penalties are left alone.
+pub fn apply_late_fees(session_id: i32, as_of: NaiveDate) -> Result<LateFeeRun> {
+    let mut db = conn()?;
+    db.execute("PRAGMA foreign_keys = ON", [])?;
+    let tx = db.transaction()?;
+
+    let policies = LateFeePolicy::get_all_with(&tx, session_id)?;
+    let mut run = LateFeeRun {
+        session_id,
+        as_of: Some(as_of),
+        ..Default::default()
+    };
+    if policies.is_empty() {
+        return Ok(run);
+    }
+
+    let lines: Vec<(i32, i32, i32, String, i32)> = {
+        let mut stmt = tx.prepare(
+            "SELECT id, student_id, class_id, month, amount - discount FROM invoice_lines
+             WHERE session_id = ?1 AND fee_type = ?2
+             ORDER BY student_id ASC, month ASC",
+        )?;
+        let rows = stmt.query_map(params![session_id, FeeType::Monthly], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })?;
+        rows.collect::<Result<_>>()?
+    };
+    let now = Local::now().naive_local();
+    let mut charged = BTreeSet::new();
+
+    for (line_id, student_id, class_id, month, due) in lines {
+        if due <= 0 {
+            continue;
+        }
+        let Some(policy) = policies
+            .iter()
+            .find(|p| p.class_id == Some(class_id))
+            .or_else(|| policies.iter().find(|p| p.class_id.is_none()))
+        else {
+            continue;
+        };
+        let Some(due_date) = policy.due_date(&month) else {
+            continue;
+        };
+        if due_date >= as_of {
+            continue;
+        }
+
+        // The payment that brought the total up to the fee settled it
+        let settled = settled_on(&tx, line_id, due)?;
+        let until = settled.unwrap_or(as_of).min(as_of);
+        let penalty = policy.penalty((until - due_date).num_days());
+        if penalty <= 0 {
+            continue;
+        }
+
+        let existing: Option<(i32, bool)> = tx
+            .query_row(
+                "SELECT amount, waived_at IS NOT NULL FROM invoice_lines
+                 WHERE student_id = ?1 AND fee_type = ?2 AND month = ?3",
+                params![student_id, FeeType::LateFee, month],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        match existing {
+            Some((_, true)) => continue,
+            Some((amount, false)) if amount >= penalty => {}
+            Some((_, false)) => {
+                tx.execute(
+                    "UPDATE invoice_lines SET amount = ?1
+                     WHERE student_id = ?2 AND fee_type = ?3 AND month = ?4",
+                    params![penalty, student_id, FeeType::LateFee, month],
+                )?;
+                run.updated += 1;
+                charged.insert(student_id);
+            }
+            None => {
+                tx.execute(
+                    "INSERT INTO invoice_lines
+                        (student_id, session_id, class_id, fee_type, month, charge_date,
+                         description, amount, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    params![
+                        student_id,
+                        session_id,
+                        class_id,
+                        FeeType::LateFee,
+                        month,
+                        due_date.succ_opt().unwrap_or(due_date),
+                        format!("{} {}", FeeType::LateFee.label(), month),
+                        penalty,
+                        now
+                    ],
+                )?;
+                run.created += 1;
+                charged.insert(student_id);
+            }
+        }
+    }
+    // Credit held from earlier payments goes to the new penalties
+    for student_id in charged {
+        allocate_with(&tx, student_id)?;
+    }
+
+    run.amount = tx.query_row(
+        "SELECT COALESCE(SUM(amount - discount), 0) FROM invoice_lines
+         WHERE session_id = ?1 AND fee_type = ?2 AND waived_at IS NULL",
+        params![session_id, FeeType::LateFee],
+        |row| row.get(0),
+    )?;
+    tx.commit()?;
+    Ok(run)
+}
+
+// Forgives what is left of a penalty. Anything already paid on it stays
+// paid; the rest is written off as a discount so dues and the ledger agree.
+pub fn waive(invoice_line_id: i32, reason: &str) -> Result<()> {
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err(late_fee_error("A reason is needed to waive a late fee"));
+    }
+
+    let mut db = conn()?;
+    let tx = db.transaction()?;
+    let (fee_type, month, amount, waived): (FeeType, String, i32, bool) = tx.query_row(
+        "SELECT fee_type, month, amount, waived_at IS NOT NULL
+         FROM invoice_lines WHERE id = ?1",
+        params![invoice_line_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+    if fee_type != FeeType::LateFee {
+        return Err(late_fee_error("Only late fees can be waived"));
+    }
+    if waived {
+        return Err(late_fee_error("Late fee is already waived"));
+    }
+    let paid: i32 = tx.query_row(
+        "SELECT COALESCE(SUM(amount), 0) FROM payment_allocations WHERE invoice_line_id = ?1",
+        params![invoice_line_id],
+        |row| row.get(0),
+    )?;
+    if paid >= amount {
+        return Err(late_fee_error("Late fee is already paid"));
+    }
+
+    tx.execute(
+        "UPDATE invoice_lines
+         SET discount = ?1, discount_note = 'Waived', waived_at = ?2, waive_reason = ?3
+         WHERE id = ?4",
+        params![
+            amount - paid,
+            Local::now().naive_local(),
+            reason,
+            invoice_line_id
+        ],
+    )?;
+    History::record(
+        &tx,
+        "invoice_line",
+        invoice_line_id,
+        "waive",
+        Some(format!(
+            "{} {} of {} Tk: {}",
+            FeeType::LateFee.label(),
+            month,
+            amount - paid,
+            reason
+        )),
+    )?;
+    tx.commit()
+}
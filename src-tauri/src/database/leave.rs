@@ -0,0 +1,686 @@
+use super::attendance::{ensure_unlocked, AttendanceStatus};
+use super::calendar::{working_days_in, SchoolCalendar};
+use super::conn;
+use super::history::History;
+use super::shift::apply_timings;
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+// Seeded on first run; more can be added from the UI
+const DEFAULT_LEAVE_TYPES: [(&str, bool); 4] = [
+    ("Casual", true),
+    ("Sick", true),
+    ("Earned", true),
+    ("Maternity", true),
+];
+
+fn leave_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LeaveType {
+    pub id: i32,
+    pub name: String,
+    pub paid: bool,
+}
+
+impl LeaveType {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS leave_types (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                paid BOOLEAN NOT NULL DEFAULT 1
+            )",
+            [],
+        )?;
+
+        let empty: bool =
+            db.query_row("SELECT COUNT(*) = 0 FROM leave_types", [], |row| row.get(0))?;
+        if empty {
+            for (name, paid) in DEFAULT_LEAVE_TYPES {
+                db.execute(
+                    "INSERT INTO leave_types (name, paid) VALUES (?1, ?2)",
+                    params![name, paid],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn create(name: &str, paid: bool) -> Result<Self> {
+        let db = conn()?;
+        db.execute(
+            "INSERT INTO leave_types (name, paid) VALUES (?1, ?2)",
+            params![name.trim(), paid],
+        )?;
+
+        Ok(Self {
+            id: db.last_insert_rowid() as i32,
+            name: name.trim().to_string(),
+            paid,
+        })
+    }
+
+    pub fn get_all() -> Result<Vec<Self>> {
+        let db = conn()?;
+        Self::get_all_with(&db)
+    }
+
+    fn get_all_with(db: &Connection) -> Result<Vec<Self>> {
+        let mut stmt = db.prepare("SELECT id, name, paid FROM leave_types ORDER BY id ASC")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Self {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                paid: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn edit(id: i32, name: &str, paid: bool) -> Result<Self> {
+        let db = conn()?;
+        let affected = db.execute(
+            "UPDATE leave_types SET name = ?1, paid = ?2 WHERE id = ?3",
+            params![name.trim(), paid, id],
+        )?;
+        if affected == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+
+        Ok(Self {
+            id,
+            name: name.trim().to_string(),
+            paid,
+        })
+    }
+
+    // Types already used by an application cannot be removed
+    pub fn delete(id: i32) -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let affected = db.execute("DELETE FROM leave_types WHERE id = ?1", params![id])?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// Days of a leave type allowed per session for staff with a given role
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LeaveEntitlement {
+    pub id: i32,
+    pub leave_type_id: i32,
+    pub leave_type: String,
+    pub role: String,
+    pub days: i32,
+}
+
+impl LeaveEntitlement {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS leave_entitlements (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                leave_type_id INTEGER NOT NULL,
+                role TEXT NOT NULL COLLATE NOCASE,
+                days INTEGER NOT NULL CHECK (days >= 0),
+                FOREIGN KEY (leave_type_id) REFERENCES leave_types(id) ON DELETE CASCADE,
+                UNIQUE (leave_type_id, role)
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    pub fn set(leave_type_id: i32, role: &str, days: i32) -> Result<Self> {
+        let role = role.trim();
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let id: i32 = db.query_row(
+            "INSERT INTO leave_entitlements (leave_type_id, role, days) VALUES (?1, ?2, ?3)
+             ON CONFLICT(leave_type_id, role) DO UPDATE SET days = excluded.days
+             RETURNING id",
+            params![leave_type_id, role, days],
+            |row| row.get(0),
+        )?;
+        let leave_type = db.query_row(
+            "SELECT name FROM leave_types WHERE id = ?1",
+            params![leave_type_id],
+            |row| row.get(0),
+        )?;
+
+        Ok(Self {
+            id,
+            leave_type_id,
+            leave_type,
+            role: role.to_string(),
+            days,
+        })
+    }
+
+    pub fn get_all() -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(
+            "SELECT e.id, e.leave_type_id, t.name, e.role, e.days
+             FROM leave_entitlements e
+             JOIN leave_types t ON e.leave_type_id = t.id
+             ORDER BY e.role ASC, t.id ASC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Self {
+                id: row.get(0)?,
+                leave_type_id: row.get(1)?,
+                leave_type: row.get(2)?,
+                role: row.get(3)?,
+                days: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn delete(id: i32) -> Result<()> {
+        let db = conn()?;
+        let affected = db.execute("DELETE FROM leave_entitlements WHERE id = ?1", params![id])?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LeaveStatus {
+    Pending,
+    Approved,
+    Rejected,
+    Cancelled,
+}
+
+impl LeaveStatus {
+    pub const ALL: [Self; 4] = [
+        Self::Pending,
+        Self::Approved,
+        Self::Rejected,
+        Self::Cancelled,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "PENDING",
+            Self::Approved => "APPROVED",
+            Self::Rejected => "REJECTED",
+            Self::Cancelled => "CANCELLED",
+        }
+    }
+}
+
+impl ToSql for LeaveStatus {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for LeaveStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|s| s.as_str() == text)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown leave status: {}", text).into()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LeaveApplication {
+    pub id: i32,
+    pub staff_id: i32,
+    pub staff_name: String,
+    pub leave_type_id: i32,
+    pub leave_type: String,
+    pub from_date: NaiveDate,
+    pub to_date: NaiveDate,
+    // Working days in the range; holidays and off-days are not charged
+    pub days: i32,
+    pub reason: Option<String>,
+    pub status: LeaveStatus,
+    pub applied_on: NaiveDate,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<NaiveDateTime>,
+    pub decision_note: Option<String>,
+}
+
+const APPLICATION_SELECT: &str = "SELECT a.id, a.staff_id, s.name, a.leave_type_id, t.name,
+        a.from_date, a.to_date, a.days, a.reason, a.status, a.applied_on, a.decided_by,
+        a.decided_at, a.decision_note
+     FROM leave_applications a
+     JOIN staffs s ON a.staff_id = s.id
+     JOIN leave_types t ON a.leave_type_id = t.id";
+
+impl LeaveApplication {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS leave_applications (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                staff_id INTEGER NOT NULL,
+                leave_type_id INTEGER NOT NULL,
+                from_date DATE NOT NULL,
+                to_date DATE NOT NULL,
+                days INTEGER NOT NULL,
+                reason TEXT,
+                status TEXT NOT NULL CHECK (status IN ('PENDING', 'APPROVED', 'REJECTED', 'CANCELLED')),
+                applied_on DATE NOT NULL,
+                decided_by TEXT,
+                decided_at DATETIME,
+                decision_note TEXT,
+                FOREIGN KEY (staff_id) REFERENCES staffs(id) ON DELETE CASCADE,
+                FOREIGN KEY (leave_type_id) REFERENCES leave_types(id),
+                CHECK (to_date >= from_date)
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            staff_id: row.get(1)?,
+            staff_name: row.get(2)?,
+            leave_type_id: row.get(3)?,
+            leave_type: row.get(4)?,
+            from_date: row.get(5)?,
+            to_date: row.get(6)?,
+            days: row.get(7)?,
+            reason: row.get(8)?,
+            status: row.get(9)?,
+            applied_on: row.get(10)?,
+            decided_by: row.get(11)?,
+            decided_at: row.get(12)?,
+            decision_note: row.get(13)?,
+        })
+    }
+
+    fn get_with(db: &Connection, id: i32) -> Result<Self> {
+        db.query_row(
+            &format!("{} WHERE a.id = ?1", APPLICATION_SELECT),
+            params![id],
+            Self::from_row,
+        )
+    }
+
+    pub fn get_by_id(id: i32) -> Result<Self> {
+        let db = conn()?;
+        Self::get_with(&db, id)
+    }
+
+    // Ranges may not overlap another pending or approved application
+    pub fn apply(
+        staff_id: i32,
+        leave_type_id: i32,
+        from_date: NaiveDate,
+        to_date: NaiveDate,
+        reason: Option<String>,
+    ) -> Result<Self> {
+        if to_date < from_date {
+            return Err(leave_error("Leave cannot end before it starts"));
+        }
+        let calendars = SchoolCalendar::load_overlapping(from_date, to_date)?;
+        let days = working_days_in(&calendars, from_date, to_date).len() as i32;
+        if days == 0 {
+            return Err(leave_error("No working days in the selected range"));
+        }
+
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let overlapping: Option<i32> = db
+            .query_row(
+                "SELECT id FROM leave_applications
+                 WHERE staff_id = ?1 AND status IN ('PENDING', 'APPROVED')
+                   AND from_date <= ?3 AND to_date >= ?2",
+                params![staff_id, from_date, to_date],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(other) = overlapping {
+            return Err(leave_error(&format!(
+                "Overlaps leave application #{}",
+                other
+            )));
+        }
+
+        db.execute(
+            "INSERT INTO leave_applications
+                (staff_id, leave_type_id, from_date, to_date, days, reason, status, applied_on)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                staff_id,
+                leave_type_id,
+                from_date,
+                to_date,
+                days,
+                reason,
+                LeaveStatus::Pending,
+                Local::now().naive_local().date()
+            ],
+        )?;
+        let id = db.last_insert_rowid() as i32;
+        Self::get_with(&db, id)
+    }
+
+    pub fn get(
+        staff_id: Option<i32>,
+        status: Option<LeaveStatus>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<Self>> {
+        let db = conn()?;
+
+        let mut query = format!("{} WHERE 1 = 1", APPLICATION_SELECT);
+        let mut params_c: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+        if let Some(staff_id) = staff_id {
+            query.push_str(" AND a.staff_id = ?");
+            params_c.push(Box::new(staff_id));
+        }
+
+        if let Some(status) = status {
+            query.push_str(" AND a.status = ?");
+            params_c.push(Box::new(status));
+        }
+
+        if let Some(from) = from {
+            query.push_str(" AND a.to_date >= ?");
+            params_c.push(Box::new(from));
+        }
+
+        if let Some(to) = to {
+            query.push_str(" AND a.from_date <= ?");
+            params_c.push(Box::new(to));
+        }
+
+        query.push_str(" ORDER BY a.from_date DESC, a.id DESC");
+
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params_c.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = db.prepare(&query)?;
+        let rows = stmt.query_map(param_refs.as_slice(), Self::from_row)?;
+        rows.collect()
+    }
+
+    // Approval is refused when it would take a session's balance below zero.
+    // Every working day of the range is then marked ON_LEAVE in staff
+    // attendance, replacing whatever was recorded.
+    pub fn approve(id: i32, decided_by: Option<String>, note: Option<String>) -> Result<Self> {
+        let application = Self::get_by_id(id)?;
+        if application.status != LeaveStatus::Pending {
+            return Err(leave_error("Only pending applications can be approved"));
+        }
+        let calendars =
+            SchoolCalendar::load_overlapping(application.from_date, application.to_date)?;
+        let days = working_days_in(&calendars, application.from_date, application.to_date);
+
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        let role: String = tx.query_row(
+            "SELECT role FROM staffs WHERE id = ?1",
+            params![application.staff_id],
+            |row| row.get(0),
+        )?;
+        for calendar in &calendars {
+            let balance = session_balances(&tx, calendar, application.staff_id, &role)?
+                .into_iter()
+                .find(|b| b.leave_type_id == application.leave_type_id);
+            let remaining = balance.map_or(0, |b| b.remaining);
+            let needed = days
+                .iter()
+                .filter(|d| calendar.start_date <= **d && **d <= calendar.end_date)
+                .count() as i32;
+            if needed > remaining {
+                return Err(leave_error(&format!(
+                    "Only {} day(s) of {} leave left for this session, {} requested",
+                    remaining.max(0),
+                    application.leave_type,
+                    needed
+                )));
+            }
+        }
+
+        for date in &days {
+            ensure_unlocked(&tx, *date)?;
+            let attendance_id: i32 = tx.query_row(
+                "INSERT INTO attendance_staff (staff_id, date, status, remark)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(staff_id, date) DO UPDATE SET
+                    status = excluded.status,
+                    remark = excluded.remark,
+                    arrival_time = NULL,
+                    departure_time = NULL
+                 RETURNING id",
+                params![
+                    application.staff_id,
+                    date,
+                    AttendanceStatus::OnLeave,
+                    format!("{} leave", application.leave_type)
+                ],
+                |row| row.get(0),
+            )?;
+            apply_timings(&tx, attendance_id)?;
+        }
+
+        let decided = Self::decide(&tx, id, LeaveStatus::Approved, decided_by, note)?;
+        tx.commit()?;
+        Ok(decided)
+    }
+
+    pub fn reject(id: i32, decided_by: Option<String>, note: Option<String>) -> Result<Self> {
+        let mut db = conn()?;
+        let tx = db.transaction()?;
+        if Self::get_with(&tx, id)?.status != LeaveStatus::Pending {
+            return Err(leave_error("Only pending applications can be rejected"));
+        }
+        let decided = Self::decide(&tx, id, LeaveStatus::Rejected, decided_by, note)?;
+        tx.commit()?;
+        Ok(decided)
+    }
+
+    // Cancelling approved leave clears the ON_LEAVE marks it made, so the
+    // days return to the balance and can be marked again.
+    pub fn cancel(id: i32, decided_by: Option<String>, note: Option<String>) -> Result<Self> {
+        let mut db = conn()?;
+        let tx = db.transaction()?;
+        let application = Self::get_with(&tx, id)?;
+        match application.status {
+            LeaveStatus::Pending => {}
+            LeaveStatus::Approved => {
+                let dates: Vec<NaiveDate> = {
+                    let mut stmt = tx.prepare(
+                        "SELECT date FROM attendance_staff
+                         WHERE staff_id = ?1 AND date >= ?2 AND date <= ?3 AND status = ?4",
+                    )?;
+                    let rows = stmt.query_map(
+                        params![
+                            application.staff_id,
+                            application.from_date,
+                            application.to_date,
+                            AttendanceStatus::OnLeave
+                        ],
+                        |row| row.get(0),
+                    )?;
+                    rows.collect::<Result<_>>()?
+                };
+                for date in dates {
+                    ensure_unlocked(&tx, date)?;
+                    tx.execute(
+                        "DELETE FROM attendance_staff WHERE staff_id = ?1 AND date = ?2",
+                        params![application.staff_id, date],
+                    )?;
+                }
+            }
+            _ => return Err(leave_error("Application is already closed")),
+        }
+        let decided = Self::decide(&tx, id, LeaveStatus::Cancelled, decided_by, note)?;
+        tx.commit()?;
+        Ok(decided)
+    }
+
+    fn decide(
+        db: &Connection,
+        id: i32,
+        status: LeaveStatus,
+        decided_by: Option<String>,
+        note: Option<String>,
+    ) -> Result<Self> {
+        db.execute(
+            "UPDATE leave_applications
+             SET status = ?1, decided_by = ?2, decided_at = ?3, decision_note = ?4
+             WHERE id = ?5",
+            params![status, decided_by, Local::now().naive_local(), note, id],
+        )?;
+        let application = Self::get_with(db, id)?;
+        History::record(
+            db,
+            "leave_application",
+            id,
+            status.as_str(),
+            Some(format!(
+                "{} {} leave {} to {}",
+                application.staff_name,
+                application.leave_type,
+                application.from_date,
+                application.to_date
+            )),
+        )?;
+        Ok(application)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LeaveBalance {
+    pub leave_type_id: i32,
+    pub leave_type: String,
+    pub paid: bool,
+    pub entitled: i32,
+    pub taken: i32,
+    // Awaiting a decision; not yet deducted
+    pub pending: i32,
+    pub remaining: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StaffLeaveBalance {
+    pub staff_id: i32,
+    pub staff_name: String,
+    pub role: String,
+    pub session_id: i32,
+    pub balances: Vec<LeaveBalance>,
+}
+
+// Balances of one staff member within a session. Applications crossing the
+// session boundary only count the working days inside it.
+fn session_balances(
+    db: &Connection,
+    calendar: &SchoolCalendar,
+    staff_id: i32,
+    role: &str,
+) -> Result<Vec<LeaveBalance>> {
+    let applications: Vec<(i32, NaiveDate, NaiveDate, LeaveStatus)> = {
+        let mut stmt = db.prepare(
+            "SELECT leave_type_id, from_date, to_date, status FROM leave_applications
+             WHERE staff_id = ?1 AND status IN ('PENDING', 'APPROVED')
+               AND from_date <= ?3 AND to_date >= ?2",
+        )?;
+        let rows = stmt.query_map(
+            params![staff_id, calendar.start_date, calendar.end_date],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        rows.collect::<Result<_>>()?
+    };
+
+    let mut entitlement =
+        db.prepare("SELECT days FROM leave_entitlements WHERE leave_type_id = ?1 AND role = ?2")?;
+
+    LeaveType::get_all_with(db)?
+        .into_iter()
+        .map(|leave_type| {
+            let entitled: i32 = entitlement
+                .query_row(params![leave_type.id, role.trim()], |row| row.get(0))
+                .optional()?
+                .unwrap_or(0);
+
+            let (mut taken, mut pending) = (0, 0);
+            for (type_id, from, to, status) in &applications {
+                if *type_id != leave_type.id {
+                    continue;
+                }
+                let days = calendar
+                    .working_days(
+                        (*from).max(calendar.start_date),
+                        (*to).min(calendar.end_date),
+                    )
+                    .len() as i32;
+                match status {
+                    LeaveStatus::Approved => taken += days,
+                    _ => pending += days,
+                }
+            }
+
+            Ok(LeaveBalance {
+                leave_type_id: leave_type.id,
+                leave_type: leave_type.name,
+                paid: leave_type.paid,
+                entitled,
+                taken,
+                pending,
+                remaining: entitled - taken,
+            })
+        })
+        .collect()
+}
+
+pub fn balances(session_id: i32, staff_id: Option<i32>) -> Result<Vec<StaffLeaveBalance>> {
+    let calendar = SchoolCalendar::load(session_id)?;
+
+    let db = conn()?;
+    let staff: Vec<(i32, String, String)> = {
+        let mut stmt = db.prepare(
+            "SELECT id, name, role FROM staffs WHERE (?1 IS NULL OR id = ?1) ORDER BY name ASC",
+        )?;
+        let rows = stmt.query_map(params![staff_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        rows.collect::<Result<_>>()?
+    };
+
+    staff
+        .into_iter()
+        .map(|(staff_id, staff_name, role)| {
+            Ok(StaffLeaveBalance {
+                balances: session_balances(&db, &calendar, staff_id, &role)?,
+                staff_id,
+                staff_name,
+                role,
+                session_id,
+            })
+        })
+        .collect()
+}
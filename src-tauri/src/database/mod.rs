@@ -1,17 +1,60 @@
+pub mod advance;
+pub mod allocation;
+pub mod analytics;
+pub mod attendance;
+pub mod billing;
+pub mod biometric;
+pub mod calendar;
+pub mod card;
 pub mod class;
+pub mod discount;
+pub mod duplicate;
+pub mod family;
+pub mod fee;
 pub mod guardian;
+pub mod history;
+pub mod late_fee;
+pub mod leave;
+pub mod payroll;
+pub mod receipt;
+pub mod reconciliation;
+pub mod register;
+pub mod residency;
+pub mod routine;
 pub mod session;
+pub mod settings;
+pub mod shift;
 pub mod staff;
 pub mod student;
 pub mod subject;
 
+use chrono::Local;
 use rusqlite::{Connection, Result};
 use std::sync::{Mutex, MutexGuard, OnceLock};
 use tauri::{App, Manager};
 
+use self::advance::SalaryAdvance;
+use self::allocation::Allocation;
+use self::attendance::AttendanceOverride;
+use self::billing::InvoiceLine;
+use self::biometric::DeviceMapping;
+use self::calendar::CalendarEvent;
 use self::class::{Class, Section};
+use self::discount::{DiscountGrant, DiscountRule};
+use self::fee::{Payment, StudentFeeOverride};
 use self::guardian::{Guardian, StudentRelationship};
+use self::history::History;
+use self::late_fee::LateFeePolicy;
+use self::leave::{LeaveApplication, LeaveEntitlement, LeaveType};
+use self::payroll::{SalaryDeduction, SalaryPayment, SalaryRevision};
+use self::receipt::Receipt;
+use self::reconciliation::{CashDeposit, StatementLine};
+use self::residency::ResidentPeriod;
+use self::routine::{ClassRoutine, PeriodAttendance};
 use self::session::Session;
+use self::settings::Setting;
+use self::shift::Shift;
+use self::staff::Staff;
 use self::student::{Attendance, Student};
 use self::subject::{ClassSubject, Subject};
 
@@ -28,6 +71,30 @@ pub fn conn() -> Result<MutexGuard<'static, Connection>, rusqlite::Error> {
     Ok(conn)
 }
 
+// For columns added after a table was first released
+pub fn add_column_if_missing(
+    db: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let exists: bool = db.query_row(
+        &format!(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('{}') WHERE name = ?1",
+            table
+        ),
+        [column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        db.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
 pub fn setup(app: &App) {
     let path = get_db_path(app.path());
     let connection = Connection::open(path).expect("Failed to open database");
@@ -37,6 +104,7 @@ pub fn setup(app: &App) {
 }
 
 pub fn init() -> Result<()> {
+    Setting::init()?;
     Session::init()?;
     Subject::init()?;
     ClassSubject::init()?;
@@ -47,5 +115,38 @@ pub fn init() -> Result<()> {
     StudentRelationship::init()?;
     Attendance::init()?;
     staff::init_all()?;
+    DeviceMapping::init()?;
+    Shift::init()?;
+    LeaveType::init()?;
+    LeaveEntitlement::init()?;
+    LeaveApplication::init()?;
+    History::init()?;
+    AttendanceOverride::init()?;
+    CalendarEvent::init()?;
+    ClassRoutine::init()?;
+    PeriodAttendance::init()?;
+    card::init()?;
+    StudentFeeOverride::init()?;
+    ResidentPeriod::init()?;
+    Payment::init()?;
+    DiscountRule::init()?;
+    DiscountGrant::init()?;
+    InvoiceLine::init()?;
+    Receipt::init()?;
+    Allocation::init()?;
+    StatementLine::init()?;
+    CashDeposit::init()?;
+    LateFeePolicy::init()?;
+    SalaryRevision::init()?;
+    SalaryDeduction::init()?;
+    SalaryPayment::init()?;
+    SalaryAdvance::init()?;
+
+    let country = crate::phone::default_country();
+    Guardian::normalize_phones(&country)?;
+    Staff::normalize_phones(&country)?;
+    Student::normalize_phones(&country)?;
+
+    late_fee::apply_daily(Local::now().date_naive())?;
     Ok(())
 }
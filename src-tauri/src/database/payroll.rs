@@ -0,0 +1,1097 @@
+use super::advance::{due_with, recover_with};
+use super::attendance::AttendanceStatus;
+use super::conn;
+use super::fee::{month_key, parse_month, PaymentMethod};
+use super::history::History;
+use super::leave::LeaveStatus;
+use super::receipt::{
+    grey, school_header, table_row, RECEIPT_HEIGHT, RECEIPT_MARGIN, RECEIPT_ROW, RECEIPT_WIDTH,
+};
+use crate::report::{fit, line};
+use crate::words::taka_in_english;
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use printpdf::{BuiltinFont, Mm, PdfDocument};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+fn payroll_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+// First and last day of a "YYYY-MM" month
+fn month_bounds(month: &str) -> Result<(NaiveDate, NaiveDate)> {
+    let first = parse_month(month).ok_or(rusqlite::Error::InvalidQuery)?;
+    let last = first
+        .checked_add_months(chrono::Months::new(1))
+        .and_then(|d| d.pred_opt())
+        .ok_or(rusqlite::Error::InvalidQuery)?;
+    Ok((first, last))
+}
+
+// `amount` scaled by part / whole, to the nearest taka
+fn share(amount: i32, part: i64, whole: i64) -> i32 {
+    ((amount as i64 * part + whole / 2) / whole) as i32
+}
+
+pub(super) fn has_payroll(db: &Connection, staff_id: i32, from_month: &str) -> Result<bool> {
+    db.query_row(
+        "SELECT COUNT(*) > 0 FROM salary_payments WHERE staff_id = ?1 AND month >= ?2",
+        params![staff_id, from_month],
+        |row| row.get(0),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SalaryComponent {
+    pub name: String,
+    pub amount: i32,
+}
+
+// A staff member's pay from `effective_from` until the next revision. The
+// gross is kept on `staffs.salary` for the revision in effect today.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SalaryRevision {
+    pub id: i32,
+    pub staff_id: i32,
+    pub effective_from: NaiveDate,
+    pub basic: i32,
+    pub allowances: Vec<SalaryComponent>,
+    // Share of basic withheld for the provident fund, in percent
+    pub provident_fund_percent: i32,
+    pub gross: i32,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+const REVISION_COLUMNS: &str =
+    "id, staff_id, effective_from, basic, provident_fund_percent, note, created_at";
+
+impl SalaryRevision {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS salary_revisions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                staff_id INTEGER NOT NULL,
+                effective_from DATE NOT NULL,
+                basic INTEGER NOT NULL CHECK (basic >= 0),
+                provident_fund_percent INTEGER NOT NULL DEFAULT 0
+                    CHECK (provident_fund_percent BETWEEN 0 AND 100),
+                note TEXT,
+                created_at DATETIME NOT NULL,
+                FOREIGN KEY (staff_id) REFERENCES staffs(id) ON DELETE CASCADE,
+                UNIQUE (staff_id, effective_from)
+            )",
+            [],
+        )?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS salary_allowances (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                revision_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                amount INTEGER NOT NULL CHECK (amount > 0),
+                FOREIGN KEY (revision_id) REFERENCES salary_revisions(id) ON DELETE CASCADE,
+                UNIQUE (revision_id, name)
+            )",
+            [],
+        )?;
+        // Staff added before revisions were kept have been paid their salary
+        // as basic since they joined
+        db.execute(
+            "INSERT INTO salary_revisions (staff_id, effective_from, basic, created_at)
+             SELECT s.id, s.hire_date, s.salary, ?1 FROM staffs s
+             WHERE NOT EXISTS (SELECT 1 FROM salary_revisions r WHERE r.staff_id = s.id)",
+            params![Local::now().naive_local()],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(db: &Connection, row: &rusqlite::Row) -> Result<Self> {
+        let id: i32 = row.get(0)?;
+        let basic: i32 = row.get(3)?;
+        let allowances = Self::allowances_with(db, id)?;
+        Ok(Self {
+            id,
+            staff_id: row.get(1)?,
+            effective_from: row.get(2)?,
+            basic,
+            gross: basic + allowances.iter().map(|a| a.amount).sum::<i32>(),
+            allowances,
+            provident_fund_percent: row.get(4)?,
+            note: row.get(5)?,
+            created_at: row.get(6)?,
+        })
+    }
+
+    fn allowances_with(db: &Connection, revision_id: i32) -> Result<Vec<SalaryComponent>> {
+        let mut stmt = db.prepare(
+            "SELECT name, amount FROM salary_allowances WHERE revision_id = ?1 ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map(params![revision_id], |row| {
+            Ok(SalaryComponent {
+                name: row.get(0)?,
+                amount: row.get(1)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    fn get_with(db: &Connection, id: i32) -> Result<Self> {
+        db.query_row(
+            &format!(
+                "SELECT {} FROM salary_revisions WHERE id = ?1",
+                REVISION_COLUMNS
+            ),
+            params![id],
+            |row| Self::from_row(db, row),
+        )
+    }
+
+    // Newest first
+    pub fn get(staff_id: i32) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(&format!(
+            "SELECT {} FROM salary_revisions WHERE staff_id = ?1 ORDER BY effective_from DESC",
+            REVISION_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![staff_id], |row| Self::from_row(&db, row))?;
+        rows.collect()
+    }
+
+    pub(super) fn in_effect_with(
+        db: &Connection,
+        staff_id: i32,
+        date: NaiveDate,
+    ) -> Result<Option<Self>> {
+        db.query_row(
+            &format!(
+                "SELECT {} FROM salary_revisions
+                 WHERE staff_id = ?1 AND effective_from <= ?2
+                 ORDER BY effective_from DESC LIMIT 1",
+                REVISION_COLUMNS
+            ),
+            params![staff_id, date],
+            |row| Self::from_row(db, row),
+        )
+        .optional()
+    }
+
+    fn insert_with(
+        db: &Connection,
+        staff_id: i32,
+        effective_from: NaiveDate,
+        basic: i32,
+        allowances: &[SalaryComponent],
+        provident_fund_percent: i32,
+        note: Option<String>,
+    ) -> Result<i32> {
+        let id: i32 = db.query_row(
+            "INSERT INTO salary_revisions
+                (staff_id, effective_from, basic, provident_fund_percent, note, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             RETURNING id",
+            params![
+                staff_id,
+                effective_from,
+                basic,
+                provident_fund_percent,
+                note,
+                Local::now().naive_local()
+            ],
+            |row| row.get(0),
+        )?;
+        for allowance in allowances {
+            db.execute(
+                "INSERT INTO salary_allowances (revision_id, name, amount) VALUES (?1, ?2, ?3)",
+                params![id, allowance.name, allowance.amount],
+            )?;
+        }
+        Ok(id)
+    }
+
+    // Keeps `staffs.salary` at today's gross, for the lists that show it
+    fn sync_salary(db: &Connection, staff_id: i32) -> Result<()> {
+        let today = Local::now().date_naive();
+        if let Some(current) = Self::in_effect_with(db, staff_id, today)? {
+            db.execute(
+                "UPDATE staffs SET salary = ?1 WHERE id = ?2",
+                params![current.gross, staff_id],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn create(
+        staff_id: i32,
+        effective_from: NaiveDate,
+        basic: i32,
+        mut allowances: Vec<SalaryComponent>,
+        provident_fund_percent: i32,
+        note: Option<String>,
+    ) -> Result<Self> {
+        for allowance in &mut allowances {
+            allowance.name = allowance.name.trim().to_string();
+            if allowance.name.is_empty() {
+                return Err(payroll_error("Every allowance needs a name"));
+            }
+            if allowance.amount <= 0 {
+                return Err(payroll_error(&format!(
+                    "Allowance {} must be more than zero",
+                    allowance.name
+                )));
+            }
+        }
+        if !(0..=100).contains(&provident_fund_percent) {
+            return Err(payroll_error(
+                "Provident fund must be between 0 and 100 percent",
+            ));
+        }
+        let note = note.filter(|n| !n.trim().is_empty());
+
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        let hire_date: NaiveDate = tx.query_row(
+            "SELECT hire_date FROM staffs WHERE id = ?1",
+            params![staff_id],
+            |row| row.get(0),
+        )?;
+        if effective_from < hire_date {
+            return Err(payroll_error(&format!(
+                "A revision cannot take effect before joining on {}",
+                hire_date
+            )));
+        }
+        if has_payroll(&tx, staff_id, &month_key(effective_from))? {
+            return Err(payroll_error(&format!(
+                "Salary has already been paid for {} or later",
+                month_key(effective_from)
+            )));
+        }
+        let taken: bool = tx.query_row(
+            "SELECT COUNT(*) > 0 FROM salary_revisions WHERE staff_id = ?1 AND effective_from = ?2",
+            params![staff_id, effective_from],
+            |row| row.get(0),
+        )?;
+        if taken {
+            return Err(payroll_error(&format!(
+                "A revision already takes effect on {}",
+                effective_from
+            )));
+        }
+
+        let id = Self::insert_with(
+            &tx,
+            staff_id,
+            effective_from,
+            basic,
+            &allowances,
+            provident_fund_percent,
+            note,
+        )?;
+        Self::sync_salary(&tx, staff_id)?;
+        let revision = Self::get_with(&tx, id)?;
+        History::record(
+            &tx,
+            "staff",
+            staff_id,
+            "salary_revision",
+            Some(format!(
+                "Gross {} Tk from {}",
+                revision.gross, revision.effective_from
+            )),
+        )?;
+
+        tx.commit()?;
+        Ok(revision)
+    }
+
+    // For the salary on the staff form. A new member starts on it as basic;
+    // a changed salary becomes a revision from `date` with the allowances and
+    // provident fund of the one it replaces, the basic taking the difference.
+    pub(super) fn from_form(
+        db: &Connection,
+        staff_id: i32,
+        salary: i32,
+        date: NaiveDate,
+    ) -> Result<()> {
+        let Some(current) = Self::in_effect_with(db, staff_id, date)? else {
+            Self::insert_with(db, staff_id, date, salary, &[], 0, None)?;
+            return Ok(());
+        };
+        if current.gross == salary {
+            return Ok(());
+        }
+        let allowances: i32 = current.allowances.iter().map(|a| a.amount).sum();
+        if salary < allowances {
+            return Err(payroll_error(&format!(
+                "Salary is less than the {} Tk of allowances; revise the salary structure instead",
+                allowances
+            )));
+        }
+        if has_payroll(db, staff_id, &month_key(date))? {
+            return Err(payroll_error(&format!(
+                "Salary has already been paid for {} or later",
+                month_key(date)
+            )));
+        }
+        if current.effective_from == date {
+            db.execute(
+                "UPDATE salary_revisions SET basic = ?1 WHERE id = ?2",
+                params![salary - allowances, current.id],
+            )?;
+        } else {
+            Self::insert_with(
+                db,
+                staff_id,
+                date,
+                salary - allowances,
+                &current.allowances,
+                current.provident_fund_percent,
+                None,
+            )?;
+        }
+        Self::sync_salary(db, staff_id)
+    }
+
+    // Revisions already paid on are kept
+    pub fn delete(id: i32) -> Result<()> {
+        let mut db = conn()?;
+        let tx = db.transaction()?;
+        let revision = Self::get_with(&tx, id)?;
+        let used: bool = tx.query_row(
+            "SELECT COUNT(*) > 0 FROM salary_payments WHERE revision_id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+        if used {
+            return Err(payroll_error(
+                "Salary has already been paid on this revision",
+            ));
+        }
+        tx.execute("DELETE FROM salary_revisions WHERE id = ?1", params![id])?;
+        Self::sync_salary(&tx, revision.staff_id)?;
+        History::record(
+            &tx,
+            "staff",
+            revision.staff_id,
+            "salary_revision_delete",
+            Some(format!(
+                "Gross {} Tk from {}",
+                revision.gross, revision.effective_from
+            )),
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+// A one-off deduction for a month's payroll, such as a fine. Taken off when
+// the month is run.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SalaryDeduction {
+    pub id: i32,
+    pub staff_id: i32,
+    pub staff_name: String,
+    pub month: String,
+    pub name: String,
+    pub amount: i32,
+    pub note: Option<String>,
+}
+
+const DEDUCTION_SELECT: &str = "SELECT d.id, d.staff_id, s.name, d.month, d.name, d.amount, d.note
+     FROM salary_deductions d JOIN staffs s ON s.id = d.staff_id";
+
+impl SalaryDeduction {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS salary_deductions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                staff_id INTEGER NOT NULL,
+                month TEXT NOT NULL,
+                name TEXT NOT NULL,
+                amount INTEGER NOT NULL CHECK (amount > 0),
+                note TEXT,
+                FOREIGN KEY (staff_id) REFERENCES staffs(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            staff_id: row.get(1)?,
+            staff_name: row.get(2)?,
+            month: row.get(3)?,
+            name: row.get(4)?,
+            amount: row.get(5)?,
+            note: row.get(6)?,
+        })
+    }
+
+    fn for_month_with(db: &Connection, staff_id: i32, month: &str) -> Result<Vec<Self>> {
+        let mut stmt = db.prepare(&format!(
+            "{} WHERE d.staff_id = ?1 AND d.month = ?2 ORDER BY d.id ASC",
+            DEDUCTION_SELECT
+        ))?;
+        let rows = stmt.query_map(params![staff_id, month], Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn create(
+        staff_id: i32,
+        month: &str,
+        name: &str,
+        amount: i32,
+        note: Option<String>,
+    ) -> Result<Self> {
+        let (first, _) = month_bounds(month)?;
+        let month = month_key(first);
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(payroll_error("A deduction needs a name"));
+        }
+        if amount <= 0 {
+            return Err(payroll_error("A deduction must be more than zero"));
+        }
+        let note = note.filter(|n| !n.trim().is_empty());
+
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        if has_payroll(&db, staff_id, &month)? {
+            return Err(payroll_error(&format!(
+                "Salary has already been paid for {} or later",
+                month
+            )));
+        }
+        let id: i32 = db.query_row(
+            "INSERT INTO salary_deductions (staff_id, month, name, amount, note)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             RETURNING id",
+            params![staff_id, month, name, amount, note],
+            |row| row.get(0),
+        )?;
+        db.query_row(
+            &format!("{} WHERE d.id = ?1", DEDUCTION_SELECT),
+            params![id],
+            Self::from_row,
+        )
+    }
+
+    pub fn get(month: Option<String>, staff_id: Option<i32>) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(&format!(
+            "{} WHERE (?1 IS NULL OR d.month = ?1) AND (?2 IS NULL OR d.staff_id = ?2)
+             ORDER BY d.month DESC, s.name ASC",
+            DEDUCTION_SELECT
+        ))?;
+        let rows = stmt.query_map(params![month, staff_id], Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn delete(id: i32) -> Result<()> {
+        let db = conn()?;
+        let (staff_id, month): (i32, String) = db.query_row(
+            "SELECT staff_id, month FROM salary_deductions WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        if has_payroll(&db, staff_id, &month)? {
+            return Err(payroll_error(&format!(
+                "Salary has already been paid for {}",
+                month
+            )));
+        }
+        db.execute("DELETE FROM salary_deductions WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PayLineKind {
+    Earning,
+    Deduction,
+}
+
+impl PayLineKind {
+    pub const ALL: [Self; 2] = [Self::Earning, Self::Deduction];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Earning => "EARNING",
+            Self::Deduction => "DEDUCTION",
+        }
+    }
+}
+
+impl ToSql for PayLineKind {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for PayLineKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == text)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown pay line: {}", text).into()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PayslipLine {
+    pub kind: PayLineKind,
+    pub name: String,
+    pub amount: i32,
+}
+
+// A month's salary paid to a staff member, itemised as on the payslip. A
+// staff member is paid at most once a month, which makes re-running a
+// month safe.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SalaryPayment {
+    pub id: i32,
+    pub staff_id: i32,
+    pub staff_name: String,
+    pub role: String,
+    pub month: String,
+    pub revision_id: i32,
+    pub payment_date: NaiveDate,
+    pub method: PaymentMethod,
+    pub gross: i32,
+    pub deductions: i32,
+    pub net: i32,
+    pub net_in_words: String,
+    pub lines: Vec<PayslipLine>,
+    pub remark: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+const PAYMENT_SELECT: &str = "SELECT p.id, p.staff_id, s.name, s.role, p.month, p.revision_id,
+            p.payment_date, p.method, p.gross, p.deductions, p.remark, p.created_at
+     FROM salary_payments p JOIN staffs s ON s.id = p.staff_id";
+
+impl SalaryPayment {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS salary_payments (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    staff_id INTEGER NOT NULL,
+                    month TEXT NOT NULL,
+                    revision_id INTEGER NOT NULL,
+                    payment_date DATE NOT NULL,
+                    method TEXT NOT NULL {},
+                    gross INTEGER NOT NULL CHECK (gross >= 0),
+                    deductions INTEGER NOT NULL CHECK (deductions >= 0),
+                    remark TEXT,
+                    created_at DATETIME NOT NULL,
+                    FOREIGN KEY (staff_id) REFERENCES staffs(id),
+                    FOREIGN KEY (revision_id) REFERENCES salary_revisions(id),
+                    UNIQUE (staff_id, month),
+                    CHECK (deductions <= gross)
+                )",
+                PaymentMethod::check_constraint("method")
+            ),
+            [],
+        )?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS salary_payment_lines (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                salary_payment_id INTEGER NOT NULL,
+                kind TEXT NOT NULL CHECK (kind IN ('EARNING', 'DEDUCTION')),
+                name TEXT NOT NULL,
+                amount INTEGER NOT NULL CHECK (amount > 0),
+                FOREIGN KEY (salary_payment_id) REFERENCES salary_payments(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(db: &Connection, row: &rusqlite::Row) -> Result<Self> {
+        let id: i32 = row.get(0)?;
+        let gross: i32 = row.get(8)?;
+        let deductions: i32 = row.get(9)?;
+        let lines = {
+            let mut stmt = db.prepare(
+                "SELECT kind, name, amount FROM salary_payment_lines
+                 WHERE salary_payment_id = ?1 ORDER BY id ASC",
+            )?;
+            let rows = stmt.query_map(params![id], |row| {
+                Ok(PayslipLine {
+                    kind: row.get(0)?,
+                    name: row.get(1)?,
+                    amount: row.get(2)?,
+                })
+            })?;
+            rows.collect::<Result<Vec<_>>>()?
+        };
+        Ok(Self {
+            id,
+            staff_id: row.get(1)?,
+            staff_name: row.get(2)?,
+            role: row.get(3)?,
+            month: row.get(4)?,
+            revision_id: row.get(5)?,
+            payment_date: row.get(6)?,
+            method: row.get(7)?,
+            gross,
+            deductions,
+            net: gross - deductions,
+            net_in_words: taka_in_english(gross - deductions),
+            lines,
+            remark: row.get(10)?,
+            created_at: row.get(11)?,
+        })
+    }
+
+    fn get_with(db: &Connection, id: i32) -> Result<Self> {
+        db.query_row(
+            &format!("{} WHERE p.id = ?1", PAYMENT_SELECT),
+            params![id],
+            |row| Self::from_row(db, row),
+        )
+    }
+
+    pub fn get(id: i32) -> Result<Self> {
+        let db = conn()?;
+        Self::get_with(&db, id)
+    }
+
+    pub fn get_all(month: Option<String>, staff_id: Option<i32>) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(&format!(
+            "{} WHERE (?1 IS NULL OR p.month = ?1) AND (?2 IS NULL OR p.staff_id = ?2)
+             ORDER BY p.month DESC, s.name ASC",
+            PAYMENT_SELECT
+        ))?;
+        let rows = stmt.query_map(params![month, staff_id], |row| Self::from_row(&db, row))?;
+        rows.collect()
+    }
+
+    // For a payment made in error; running the month again pays it afresh.
+    // Advance installments it recovered fall due again.
+    pub fn delete(id: i32) -> Result<()> {
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+        let payment = Self::get_with(&tx, id)?;
+        tx.execute("DELETE FROM salary_payments WHERE id = ?1", params![id])?;
+        History::record(
+            &tx,
+            "staff",
+            payment.staff_id,
+            "salary_payment_delete",
+            Some(format!(
+                "{} net {} Tk paid {}",
+                payment.month, payment.net, payment.payment_date
+            )),
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+// Half days of [from, to] the staff member is not paid for: absences, half
+// days, and approved leave of an unpaid type
+fn unpaid_half_days(db: &Connection, staff_id: i32, from: NaiveDate, to: NaiveDate) -> Result<i64> {
+    db.query_row(
+        "SELECT COALESCE(SUM(CASE
+                    WHEN a.status = ?4 THEN 2
+                    WHEN a.status = ?5 THEN 1
+                    WHEN a.status = ?6 AND EXISTS (
+                        SELECT 1 FROM leave_applications l
+                        JOIN leave_types t ON t.id = l.leave_type_id
+                        WHERE l.staff_id = a.staff_id AND l.status = ?7 AND NOT t.paid
+                          AND l.from_date <= a.date AND l.to_date >= a.date
+                    ) THEN 2
+                    ELSE 0
+                END), 0)
+         FROM attendance_staff a
+         WHERE a.staff_id = ?1 AND a.date >= ?2 AND a.date <= ?3",
+        params![
+            staff_id,
+            from,
+            to,
+            AttendanceStatus::Absent,
+            AttendanceStatus::HalfDay,
+            AttendanceStatus::OnLeave,
+            LeaveStatus::Approved
+        ],
+        |row| row.get(0),
+    )
+}
+
+// Day counts on payslips, with halves where there are any
+fn days_label(half_days: i64) -> String {
+    if half_days % 2 == 0 {
+        format!("{}", half_days / 2)
+    } else {
+        format!("{}.5", half_days / 2)
+    }
+}
+
+// A month's payslip before it is written
+struct DraftPayslip {
+    lines: Vec<PayslipLine>,
+    // Advance id and the amount recovered from it
+    recoveries: Vec<(i32, i32)>,
+}
+
+// What a staff member earns for the month and what is taken off. Deductions
+// are taken in order and stop at the gross.
+fn draft_payslip(
+    db: &Connection,
+    staff_id: i32,
+    hire_date: NaiveDate,
+    revision: &SalaryRevision,
+    month: &str,
+    first: NaiveDate,
+    last: NaiveDate,
+) -> Result<DraftPayslip> {
+    let mut lines = vec![PayslipLine {
+        kind: PayLineKind::Earning,
+        name: "Basic".to_string(),
+        amount: revision.basic,
+    }];
+    for allowance in &revision.allowances {
+        lines.push(PayslipLine {
+            kind: PayLineKind::Earning,
+            name: allowance.name.clone(),
+            amount: allowance.amount,
+        });
+    }
+
+    let gross = revision.gross;
+    let days_in_month = (last - first).num_days() + 1;
+    // Name, amount, and the advance it recovers
+    let mut deductions: Vec<(String, i32, Option<i32>)> = Vec::new();
+    let start = hire_date.max(first);
+    if start > first {
+        let days = (start - first).num_days();
+        deductions.push((
+            format!("Before joining ({} days)", days),
+            share(gross, days, days_in_month),
+            None,
+        ));
+    }
+    let half_days = unpaid_half_days(db, staff_id, start, last)?;
+    if half_days > 0 {
+        deductions.push((
+            format!("Unpaid leave ({} days)", days_label(half_days)),
+            share(gross, half_days, 2 * days_in_month),
+            None,
+        ));
+    }
+    if revision.provident_fund_percent > 0 {
+        deductions.push((
+            format!("Provident fund ({}%)", revision.provident_fund_percent),
+            share(revision.basic, revision.provident_fund_percent as i64, 100),
+            None,
+        ));
+    }
+    for due in due_with(db, staff_id, month)? {
+        deductions.push((
+            format!("Advance of {}", due.given_on),
+            due.amount,
+            Some(due.advance_id),
+        ));
+    }
+    for deduction in SalaryDeduction::for_month_with(db, staff_id, month)? {
+        deductions.push((deduction.name, deduction.amount, None));
+    }
+
+    let mut recoveries = Vec::new();
+    let mut left = gross;
+    for (name, amount, advance_id) in deductions {
+        let amount = amount.min(left);
+        if amount > 0 {
+            left -= amount;
+            lines.push(PayslipLine {
+                kind: PayLineKind::Deduction,
+                name,
+                amount,
+            });
+            if let Some(advance_id) = advance_id {
+                recoveries.push((advance_id, amount));
+            }
+        }
+    }
+    Ok(DraftPayslip { lines, recoveries })
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PayrollRun {
+    pub month: String,
+    pub staff: i32,
+    // Payments made by this run
+    pub created: i32,
+    pub gross: i32,
+    pub deductions: i32,
+    pub net: i32,
+    // Staff already paid for the month
+    pub existing: i32,
+    // Staff with no salary in effect
+    pub skipped: i32,
+}
+
+// Pays every staff member who had joined by the end of `month` on the
+// salary revision in effect on its last day. Days before joining, unpaid
+// leave, provident fund, advance installments due and the month's
+// deductions are taken off.
+pub fn run_payroll(
+    month: &str,
+    payment_date: NaiveDate,
+    method: PaymentMethod,
+    remark: Option<String>,
+) -> Result<PayrollRun> {
+    let (first, last) = month_bounds(month)?;
+    let month = month_key(first);
+    let remark = remark.filter(|r| !r.trim().is_empty());
+
+    let mut db = conn()?;
+    db.execute("PRAGMA foreign_keys = ON", [])?;
+    let tx = db.transaction()?;
+
+    let staff: Vec<(i32, NaiveDate)> = {
+        let mut stmt =
+            tx.prepare("SELECT id, hire_date FROM staffs WHERE hire_date <= ?1 ORDER BY name ASC")?;
+        let rows = stmt.query_map(params![last], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<_>>()?
+    };
+
+    let mut run = PayrollRun {
+        month: month.clone(),
+        staff: staff.len() as i32,
+        ..Default::default()
+    };
+    let now = Local::now().naive_local();
+
+    for (staff_id, hire_date) in staff {
+        let paid: bool = tx.query_row(
+            "SELECT COUNT(*) > 0 FROM salary_payments WHERE staff_id = ?1 AND month = ?2",
+            params![staff_id, month],
+            |row| row.get(0),
+        )?;
+        if paid {
+            run.existing += 1;
+            continue;
+        }
+        let Some(revision) = SalaryRevision::in_effect_with(&tx, staff_id, last)? else {
+            run.skipped += 1;
+            continue;
+        };
+
+        let DraftPayslip { lines, recoveries } =
+            draft_payslip(&tx, staff_id, hire_date, &revision, &month, first, last)?;
+        let deductions: i32 = lines
+            .iter()
+            .filter(|l| l.kind == PayLineKind::Deduction)
+            .map(|l| l.amount)
+            .sum();
+        let id: i32 = tx.query_row(
+            "INSERT INTO salary_payments
+                (staff_id, month, revision_id, payment_date, method, gross, deductions, remark,
+                 created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+             RETURNING id",
+            params![
+                staff_id,
+                month,
+                revision.id,
+                payment_date,
+                method,
+                revision.gross,
+                deductions,
+                remark,
+                now
+            ],
+            |row| row.get(0),
+        )?;
+        for line in &lines {
+            tx.execute(
+                "INSERT INTO salary_payment_lines (salary_payment_id, kind, name, amount)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![id, line.kind, line.name, line.amount],
+            )?;
+        }
+        for (advance_id, amount) in recoveries {
+            recover_with(&tx, advance_id, Some(id), payment_date, amount)?;
+        }
+
+        run.created += 1;
+        run.gross += revision.gross;
+        run.deductions += deductions;
+        run.net += revision.gross - deductions;
+    }
+
+    tx.commit()?;
+    Ok(run)
+}
+
+// Rows of earnings or deductions that fit the page; longer payslips get a
+// taller page
+const PAYSLIP_ROWS: usize = 8;
+
+// Earnings and deductions side by side, on the receipt page
+pub fn render_payslip(payment: &SalaryPayment) -> std::result::Result<Vec<u8>, String> {
+    let earnings: Vec<&PayslipLine> = payment
+        .lines
+        .iter()
+        .filter(|l| l.kind == PayLineKind::Earning)
+        .collect();
+    let deductions: Vec<&PayslipLine> = payment
+        .lines
+        .iter()
+        .filter(|l| l.kind == PayLineKind::Deduction)
+        .collect();
+    let rows = earnings.len().max(deductions.len());
+
+    let title = format!("Payslip {} {}", payment.staff_name, payment.month);
+    let height = RECEIPT_HEIGHT + rows.saturating_sub(PAYSLIP_ROWS) as f32 * RECEIPT_ROW;
+    let (doc, page, layer) = PdfDocument::new(&title, Mm(RECEIPT_WIDTH), Mm(height), "Layer 1");
+    let font = doc
+        .add_builtin_font(BuiltinFont::Helvetica)
+        .map_err(|e| e.to_string())?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .map_err(|e| e.to_string())?;
+    let layer = doc.get_page(page).get_layer(layer);
+    let content = RECEIPT_WIDTH - 2.0 * RECEIPT_MARGIN;
+
+    let rule = school_header(&layer, &font, &bold, height)?;
+
+    let mut y = rule - 7.0;
+    layer.use_text("PAYSLIP", 12.0, Mm(RECEIPT_MARGIN), Mm(y), &bold);
+    let right = RECEIPT_MARGIN + content * 0.62;
+    let period = parse_month(&payment.month)
+        .map(|m| m.format("%B %Y").to_string())
+        .unwrap_or_else(|| payment.month.clone());
+    layer.use_text(&period, 10.0, Mm(right), Mm(y), &bold);
+    y -= 6.0;
+    layer.use_text(
+        format!("Paid: {}", payment.payment_date.format("%d/%m/%Y")),
+        9.0,
+        Mm(right),
+        Mm(y),
+        &font,
+    );
+    for detail in [
+        format!("Name: {}", payment.staff_name),
+        format!("Designation: {}", payment.role),
+        format!("Paid by: {}", payment.method.label()),
+    ] {
+        layer.use_text(
+            fit(&detail, content * 0.6, 9.0),
+            9.0,
+            Mm(RECEIPT_MARGIN),
+            Mm(y),
+            &font,
+        );
+        y -= 5.0;
+    }
+
+    y -= 1.0;
+    let half = content / 2.0;
+    let widths = [half - 30.0, 30.0, half - 30.0, 30.0];
+    layer.set_outline_thickness(0.2);
+    y = table_row(
+        &layer,
+        &bold,
+        &widths,
+        &[
+            "Earnings".to_string(),
+            "Tk".to_string(),
+            "Deductions".to_string(),
+            "Tk".to_string(),
+        ],
+        y,
+    );
+    let cell = |line: Option<&&PayslipLine>| match line {
+        Some(line) => (line.name.clone(), line.amount.to_string()),
+        None => (String::new(), String::new()),
+    };
+    for index in 0..rows {
+        let (earning, earned) = cell(earnings.get(index));
+        let (deduction, deducted) = cell(deductions.get(index));
+        y = table_row(
+            &layer,
+            &font,
+            &widths,
+            &[earning, earned, deduction, deducted],
+            y,
+        );
+    }
+    y = table_row(
+        &layer,
+        &bold,
+        &widths,
+        &[
+            "Gross".to_string(),
+            payment.gross.to_string(),
+            "Total deductions".to_string(),
+            payment.deductions.to_string(),
+        ],
+        y,
+    );
+
+    y -= 7.0;
+    layer.use_text(
+        format!("Net pay: {} Tk", payment.net),
+        11.0,
+        Mm(RECEIPT_MARGIN),
+        Mm(y),
+        &bold,
+    );
+    y -= 5.5;
+    layer.use_text(
+        fit(&format!("In words: {}", payment.net_in_words), content, 9.0),
+        9.0,
+        Mm(RECEIPT_MARGIN),
+        Mm(y),
+        &font,
+    );
+    if let Some(remark) = &payment.remark {
+        y -= 5.0;
+        layer.use_text(
+            fit(&format!("Remark: {}", remark), content, 9.0),
+            9.0,
+            Mm(RECEIPT_MARGIN),
+            Mm(y),
+            &font,
+        );
+    }
+
+    // Signatures
+    let sign_y = RECEIPT_MARGIN + 6.0;
+    layer.set_outline_color(grey(0.0));
+    layer.set_outline_thickness(0.3);
+    for (x, label) in [
+        (RECEIPT_MARGIN, "Received by"),
+        (RECEIPT_MARGIN + content - 50.0, "Authorised by"),
+    ] {
+        layer.add_line(line((x, sign_y), (x + 50.0, sign_y)));
+        layer.use_text(label, 8.5, Mm(x), Mm(sign_y - 4.0), &font);
+    }
+
+    doc.save_to_bytes().map_err(|e| e.to_string())
+}
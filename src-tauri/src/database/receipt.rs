@@ -0,0 +1,922 @@
+use super::allocation::{allocate_selected, allocate_with, Allocation};
+use super::fee::{FeeType, Payment, PaymentMethod, RelatedType};
+use super::history::History;
+use super::reconciliation::release_receipt;
+use super::settings::{
+    Setting, BANGLA_FONT_KEY, SCHOOL_ADDRESS_KEY, SCHOOL_LOGO_KEY, SCHOOL_NAME_KEY,
+};
+use super::{add_column_if_missing, conn};
+use crate::report::{decode_image, fit, line, place_image};
+use crate::words::{taka_in_bangla, taka_in_english};
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use printpdf::{
+    BuiltinFont, Color, Greyscale, IndirectFontRef, Mm, PdfDocument, PdfLayerReference, Rgb,
+};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+fn receipt_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+// Wallet and bank payments should carry the transaction id so the statement
+// line matches by it; those taken without one are matched by the payer's
+// phone instead. Cash has none.
+fn check_tender(method: PaymentMethod, transaction_ref: Option<String>) -> Result<Option<String>> {
+    let transaction_ref = transaction_ref
+        .map(|r| r.trim().to_string())
+        .filter(|r| !r.is_empty());
+    if method == PaymentMethod::Cash && transaction_ref.is_some() {
+        return Err(receipt_error("Cash payments have no transaction id"));
+    }
+    Ok(transaction_ref)
+}
+
+// One fee paid on a receipt, as entered at the counter
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ReceiptItemInput {
+    pub fee_type: FeeType,
+    pub month: Option<String>,
+    pub amount: i32,
+}
+
+// Items are kept when a receipt is cancelled; only the payment goes
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ReceiptItem {
+    pub id: i32,
+    pub receipt_id: i32,
+    pub payment_id: Option<i32>,
+    // None for the part of a lump sum held as credit
+    pub fee_type: Option<FeeType>,
+    pub month: Option<String>,
+    pub description: String,
+    pub amount: i32,
+    // Discount given on the billed fee, shown for the payer's benefit
+    pub discount: i32,
+    pub discount_note: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Receipt {
+    pub id: i32,
+    pub session_id: i32,
+    pub session_name: String,
+    // Sequential within the session, without gaps
+    pub number: i32,
+    pub receipt_date: NaiveDate,
+    pub student_id: i32,
+    pub student_name: String,
+    pub roll: i32,
+    pub class_id: i32,
+    pub class_name: String,
+    pub payer_id: i32,
+    pub payer_type: RelatedType,
+    pub payer_name: String,
+    pub method: PaymentMethod,
+    // Wallet or bank transaction id, unique among receipts in force
+    pub transaction_ref: Option<String>,
+    pub remark: Option<String>,
+    // As issued; a cancelled receipt no longer counts towards any fee
+    pub amount: i32,
+    pub amount_in_words: String,
+    pub amount_in_words_bn: String,
+    pub items: Vec<ReceiptItem>,
+    pub created_at: NaiveDateTime,
+    pub cancelled_at: Option<NaiveDateTime>,
+    pub cancel_reason: Option<String>,
+}
+
+const RECEIPT_SELECT: &str = "SELECT r.id, r.session_id, se.name, r.number, r.receipt_date,
+        r.student_id, s.name, s.roll, r.class_id, c.name, r.payer_id, r.payer_type, r.payer_name,
+        r.remark, r.amount, r.created_at, r.cancelled_at, r.cancel_reason, r.method,
+        r.transaction_ref
+     FROM receipts r
+     JOIN sessions se ON se.id = r.session_id
+     JOIN students s ON s.id = r.student_id
+     JOIN classes c ON c.id = r.class_id";
+
+impl Receipt {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        // Receipts are never deleted, which is what keeps the numbering
+        // free of gaps.
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS receipts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id INTEGER NOT NULL,
+                number INTEGER NOT NULL,
+                receipt_date DATE NOT NULL,
+                student_id INTEGER NOT NULL,
+                class_id INTEGER NOT NULL,
+                payer_id INTEGER NOT NULL,
+                payer_type TEXT NOT NULL CHECK (payer_type IN ('GUARDIAN', 'TEACHER', 'STAFF')),
+                payer_name TEXT NOT NULL,
+                remark TEXT,
+                amount INTEGER NOT NULL,
+                created_at DATETIME NOT NULL,
+                cancelled_at DATETIME,
+                cancel_reason TEXT,
+                FOREIGN KEY (session_id) REFERENCES sessions(id),
+                FOREIGN KEY (student_id) REFERENCES students(id),
+                UNIQUE (session_id, number)
+            )",
+            [],
+        )?;
+        db.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS receipt_items (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    receipt_id INTEGER NOT NULL,
+                    payment_id INTEGER,
+                    fee_type TEXT {},
+                    month TEXT,
+                    description TEXT NOT NULL,
+                    amount INTEGER NOT NULL CHECK (amount > 0),
+                    FOREIGN KEY (receipt_id) REFERENCES receipts(id) ON DELETE CASCADE,
+                    FOREIGN KEY (payment_id) REFERENCES payments(id) ON DELETE SET NULL
+                )",
+                FeeType::check_constraint("fee_type")
+            ),
+            [],
+        )?;
+        add_column_if_missing(
+            &db,
+            "receipt_items",
+            "discount",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        add_column_if_missing(&db, "receipt_items", "discount_note", "TEXT")?;
+        add_column_if_missing(
+            &db,
+            "receipts",
+            "method",
+            &format!(
+                "TEXT NOT NULL DEFAULT 'CASH' {}",
+                PaymentMethod::check_constraint("method")
+            ),
+        )?;
+        add_column_if_missing(&db, "receipts", "transaction_ref", "TEXT")?;
+        db.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_receipts_transaction
+             ON receipts (method, transaction_ref)
+             WHERE transaction_ref IS NOT NULL AND cancelled_at IS NULL",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        let amount: i32 = row.get(14)?;
+        Ok(Self {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            session_name: row.get(2)?,
+            number: row.get(3)?,
+            receipt_date: row.get(4)?,
+            student_id: row.get(5)?,
+            student_name: row.get(6)?,
+            roll: row.get(7)?,
+            class_id: row.get(8)?,
+            class_name: row.get(9)?,
+            payer_id: row.get(10)?,
+            payer_type: row.get(11)?,
+            payer_name: row.get(12)?,
+            method: row.get(18)?,
+            transaction_ref: row.get(19)?,
+            remark: row.get(13)?,
+            amount,
+            amount_in_words: taka_in_english(amount),
+            amount_in_words_bn: taka_in_bangla(amount),
+            items: Vec::new(),
+            created_at: row.get(15)?,
+            cancelled_at: row.get(16)?,
+            cancel_reason: row.get(17)?,
+        })
+    }
+
+    fn items_with(db: &Connection, receipt_id: i32) -> Result<Vec<ReceiptItem>> {
+        let mut stmt = db.prepare(
+            "SELECT id, receipt_id, payment_id, fee_type, month, description, amount, discount,
+                    discount_note
+             FROM receipt_items WHERE receipt_id = ?1 ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map(params![receipt_id], |row| {
+            Ok(ReceiptItem {
+                id: row.get(0)?,
+                receipt_id: row.get(1)?,
+                payment_id: row.get(2)?,
+                fee_type: row.get(3)?,
+                month: row.get(4)?,
+                description: row.get(5)?,
+                amount: row.get(6)?,
+                discount: row.get(7)?,
+                discount_note: row.get(8)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn get_with(db: &Connection, id: i32) -> Result<Self> {
+        let mut receipt = db.query_row(
+            &format!("{} WHERE r.id = ?1", RECEIPT_SELECT),
+            params![id],
+            Self::from_row,
+        )?;
+        receipt.items = Self::items_with(db, id)?;
+        Ok(receipt)
+    }
+
+    pub fn get(id: i32) -> Result<Self> {
+        let db = conn()?;
+        Self::get_with(&db, id)
+    }
+
+    // Newest first
+    pub fn get_all(
+        session_id: i32,
+        student_id: Option<i32>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut receipts: Vec<Self> = {
+            let mut stmt = db.prepare(&format!(
+                "{} WHERE r.session_id = ?1
+                   AND (?2 IS NULL OR r.student_id = ?2)
+                   AND (?3 IS NULL OR r.receipt_date >= ?3)
+                   AND (?4 IS NULL OR r.receipt_date <= ?4)
+                 ORDER BY r.number DESC",
+                RECEIPT_SELECT
+            ))?;
+            let rows = stmt.query_map(params![session_id, student_id, from, to], Self::from_row)?;
+            rows.collect::<Result<_>>()?
+        };
+        for receipt in &mut receipts {
+            receipt.items = Self::items_with(&db, receipt.id)?;
+        }
+        Ok(receipts)
+    }
+
+    // Numbers and writes the receipt itself, returning its id and the
+    // student's class
+    #[allow(clippy::too_many_arguments)]
+    fn insert_receipt(
+        db: &Connection,
+        student_id: i32,
+        receipt_date: NaiveDate,
+        payer_id: i32,
+        payer_type: RelatedType,
+        method: PaymentMethod,
+        transaction_ref: Option<&str>,
+        remark: Option<&str>,
+        amount: i32,
+    ) -> Result<(i32, i32)> {
+        let (session_id, class_id): (i32, i32) = db.query_row(
+            "SELECT session_id, class_id FROM students WHERE id = ?1",
+            params![student_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Payment::check_payer(db, student_id, payer_id, payer_type)?;
+        let payer_name: String = match payer_type {
+            RelatedType::Guardian => db.query_row(
+                "SELECT name FROM guardians WHERE id = ?1",
+                params![payer_id],
+                |row| row.get(0),
+            )?,
+            RelatedType::Teacher | RelatedType::Staff => db.query_row(
+                "SELECT name FROM staffs WHERE id = ?1",
+                params![payer_id],
+                |row| row.get(0),
+            )?,
+        };
+        if let Some(reference) = transaction_ref {
+            let existing: Option<i32> = db
+                .query_row(
+                    "SELECT number FROM receipts
+                 WHERE method = ?1 AND transaction_ref = ?2 AND cancelled_at IS NULL",
+                    params![method, reference],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(number) = existing {
+                return Err(receipt_error(&format!(
+                    "Transaction {} is already on receipt {}",
+                    reference, number
+                )));
+            }
+        }
+        let number: i32 = db.query_row(
+            "SELECT COALESCE(MAX(number), 0) + 1 FROM receipts WHERE session_id = ?1",
+            params![session_id],
+            |row| row.get(0),
+        )?;
+
+        db.execute(
+            "INSERT INTO receipts
+            (session_id, number, receipt_date, student_id, class_id, payer_id, payer_type,
+             payer_name, method, transaction_ref, remark, amount, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                session_id,
+                number,
+                receipt_date,
+                student_id,
+                class_id,
+                payer_id,
+                payer_type,
+                payer_name,
+                method,
+                transaction_ref,
+                remark,
+                amount,
+                Local::now().naive_local()
+            ],
+        )?;
+        Ok((db.last_insert_rowid() as i32, class_id))
+    }
+
+    // Records the payments and numbers the receipt in one go, so a number is
+    // only ever taken by a receipt that exists.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        student_id: i32,
+        receipt_date: NaiveDate,
+        payer_id: i32,
+        payer_type: RelatedType,
+        method: PaymentMethod,
+        transaction_ref: Option<String>,
+        items: Vec<ReceiptItemInput>,
+        remark: Option<String>,
+    ) -> Result<Self> {
+        if items.is_empty() {
+            return Err(receipt_error("A receipt needs at least one fee"));
+        }
+        let mut lines: Vec<(FeeType, Option<String>, i32)> = Vec::new();
+        for item in items {
+            if item.amount <= 0 {
+                return Err(receipt_error("Amount must be positive"));
+            }
+            let month = Payment::normalize_month(item.fee_type, item.month)?;
+            if lines
+                .iter()
+                .any(|(fee_type, m, _)| *fee_type == item.fee_type && *m == month)
+            {
+                return Err(receipt_error("The same fee is listed twice"));
+            }
+            lines.push((item.fee_type, month, item.amount));
+        }
+        let transaction_ref = check_tender(method, transaction_ref)?;
+        let remark = remark.filter(|r| !r.trim().is_empty());
+
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        let amount = lines.iter().map(|(_, _, amount)| amount).sum();
+        let (receipt_id, class_id) = Self::insert_receipt(
+            &tx,
+            student_id,
+            receipt_date,
+            payer_id,
+            payer_type,
+            method,
+            transaction_ref.as_deref(),
+            remark.as_deref(),
+            amount,
+        )?;
+
+        for (fee_type, month, amount) in &lines {
+            let payment_id = Payment::insert(
+                &tx,
+                student_id,
+                class_id,
+                *amount,
+                receipt_date,
+                Some(*fee_type),
+                month.as_deref(),
+                payer_id,
+                payer_type,
+                remark.as_deref(),
+                receipt_id,
+            )?;
+            let description = match month {
+                Some(month) => format!("{} {}", fee_type.label(), month),
+                None => fee_type.label().to_string(),
+            };
+            let (discount, discount_note): (i32, Option<String>) = tx
+                .query_row(
+                    "SELECT discount, discount_note FROM invoice_lines
+                     WHERE student_id = ?1 AND fee_type = ?2 AND (?3 IS NULL OR month = ?3)
+                     ORDER BY month ASC LIMIT 1",
+                    params![student_id, fee_type, month],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?
+                .unwrap_or((0, None));
+            tx.execute(
+                "INSERT INTO receipt_items
+                    (receipt_id, payment_id, fee_type, month, description, amount, discount,
+                     discount_note)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    receipt_id,
+                    payment_id,
+                    fee_type,
+                    month,
+                    description,
+                    amount,
+                    discount,
+                    discount_note
+                ],
+            )?;
+        }
+        allocate_with(&tx, student_id)?;
+
+        let receipt = Self::get_with(&tx, receipt_id)?;
+        tx.commit()?;
+        Ok(receipt)
+    }
+
+    // Takes one sum towards several fees. It goes to the selected invoice
+    // lines first, in the order given, then to the oldest fees still owed;
+    // anything left is held as credit for fees not yet billed. The items
+    // show where the money went.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_lump_sum(
+        student_id: i32,
+        receipt_date: NaiveDate,
+        payer_id: i32,
+        payer_type: RelatedType,
+        method: PaymentMethod,
+        transaction_ref: Option<String>,
+        amount: i32,
+        invoice_line_ids: Vec<i32>,
+        remark: Option<String>,
+    ) -> Result<Self> {
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        let receipt = Self::create_lump_sum_with(
+            &tx,
+            student_id,
+            receipt_date,
+            payer_id,
+            payer_type,
+            method,
+            transaction_ref,
+            amount,
+            &invoice_line_ids,
+            remark,
+        )?;
+        tx.commit()?;
+        Ok(receipt)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn create_lump_sum_with(
+        db: &Connection,
+        student_id: i32,
+        receipt_date: NaiveDate,
+        payer_id: i32,
+        payer_type: RelatedType,
+        method: PaymentMethod,
+        transaction_ref: Option<String>,
+        amount: i32,
+        invoice_line_ids: &[i32],
+        remark: Option<String>,
+    ) -> Result<Self> {
+        if amount <= 0 {
+            return Err(receipt_error("Amount must be positive"));
+        }
+        let transaction_ref = check_tender(method, transaction_ref)?;
+        let remark = remark.filter(|r| !r.trim().is_empty());
+
+        let (receipt_id, class_id) = Self::insert_receipt(
+            db,
+            student_id,
+            receipt_date,
+            payer_id,
+            payer_type,
+            method,
+            transaction_ref.as_deref(),
+            remark.as_deref(),
+            amount,
+        )?;
+        let payment_id = Payment::insert(
+            db,
+            student_id,
+            class_id,
+            amount,
+            receipt_date,
+            None,
+            None,
+            payer_id,
+            payer_type,
+            remark.as_deref(),
+            receipt_id,
+        )?;
+        allocate_selected(db, payment_id, invoice_line_ids)?;
+
+        let mut held = amount;
+        for allocation in Allocation::for_payment_with(db, payment_id)? {
+            let (discount, discount_note): (i32, Option<String>) = db.query_row(
+                "SELECT discount, discount_note FROM invoice_lines WHERE id = ?1",
+                params![allocation.invoice_line_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            db.execute(
+                "INSERT INTO receipt_items
+                    (receipt_id, payment_id, fee_type, month, description, amount, discount,
+                     discount_note)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    receipt_id,
+                    payment_id,
+                    allocation.fee_type,
+                    allocation
+                        .fee_type
+                        .is_monthly()
+                        .then_some(allocation.month.as_str()),
+                    allocation.description,
+                    allocation.amount,
+                    discount,
+                    discount_note
+                ],
+            )?;
+            held -= allocation.amount;
+        }
+        if held > 0 {
+            db.execute(
+                "INSERT INTO receipt_items (receipt_id, payment_id, description, amount)
+                 VALUES (?1, ?2, 'Advance (held as credit)', ?3)",
+                params![receipt_id, payment_id, held],
+            )?;
+        }
+
+        Self::get_with(db, receipt_id)
+    }
+
+    // Keeps the number and the items for the record but removes the
+    // payments, so the fees show as owed again.
+    pub fn cancel(id: i32, reason: &str) -> Result<Self> {
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err(receipt_error("A reason is needed to cancel a receipt"));
+        }
+
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        let receipt = Self::get_with(&tx, id)?;
+        if receipt.cancelled_at.is_some() {
+            return Err(receipt_error("Receipt is already cancelled"));
+        }
+        tx.execute(
+            "UPDATE receipts SET cancelled_at = ?1, cancel_reason = ?2 WHERE id = ?3",
+            params![Local::now().naive_local(), reason, id],
+        )?;
+        tx.execute("DELETE FROM payments WHERE receipt_id = ?1", params![id])?;
+        // Lines the cancelled payments covered are settled from credit
+        allocate_with(&tx, receipt.student_id)?;
+        release_receipt(&tx, id)?;
+        History::record(
+            &tx,
+            "receipt",
+            id,
+            "cancel",
+            Some(format!(
+                "No. {} of {} for {} Tk: {}",
+                receipt.number, receipt.session_name, receipt.amount, reason
+            )),
+        )?;
+
+        let cancelled = Self::get_with(&tx, id)?;
+        tx.commit()?;
+        Ok(cancelled)
+    }
+}
+
+// A5 landscape, in millimetres; payslips use the same page
+pub(super) const RECEIPT_WIDTH: f32 = 210.0;
+pub(super) const RECEIPT_HEIGHT: f32 = 148.0;
+pub(super) const RECEIPT_MARGIN: f32 = 12.0;
+pub(super) const RECEIPT_ROW: f32 = 6.5;
+// Items that fit the page; longer receipts get a taller page
+const RECEIPT_ITEMS: usize = 8;
+const LOGO_PIXELS: u32 = 200;
+
+pub(super) fn grey(level: f32) -> Color {
+    Color::Greyscale(Greyscale::new(level, None))
+}
+
+// School name, address and logo across the top of a page, ruled off
+// beneath; returns the height of the rule.
+pub(super) fn school_header(
+    layer: &PdfLayerReference,
+    font: &IndirectFontRef,
+    bold: &IndirectFontRef,
+    height: f32,
+) -> std::result::Result<f32, String> {
+    let school_name = Setting::get(SCHOOL_NAME_KEY)
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
+    let school_address = Setting::get(SCHOOL_ADDRESS_KEY).map_err(|e| e.to_string())?;
+    let logo = Setting::get(SCHOOL_LOGO_KEY)
+        .map_err(|e| e.to_string())?
+        .and_then(|logo| decode_image(&logo, LOGO_PIXELS));
+    let content = RECEIPT_WIDTH - 2.0 * RECEIPT_MARGIN;
+
+    let top = height - RECEIPT_MARGIN;
+    let mut text_x = RECEIPT_MARGIN;
+    if let Some(logo) = &logo {
+        place_image(layer, logo, RECEIPT_MARGIN, top - 16.0, 16.0, 16.0);
+        text_x += 19.0;
+    }
+    layer.set_fill_color(grey(0.0));
+    layer.use_text(
+        fit(&school_name, RECEIPT_MARGIN + content - text_x, 14.0),
+        14.0,
+        Mm(text_x),
+        Mm(top - 6.0),
+        bold,
+    );
+    if let Some(address) = &school_address {
+        layer.use_text(
+            fit(address, RECEIPT_MARGIN + content - text_x, 8.5),
+            8.5,
+            Mm(text_x),
+            Mm(top - 11.5),
+            font,
+        );
+    }
+
+    layer.set_outline_color(grey(0.0));
+    layer.set_outline_thickness(0.4);
+    let rule = top - 19.0;
+    layer.add_line(line(
+        (RECEIPT_MARGIN, rule),
+        (RECEIPT_MARGIN + content, rule),
+    ));
+    Ok(rule)
+}
+
+// Draws a bordered row of cells with its top edge at `top` and returns its
+// bottom edge.
+pub(super) fn table_row(
+    layer: &PdfLayerReference,
+    font: &IndirectFontRef,
+    widths: &[f32],
+    cells: &[String],
+    top: f32,
+) -> f32 {
+    let bottom = top - RECEIPT_ROW;
+    let right = RECEIPT_MARGIN + widths.iter().sum::<f32>();
+    layer.add_line(line((RECEIPT_MARGIN, bottom), (right, bottom)));
+    layer.add_line(line((RECEIPT_MARGIN, top), (right, top)));
+
+    let mut x = RECEIPT_MARGIN;
+    layer.add_line(line((x, bottom), (x, top)));
+    for (width, cell) in widths.iter().zip(cells) {
+        layer.use_text(
+            fit(cell, *width, 9.0),
+            9.0,
+            Mm(x + 1.5),
+            Mm(bottom + 2.0),
+            font,
+        );
+        x += width;
+        layer.add_line(line((x, bottom), (x, top)));
+    }
+    bottom
+}
+
+// Fonts with Bangla glyphs shipped with Windows and common Linux distributions
+const SYSTEM_BANGLA_FONTS: [&str; 5] = [
+    "C:\\Windows\\Fonts\\Nirmala.ttf",
+    "C:\\Windows\\Fonts\\vrinda.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansBengali-Regular.ttf",
+    "/usr/share/fonts/truetype/lohit-bengali/Lohit-Bengali.ttf",
+    "/usr/share/fonts/truetype/freefont/FreeSerif.ttf",
+];
+
+// A TrueType font file, checked to be one a PDF can embed
+pub fn read_font(path: &str) -> std::result::Result<Vec<u8>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Cannot read font {}: {}", path, e))?;
+    PdfDocument::empty("font")
+        .add_external_font(bytes.as_slice())
+        .map_err(|_| format!("{} is not a readable TrueType font", path))?;
+    Ok(bytes)
+}
+
+// The configured Bangla font, or the first system one found
+fn bangla_font() -> std::result::Result<Option<Vec<u8>>, String> {
+    let configured = Setting::get(BANGLA_FONT_KEY)
+        .map_err(|e| e.to_string())?
+        .filter(|path| !path.trim().is_empty());
+    if let Some(path) = configured {
+        return read_font(path.trim()).map(Some);
+    }
+    Ok(SYSTEM_BANGLA_FONTS
+        .iter()
+        .find_map(|path| std::fs::read(path).ok()))
+}
+
+// One receipt per page. The builtin PDF fonts have no Bangla glyphs, so the
+// Bangla amount in words is printed with an embedded TrueType font, and left
+// out when none is available.
+pub fn render_receipt(receipt: &Receipt) -> std::result::Result<Vec<u8>, String> {
+    let title = format!("Receipt {} ({})", receipt.number, receipt.session_name);
+    let height =
+        RECEIPT_HEIGHT + receipt.items.len().saturating_sub(RECEIPT_ITEMS) as f32 * RECEIPT_ROW;
+    let (doc, page, layer) = PdfDocument::new(&title, Mm(RECEIPT_WIDTH), Mm(height), "Layer 1");
+    let font = doc
+        .add_builtin_font(BuiltinFont::Helvetica)
+        .map_err(|e| e.to_string())?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .map_err(|e| e.to_string())?;
+    let bangla = match bangla_font()? {
+        Some(bytes) => Some(
+            doc.add_external_font(bytes.as_slice())
+                .map_err(|e| e.to_string())?,
+        ),
+        None => None,
+    };
+    let layer = doc.get_page(page).get_layer(layer);
+    let content = RECEIPT_WIDTH - 2.0 * RECEIPT_MARGIN;
+
+    let rule = school_header(&layer, &font, &bold, height)?;
+
+    let mut y = rule - 7.0;
+    layer.use_text("MONEY RECEIPT", 12.0, Mm(RECEIPT_MARGIN), Mm(y), &bold);
+    let right = RECEIPT_MARGIN + content * 0.62;
+    layer.use_text(
+        format!("No. {:05}  ({})", receipt.number, receipt.session_name),
+        10.0,
+        Mm(right),
+        Mm(y),
+        &bold,
+    );
+    y -= 6.0;
+    layer.use_text(
+        format!("Date: {}", receipt.receipt_date.format("%d/%m/%Y")),
+        9.0,
+        Mm(right),
+        Mm(y),
+        &font,
+    );
+
+    let details = [
+        format!("Student: {} (Roll {})", receipt.student_name, receipt.roll),
+        format!("Class: {}", receipt.class_name),
+        format!(
+            "Received from: {} ({})",
+            receipt.payer_name,
+            receipt.payer_type.as_str().to_lowercase()
+        ),
+        match &receipt.transaction_ref {
+            Some(reference) => format!("Paid by: {} (Ref. {})", receipt.method.label(), reference),
+            None => format!("Paid by: {}", receipt.method.label()),
+        },
+    ];
+    for detail in &details {
+        layer.use_text(
+            fit(detail, content * 0.6, 9.0),
+            9.0,
+            Mm(RECEIPT_MARGIN),
+            Mm(y),
+            &font,
+        );
+        y -= 5.0;
+    }
+
+    // Itemised fees
+    y -= 1.0;
+    let widths = [10.0, content - 10.0 - 60.0 - 32.0, 60.0, 32.0];
+    layer.set_outline_thickness(0.2);
+    y = table_row(
+        &layer,
+        &bold,
+        &widths,
+        &[
+            "#".to_string(),
+            "Description".to_string(),
+            "Discount".to_string(),
+            "Amount (Tk)".to_string(),
+        ],
+        y,
+    );
+    for (index, item) in receipt.items.iter().enumerate() {
+        let discount = match &item.discount_note {
+            Some(note) if item.discount > 0 => format!("{} ({})", item.discount, note),
+            _ => String::new(),
+        };
+        y = table_row(
+            &layer,
+            &font,
+            &widths,
+            &[
+                (index + 1).to_string(),
+                item.description.clone(),
+                discount,
+                item.amount.to_string(),
+            ],
+            y,
+        );
+    }
+    let discount: i32 = receipt.items.iter().map(|i| i.discount).sum();
+    y = table_row(
+        &layer,
+        &bold,
+        &widths,
+        &[
+            String::new(),
+            "Total".to_string(),
+            if discount > 0 {
+                discount.to_string()
+            } else {
+                String::new()
+            },
+            receipt.amount.to_string(),
+        ],
+        y,
+    );
+
+    y -= 6.0;
+    layer.use_text(
+        fit(
+            &format!("In words: {}", receipt.amount_in_words),
+            content,
+            9.0,
+        ),
+        9.0,
+        Mm(RECEIPT_MARGIN),
+        Mm(y),
+        &font,
+    );
+    if let Some(bangla) = &bangla {
+        y -= 5.0;
+        layer.use_text(
+            &receipt.amount_in_words_bn,
+            10.0,
+            Mm(RECEIPT_MARGIN),
+            Mm(y),
+            bangla,
+        );
+    }
+    if let Some(remark) = &receipt.remark {
+        y -= 5.0;
+        layer.use_text(
+            fit(&format!("Remark: {}", remark), content, 9.0),
+            9.0,
+            Mm(RECEIPT_MARGIN),
+            Mm(y),
+            &font,
+        );
+    }
+
+    if receipt.cancelled_at.is_some() {
+        let red = Color::Rgb(Rgb::new(0.75, 0.1, 0.1, None));
+        layer.set_fill_color(red);
+        layer.use_text(
+            "CANCELLED",
+            30.0,
+            Mm(RECEIPT_MARGIN + content * 0.3),
+            Mm(height * 0.42),
+            &bold,
+        );
+        if let Some(reason) = &receipt.cancel_reason {
+            y -= 5.0;
+            layer.use_text(
+                fit(&format!("Cancelled: {}", reason), content, 9.0),
+                9.0,
+                Mm(RECEIPT_MARGIN),
+                Mm(y),
+                &bold,
+            );
+        }
+        layer.set_fill_color(grey(0.0));
+    }
+
+    // Signatures
+    let sign_y = RECEIPT_MARGIN + 6.0;
+    layer.set_outline_thickness(0.3);
+    for (x, label) in [
+        (RECEIPT_MARGIN, "Payer"),
+        (RECEIPT_MARGIN + content - 50.0, "Received by"),
+    ] {
+        layer.add_line(line((x, sign_y), (x + 50.0, sign_y)));
+        layer.use_text(label, 8.5, Mm(x), Mm(sign_y - 4.0), &font);
+    }
+
+    doc.save_to_bytes().map_err(|e| e.to_string())
+}
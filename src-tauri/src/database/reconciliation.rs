@@ -0,0 +1,1101 @@
+use super::conn;
+use super::fee::{PaymentMethod, RelatedType};
+use super::history::History;
+use super::receipt::Receipt;
+use crate::phone;
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+fn reconciliation_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+// Date layouts seen in wallet and bank exports; a time after the date is
+// ignored.
+const DATE_FORMATS: [&str; 6] = [
+    "%Y-%m-%d", "%d/%m/%Y", "%d-%m-%Y", "%Y/%m/%d", "%d-%b-%Y", "%d %b %Y",
+];
+
+// A counter payment is looked for this many days either side of the
+// statement date when matching by phone.
+const MATCH_DAYS: i64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum StatementStatus {
+    Matched,
+    Unmatched,
+    // Not a fee, such as a transfer between the school's own accounts
+    Ignored,
+}
+
+impl StatementStatus {
+    pub const ALL: [Self; 3] = [Self::Matched, Self::Unmatched, Self::Ignored];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Matched => "MATCHED",
+            Self::Unmatched => "UNMATCHED",
+            Self::Ignored => "IGNORED",
+        }
+    }
+}
+
+impl ToSql for StatementStatus {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for StatementStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|s| s.as_str() == text)
+            .ok_or_else(|| {
+                FromSqlError::Other(format!("Unknown statement status: {}", text).into())
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MatchKind {
+    // The receipt carries the statement's transaction id
+    Reference,
+    // The sender is a guardian who paid the same amount at about that time
+    Phone,
+    Manual,
+}
+
+impl MatchKind {
+    pub const ALL: [Self; 3] = [Self::Reference, Self::Phone, Self::Manual];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Reference => "REFERENCE",
+            Self::Phone => "PHONE",
+            Self::Manual => "MANUAL",
+        }
+    }
+}
+
+impl ToSql for MatchKind {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for MatchKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == text)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown match kind: {}", text).into()))
+    }
+}
+
+// One incoming transaction from a wallet or bank statement
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StatementLine {
+    pub id: i32,
+    pub method: PaymentMethod,
+    pub transaction_ref: String,
+    pub transaction_date: NaiveDate,
+    // Sending wallet number or account, as printed
+    pub sender: Option<String>,
+    pub amount: i32,
+    pub status: StatementStatus,
+    pub matched_by: Option<MatchKind>,
+    pub receipt_id: Option<i32>,
+    pub receipt_number: Option<i32>,
+    // The receipt's student once matched, otherwise the likely one
+    pub student_id: Option<i32>,
+    pub student_name: Option<String>,
+    pub guardian_id: Option<i32>,
+    pub guardian_name: Option<String>,
+    pub note: Option<String>,
+    pub imported_at: NaiveDateTime,
+}
+
+const LINE_SELECT: &str = "SELECT l.id, l.method, l.transaction_ref, l.transaction_date, l.sender,
+        l.amount, l.status, l.matched_by, l.receipt_id, r.number, l.student_id, s.name,
+        l.guardian_id, g.name, l.note, l.imported_at
+     FROM statement_lines l
+     LEFT JOIN receipts r ON r.id = l.receipt_id
+     LEFT JOIN students s ON s.id = l.student_id
+     LEFT JOIN guardians g ON g.id = l.guardian_id";
+
+impl StatementLine {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS statement_lines (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    method TEXT NOT NULL {},
+                    transaction_ref TEXT NOT NULL,
+                    transaction_date DATE NOT NULL,
+                    sender TEXT,
+                    amount INTEGER NOT NULL CHECK (amount > 0),
+                    status TEXT NOT NULL DEFAULT 'UNMATCHED'
+                        CHECK (status IN ('MATCHED', 'UNMATCHED', 'IGNORED')),
+                    matched_by TEXT CHECK (matched_by IN ('REFERENCE', 'PHONE', 'MANUAL')),
+                    receipt_id INTEGER,
+                    student_id INTEGER,
+                    guardian_id INTEGER,
+                    note TEXT,
+                    imported_at DATETIME NOT NULL,
+                    FOREIGN KEY (receipt_id) REFERENCES receipts(id),
+                    FOREIGN KEY (student_id) REFERENCES students(id) ON DELETE SET NULL,
+                    FOREIGN KEY (guardian_id) REFERENCES guardians(id) ON DELETE SET NULL,
+                    UNIQUE (method, transaction_ref)
+                )",
+                PaymentMethod::check_constraint("method")
+            ),
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            method: row.get(1)?,
+            transaction_ref: row.get(2)?,
+            transaction_date: row.get(3)?,
+            sender: row.get(4)?,
+            amount: row.get(5)?,
+            status: row.get(6)?,
+            matched_by: row.get(7)?,
+            receipt_id: row.get(8)?,
+            receipt_number: row.get(9)?,
+            student_id: row.get(10)?,
+            student_name: row.get(11)?,
+            guardian_id: row.get(12)?,
+            guardian_name: row.get(13)?,
+            note: row.get(14)?,
+            imported_at: row.get(15)?,
+        })
+    }
+
+    pub fn get_with(db: &Connection, id: i32) -> Result<Self> {
+        db.query_row(
+            &format!("{} WHERE l.id = ?1", LINE_SELECT),
+            params![id],
+            Self::from_row,
+        )
+    }
+
+    // Oldest first
+    pub fn get_all(
+        method: Option<PaymentMethod>,
+        status: Option<StatementStatus>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<Self>> {
+        let db = conn()?;
+        Self::get_all_with(&db, method, status, from, to)
+    }
+
+    fn get_all_with(
+        db: &Connection,
+        method: Option<PaymentMethod>,
+        status: Option<StatementStatus>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<Self>> {
+        let mut stmt = db.prepare(&format!(
+            "{} WHERE (?1 IS NULL OR l.method = ?1)
+               AND (?2 IS NULL OR l.status = ?2)
+               AND (?3 IS NULL OR l.transaction_date >= ?3)
+               AND (?4 IS NULL OR l.transaction_date <= ?4)
+             ORDER BY l.transaction_date ASC, l.id ASC",
+            LINE_SELECT
+        ))?;
+        let rows = stmt.query_map(params![method, status, from, to], Self::from_row)?;
+        rows.collect()
+    }
+
+    // Links a line to a receipt the matcher missed. A receipt recorded
+    // without the transaction id takes the statement's.
+    pub fn match_receipt(id: i32, receipt_id: i32) -> Result<Self> {
+        let mut db = conn()?;
+        let tx = db.transaction()?;
+
+        let line = Self::get_with(&tx, id)?;
+        if line.status == StatementStatus::Matched {
+            return Err(reconciliation_error("Statement line is already matched"));
+        }
+        let receipt = Receipt::get_with(&tx, receipt_id)?;
+        if receipt.cancelled_at.is_some() {
+            return Err(reconciliation_error("Receipt is cancelled"));
+        }
+        if receipt.method != line.method {
+            return Err(reconciliation_error(&format!(
+                "Receipt was paid by {}",
+                receipt.method.label()
+            )));
+        }
+        if receipt
+            .transaction_ref
+            .as_ref()
+            .is_some_and(|r| !r.eq_ignore_ascii_case(&line.transaction_ref))
+        {
+            return Err(reconciliation_error(
+                "Receipt has a different transaction id",
+            ));
+        }
+        if linked(&tx, receipt_id)? {
+            return Err(reconciliation_error(
+                "Receipt is matched to another statement line",
+            ));
+        }
+        link(&tx, &line, &receipt, MatchKind::Manual)?;
+
+        let line = Self::get_with(&tx, id)?;
+        tx.commit()?;
+        Ok(line)
+    }
+
+    // For money that reached the wallet but was never recorded at the
+    // counter: takes it as a lump sum for the student, dated as on the
+    // statement, and matches the line to the new receipt.
+    pub fn record(
+        id: i32,
+        student_id: i32,
+        payer_id: i32,
+        payer_type: RelatedType,
+    ) -> Result<Receipt> {
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        let line = Self::get_with(&tx, id)?;
+        if line.status != StatementStatus::Unmatched {
+            return Err(reconciliation_error(
+                "Only unmatched statement lines can be recorded",
+            ));
+        }
+
+        let receipt = Receipt::create_lump_sum_with(
+            &tx,
+            student_id,
+            line.transaction_date,
+            payer_id,
+            payer_type,
+            line.method,
+            Some(line.transaction_ref.clone()),
+            line.amount,
+            &[],
+            Some(format!("From {} statement", line.method.label())),
+        )?;
+        link(&tx, &line, &receipt, MatchKind::Manual)?;
+
+        tx.commit()?;
+        Ok(receipt)
+    }
+
+    pub fn ignore(id: i32, note: &str) -> Result<Self> {
+        let note = note.trim();
+        if note.is_empty() {
+            return Err(reconciliation_error(
+                "A note is needed to ignore a statement line",
+            ));
+        }
+        let db = conn()?;
+        let line = Self::get_with(&db, id)?;
+        if line.status == StatementStatus::Matched {
+            return Err(reconciliation_error(
+                "Unmatch the statement line before ignoring it",
+            ));
+        }
+        db.execute(
+            "UPDATE statement_lines SET status = ?1, note = ?2 WHERE id = ?3",
+            params![StatementStatus::Ignored, note, id],
+        )?;
+        History::record(
+            &db,
+            "statement_line",
+            id,
+            "ignore",
+            Some(format!(
+                "{} {} of {} Tk: {}",
+                line.method.label(),
+                line.transaction_ref,
+                line.amount,
+                note
+            )),
+        )?;
+        Self::get_with(&db, id)
+    }
+
+    // Undoes a wrong match or an ignore. The receipt keeps its transaction
+    // id.
+    pub fn unmatch(id: i32) -> Result<Self> {
+        let db = conn()?;
+        let line = Self::get_with(&db, id)?;
+        if line.status == StatementStatus::Unmatched {
+            return Err(reconciliation_error("Statement line is not matched"));
+        }
+        db.execute(
+            "UPDATE statement_lines
+             SET status = ?1, matched_by = NULL, receipt_id = NULL, note = NULL
+             WHERE id = ?2",
+            params![StatementStatus::Unmatched, id],
+        )?;
+        Self::get_with(&db, id)
+    }
+}
+
+// Cancelled receipts no longer account for their statement line
+pub(super) fn release_receipt(db: &Connection, receipt_id: i32) -> Result<()> {
+    db.execute(
+        "UPDATE statement_lines
+         SET status = ?1, matched_by = NULL, receipt_id = NULL
+         WHERE receipt_id = ?2",
+        params![StatementStatus::Unmatched, receipt_id],
+    )?;
+    Ok(())
+}
+
+fn linked(db: &Connection, receipt_id: i32) -> Result<bool> {
+    db.query_row(
+        "SELECT COUNT(*) > 0 FROM statement_lines WHERE receipt_id = ?1",
+        params![receipt_id],
+        |row| row.get(0),
+    )
+}
+
+fn link(db: &Connection, line: &StatementLine, receipt: &Receipt, kind: MatchKind) -> Result<()> {
+    let note = (receipt.amount != line.amount)
+        .then(|| format!("Receipt {} is for {} Tk", receipt.number, receipt.amount));
+    db.execute(
+        "UPDATE statement_lines
+         SET status = ?1, matched_by = ?2, receipt_id = ?3, student_id = ?4, note = ?5
+         WHERE id = ?6",
+        params![
+            StatementStatus::Matched,
+            kind,
+            receipt.id,
+            receipt.student_id,
+            note,
+            line.id
+        ],
+    )?;
+    if receipt.transaction_ref.is_none() {
+        db.execute(
+            "UPDATE receipts SET transaction_ref = ?1 WHERE id = ?2",
+            params![line.transaction_ref, receipt.id],
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StatementEntry {
+    pub transaction_ref: String,
+    pub transaction_date: NaiveDate,
+    pub sender: Option<String>,
+    pub amount: i32,
+}
+
+fn header_key(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn find_column(headers: &[String], taken: &[usize], names: &[&str]) -> Option<usize> {
+    names.iter().find_map(|name| {
+        headers
+            .iter()
+            .enumerate()
+            .find(|(i, h)| !taken.contains(i) && h.contains(name))
+            .map(|(i, _)| i)
+    })
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    let value = value.trim();
+    let date = value.split([' ', 'T']).next().unwrap_or(value);
+    DATE_FORMATS
+        .iter()
+        .find_map(|f| NaiveDate::parse_from_str(date, f).ok())
+        .or_else(|| {
+            DATE_FORMATS
+                .iter()
+                .find_map(|f| NaiveDate::parse_from_str(value, f).ok())
+        })
+}
+
+// "1,500.00", "Tk. 1500" and "৳1500" all read as 1500. A minus sign
+// before the number makes it negative.
+fn parse_amount(value: &str) -> Option<f64> {
+    let start = value.find(|c: char| c.is_ascii_digit())?;
+    let (prefix, rest) = value.split_at(start);
+    let number: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == ',' || *c == '.')
+        .filter(|c| *c != ',')
+        .collect();
+    let amount: f64 = number.trim_end_matches('.').parse().ok()?;
+    Some(if prefix.contains('-') {
+        -amount
+    } else {
+        amount
+    })
+}
+
+// Reads a statement exported as CSV. Columns are found by their headings:
+// the transaction id ("TrxID", "Transaction ID", "Reference"), the date,
+// the sender ("From", "Sender", "Account") and the amount received
+// ("Credit" or "Amount"). With separate Debit and Credit columns a line
+// without a credit is outgoing. Returns the incoming transactions, the number of
+// outgoing ones skipped and the line numbers that could not be read.
+pub fn parse_statement(
+    content: &str,
+) -> std::result::Result<(Vec<StatementEntry>, i32, Vec<usize>), String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(header_key)
+        .collect();
+
+    let date =
+        find_column(&headers, &[], &["date", "time"]).ok_or("The statement has no date column")?;
+    let reference = find_column(
+        &headers,
+        &[date],
+        &[
+            "trxid",
+            "txnid",
+            "transactionid",
+            "reference",
+            "refno",
+            "trx",
+            "txn",
+        ],
+    )
+    .ok_or("The statement has no transaction id column")?;
+    let amount = find_column(&headers, &[date, reference], &["credit", "amount"])
+        .ok_or("The statement has no amount column")?;
+    let debit = find_column(&headers, &[date, reference, amount], &["debit"]);
+    let mut taken = vec![date, reference, amount];
+    taken.extend(debit);
+    let sender = find_column(
+        &headers,
+        &taken,
+        &[
+            "sender", "from", "account", "msisdn", "mobile", "phone", "wallet",
+        ],
+    );
+
+    let mut entries = Vec::new();
+    let mut outgoing = 0;
+    let mut invalid = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        // The heading is line 1
+        let line = index + 2;
+        let Ok(record) = record else {
+            invalid.push(line);
+            continue;
+        };
+        if record.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+        let field = |i: usize| record.get(i).unwrap_or("");
+        if debit.is_some() && field(amount).is_empty() {
+            outgoing += 1;
+            continue;
+        }
+        let parsed = (
+            Some(field(reference).to_string()).filter(|r| !r.is_empty()),
+            parse_date(field(date)),
+            parse_amount(field(amount)),
+        );
+        match parsed {
+            (Some(_), Some(_), Some(value)) if value.round() <= 0.0 => outgoing += 1,
+            (Some(transaction_ref), Some(transaction_date), Some(value)) => {
+                entries.push(StatementEntry {
+                    transaction_ref,
+                    transaction_date,
+                    sender: sender
+                        .map(field)
+                        .filter(|s| !s.is_empty())
+                        .map(str::to_string),
+                    amount: value.round() as i32,
+                })
+            }
+            _ => invalid.push(line),
+        }
+    }
+    Ok((entries, outgoing, invalid))
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct StatementImport {
+    pub method: Option<PaymentMethod>,
+    // Incoming transactions read from the file
+    pub transactions: i32,
+    pub added: i32,
+    // Already imported from an earlier statement
+    pub duplicates: i32,
+    pub matched_by_reference: i32,
+    pub matched_by_phone: i32,
+    pub unmatched: i32,
+    // Outgoing transactions, which are not fees
+    pub outgoing: i32,
+    pub invalid_lines: Vec<usize>,
+}
+
+// Guardians by their phone, which is stored in E.164
+fn guardian_phones(db: &Connection) -> Result<BTreeMap<String, Vec<i32>>> {
+    let mut stmt = db.prepare("SELECT id, phone FROM guardians ORDER BY id ASC")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, i32>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut phones: BTreeMap<String, Vec<i32>> = BTreeMap::new();
+    for row in rows {
+        let (id, phone) = row?;
+        phones.entry(phone).or_default().push(id);
+    }
+    Ok(phones)
+}
+
+// Tries the transaction id first, then the sender's phone. A phone match
+// needs a receipt for one of the guardian's children of the same method and
+// amount, a few days either side and without a transaction id of its own;
+// failing that, the guardian, and the child if there is only one, are noted
+// to help whoever matches the line by hand. The sender is read as a number
+// of `country` when it has no international prefix.
+fn match_line(
+    db: &Connection,
+    line: &StatementLine,
+    phones: &BTreeMap<String, Vec<i32>>,
+    country: &str,
+) -> Result<Option<MatchKind>> {
+    let by_reference: Option<i32> = db
+        .query_row(
+            "SELECT id FROM receipts
+             WHERE method = ?1 AND transaction_ref = ?2 COLLATE NOCASE AND cancelled_at IS NULL
+               AND id NOT IN (SELECT receipt_id FROM statement_lines
+                              WHERE receipt_id IS NOT NULL)",
+            params![line.method, line.transaction_ref],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(receipt_id) = by_reference {
+        link(
+            db,
+            line,
+            &Receipt::get_with(db, receipt_id)?,
+            MatchKind::Reference,
+        )?;
+        return Ok(Some(MatchKind::Reference));
+    }
+
+    let guardians = line
+        .sender
+        .as_deref()
+        .and_then(|sender| phone::normalize(sender, country).ok())
+        .and_then(|phone| phones.get(&phone))
+        .cloned()
+        .unwrap_or_default();
+    let Some(&guardian_id) = guardians.first() else {
+        return Ok(None);
+    };
+
+    let by_phone: Option<i32> = db
+        .query_row(
+            "SELECT r.id FROM receipts r
+             JOIN student_relationships sr ON sr.student_id = r.student_id
+             WHERE sr.related_id = ?1 AND r.method = ?2 AND r.amount = ?3
+               AND r.transaction_ref IS NULL AND r.cancelled_at IS NULL
+               AND r.receipt_date BETWEEN ?4 AND ?5
+               AND r.id NOT IN (SELECT receipt_id FROM statement_lines
+                                WHERE receipt_id IS NOT NULL)
+             ORDER BY ABS(julianday(r.receipt_date) - julianday(?6)) ASC, r.id ASC
+             LIMIT 1",
+            params![
+                guardian_id,
+                line.method,
+                line.amount,
+                line.transaction_date - chrono::Duration::days(MATCH_DAYS),
+                line.transaction_date + chrono::Duration::days(MATCH_DAYS),
+                line.transaction_date
+            ],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(receipt_id) = by_phone {
+        db.execute(
+            "UPDATE statement_lines SET guardian_id = ?1 WHERE id = ?2",
+            params![guardian_id, line.id],
+        )?;
+        link(
+            db,
+            line,
+            &Receipt::get_with(db, receipt_id)?,
+            MatchKind::Phone,
+        )?;
+        return Ok(Some(MatchKind::Phone));
+    }
+
+    // Children in the guardian's latest session
+    let children: Vec<i32> = {
+        let mut stmt = db.prepare(
+            "SELECT s.id FROM students s
+             JOIN student_relationships sr ON sr.student_id = s.id
+             JOIN sessions se ON se.id = s.session_id
+             WHERE sr.related_id = ?1
+               AND se.start_date = (SELECT MAX(se2.start_date) FROM students s2
+                                    JOIN student_relationships sr2 ON sr2.student_id = s2.id
+                                    JOIN sessions se2 ON se2.id = s2.session_id
+                                    WHERE sr2.related_id = ?1)",
+        )?;
+        let rows = stmt.query_map(params![guardian_id], |row| row.get(0))?;
+        rows.collect::<Result<_>>()?
+    };
+    db.execute(
+        "UPDATE statement_lines SET guardian_id = ?1, student_id = ?2 WHERE id = ?3",
+        params![
+            guardian_id,
+            (children.len() == 1).then(|| children[0]),
+            line.id
+        ],
+    )?;
+    Ok(None)
+}
+
+// Adds the statement's incoming transactions and matches them to receipts.
+// Transactions already imported are skipped, so overlapping statements can
+// be imported safely.
+pub fn import_statement(method: PaymentMethod, content: &str) -> Result<StatementImport> {
+    if method == PaymentMethod::Cash {
+        return Err(reconciliation_error("Cash has no statement to import"));
+    }
+    let (entries, outgoing, invalid_lines) =
+        parse_statement(content).map_err(|e| reconciliation_error(&e))?;
+    let mut report = StatementImport {
+        method: Some(method),
+        transactions: entries.len() as i32,
+        outgoing,
+        invalid_lines,
+        ..Default::default()
+    };
+
+    // Read before locking the database, as the setting needs it
+    let country = phone::default_country();
+    let mut db = conn()?;
+    db.execute("PRAGMA foreign_keys = ON", [])?;
+    let tx = db.transaction()?;
+    let phones = guardian_phones(&tx)?;
+    let now = Local::now().naive_local();
+
+    for entry in entries {
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO statement_lines
+                (method, transaction_ref, transaction_date, sender, amount, imported_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                method,
+                entry.transaction_ref,
+                entry.transaction_date,
+                entry.sender,
+                entry.amount,
+                now
+            ],
+        )?;
+        if inserted == 0 {
+            report.duplicates += 1;
+            continue;
+        }
+        report.added += 1;
+        let line = StatementLine::get_with(&tx, tx.last_insert_rowid() as i32)?;
+        match match_line(&tx, &line, &phones, &country)? {
+            Some(MatchKind::Reference) => report.matched_by_reference += 1,
+            Some(MatchKind::Phone) => report.matched_by_phone += 1,
+            _ => report.unmatched += 1,
+        }
+    }
+
+    tx.commit()?;
+    Ok(report)
+}
+
+// Tries unmatched lines again, for payments recorded after the statement
+// was imported.
+pub fn rematch(method: Option<PaymentMethod>) -> Result<StatementImport> {
+    let country = phone::default_country();
+    let mut db = conn()?;
+    db.execute("PRAGMA foreign_keys = ON", [])?;
+    let tx = db.transaction()?;
+    let phones = guardian_phones(&tx)?;
+    let lines =
+        StatementLine::get_all_with(&tx, method, Some(StatementStatus::Unmatched), None, None)?;
+
+    let mut report = StatementImport {
+        method,
+        transactions: lines.len() as i32,
+        ..Default::default()
+    };
+    for line in lines {
+        match match_line(&tx, &line, &phones, &country)? {
+            Some(MatchKind::Reference) => report.matched_by_reference += 1,
+            Some(MatchKind::Phone) => report.matched_by_phone += 1,
+            _ => report.unmatched += 1,
+        }
+    }
+
+    tx.commit()?;
+    Ok(report)
+}
+
+// Both sides of a wallet or bank account over a period: statement lines by
+// status, and receipts taken for that method that no statement line
+// accounts for yet.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Reconciliation {
+    pub method: PaymentMethod,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub matched: Vec<StatementLine>,
+    pub unmatched: Vec<StatementLine>,
+    pub ignored: Vec<StatementLine>,
+    pub unconfirmed: Vec<Receipt>,
+    pub matched_amount: i32,
+    pub unmatched_amount: i32,
+    pub unconfirmed_amount: i32,
+}
+
+pub fn reconcile(method: PaymentMethod, from: NaiveDate, to: NaiveDate) -> Result<Reconciliation> {
+    let db = conn()?;
+    let lines = StatementLine::get_all_with(&db, Some(method), None, Some(from), Some(to))?;
+    let unconfirmed: Vec<Receipt> = {
+        let ids: Vec<i32> = {
+            let mut stmt = db.prepare(
+                "SELECT id FROM receipts
+                 WHERE method = ?1 AND receipt_date BETWEEN ?2 AND ?3 AND cancelled_at IS NULL
+                   AND id NOT IN (SELECT receipt_id FROM statement_lines
+                                  WHERE receipt_id IS NOT NULL)
+                 ORDER BY receipt_date ASC, id ASC",
+            )?;
+            let rows = stmt.query_map(params![method, from, to], |row| row.get(0))?;
+            rows.collect::<Result<_>>()?
+        };
+        ids.into_iter()
+            .map(|id| Receipt::get_with(&db, id))
+            .collect::<Result<_>>()?
+    };
+
+    let (mut matched, mut unmatched, mut ignored) = (Vec::new(), Vec::new(), Vec::new());
+    for line in lines {
+        match line.status {
+            StatementStatus::Matched => matched.push(line),
+            StatementStatus::Unmatched => unmatched.push(line),
+            StatementStatus::Ignored => ignored.push(line),
+        }
+    }
+    let total = |lines: &[StatementLine]| lines.iter().map(|l| l.amount).sum();
+    Ok(Reconciliation {
+        method,
+        from,
+        to,
+        matched_amount: total(&matched),
+        unmatched_amount: total(&unmatched),
+        unconfirmed_amount: unconfirmed.iter().map(|r| r.amount).sum(),
+        matched,
+        unmatched,
+        ignored,
+        unconfirmed,
+    })
+}
+
+// Cash taken to the bank
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CashDeposit {
+    pub id: i32,
+    pub deposit_date: NaiveDate,
+    pub amount: i32,
+    // Deposit slip number
+    pub reference: Option<String>,
+    pub remark: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl CashDeposit {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS cash_deposits (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                deposit_date DATE NOT NULL,
+                amount INTEGER NOT NULL CHECK (amount > 0),
+                reference TEXT,
+                remark TEXT,
+                created_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            deposit_date: row.get(1)?,
+            amount: row.get(2)?,
+            reference: row.get(3)?,
+            remark: row.get(4)?,
+            created_at: row.get(5)?,
+        })
+    }
+
+    // No more can be deposited than was in hand that day, nor so much that
+    // the cash in hand would go short on any later day
+    pub fn create(
+        deposit_date: NaiveDate,
+        amount: i32,
+        reference: Option<String>,
+        remark: Option<String>,
+    ) -> Result<Self> {
+        if amount <= 0 {
+            return Err(reconciliation_error("Amount must be positive"));
+        }
+        let db = conn()?;
+        let (in_hand, on) = lowest_cash_in_hand(&db, deposit_date)?;
+        if amount > in_hand {
+            return Err(reconciliation_error(&format!(
+                "Only {} Tk cash is in hand on {}",
+                in_hand.max(0),
+                on.format("%d/%m/%Y")
+            )));
+        }
+        let id: i32 = db.query_row(
+            "INSERT INTO cash_deposits (deposit_date, amount, reference, remark, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             RETURNING id",
+            params![
+                deposit_date,
+                amount,
+                reference.filter(|r| !r.trim().is_empty()),
+                remark.filter(|r| !r.trim().is_empty()),
+                Local::now().naive_local()
+            ],
+            |row| row.get(0),
+        )?;
+        db.query_row(
+            "SELECT id, deposit_date, amount, reference, remark, created_at
+             FROM cash_deposits WHERE id = ?1",
+            params![id],
+            Self::from_row,
+        )
+    }
+
+    pub fn get_all(from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(
+            "SELECT id, deposit_date, amount, reference, remark, created_at
+             FROM cash_deposits
+             WHERE (?1 IS NULL OR deposit_date >= ?1) AND (?2 IS NULL OR deposit_date <= ?2)
+             ORDER BY deposit_date ASC, id ASC",
+        )?;
+        let rows = stmt.query_map(params![from, to], Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn delete(id: i32) -> Result<()> {
+        let db = conn()?;
+        let affected = db.execute("DELETE FROM cash_deposits WHERE id = ?1", params![id])?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// Cash payments still standing, less what has been deposited or paid out,
+// up to and including `date`. Payments from before receipts recorded a
+// method were all cash.
+fn cash_in_hand(db: &Connection, date: NaiveDate) -> Result<i32> {
+    let received: i32 = db.query_row(
+        "SELECT COALESCE((SELECT SUM(p.amount) FROM payments p
+                          LEFT JOIN receipts r ON r.id = p.receipt_id
+                          WHERE COALESCE(r.method, 'CASH') = 'CASH'
+                            AND p.payment_date <= ?1), 0)
+              - COALESCE((SELECT SUM(amount) FROM cash_deposits WHERE deposit_date <= ?1), 0)",
+        params![date],
+        |row| row.get(0),
+    )?;
+    Ok(received - cash_paid_out(db, None, date)?)
+}
+
+// The least cash in hand on `from` or any later day cash came in or went
+// out, and the first day it was that low
+fn lowest_cash_in_hand(db: &Connection, from: NaiveDate) -> Result<(i32, NaiveDate)> {
+    let mut stmt = db.prepare(
+        "SELECT payment_date FROM payments WHERE payment_date > ?1
+         UNION SELECT deposit_date FROM cash_deposits WHERE deposit_date > ?1
+         UNION SELECT payment_date FROM salary_payments WHERE payment_date > ?1
+         UNION SELECT given_on FROM salary_advances WHERE given_on > ?1
+         UNION SELECT date FROM advance_recoveries WHERE date > ?1
+         ORDER BY 1",
+    )?;
+    let days = stmt
+        .query_map(params![from], |row| row.get::<_, NaiveDate>(0))?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut lowest = (cash_in_hand(db, from)?, from);
+    for day in days {
+        let in_hand = cash_in_hand(db, day)?;
+        if in_hand < lowest.0 {
+            lowest = (in_hand, day);
+        }
+    }
+    Ok(lowest)
+}
+
+// Net salaries and advances paid in cash between `from`, or the beginning,
+// and `to`, less advances paid back in hand
+fn cash_paid_out(db: &Connection, from: Option<NaiveDate>, to: NaiveDate) -> Result<i32> {
+    db.query_row(
+        "SELECT COALESCE((SELECT SUM(gross - deductions) FROM salary_payments
+                          WHERE method = 'CASH'
+                            AND (?1 IS NULL OR payment_date >= ?1) AND payment_date <= ?2), 0)
+              + COALESCE((SELECT SUM(amount) FROM salary_advances
+                          WHERE method = 'CASH'
+                            AND (?1 IS NULL OR given_on >= ?1) AND given_on <= ?2), 0)
+              - COALESCE((SELECT SUM(amount) FROM advance_recoveries
+                          WHERE salary_payment_id IS NULL
+                            AND (?1 IS NULL OR date >= ?1) AND date <= ?2), 0)",
+        params![from, to],
+        |row| row.get(0),
+    )
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CashSummary {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    // In hand at the start of `from`
+    pub opening: i32,
+    pub cash_received: i32,
+    pub deposited: i32,
+    // Salaries and advances paid in cash, less advances repaid
+    pub paid_out: i32,
+    // In hand at the end of `to`
+    pub in_hand: i32,
+    // Everything received in the period, cash included
+    pub by_method: BTreeMap<PaymentMethod, i32>,
+    pub deposits: Vec<CashDeposit>,
+}
+
+pub fn cash_summary(from: NaiveDate, to: NaiveDate) -> Result<CashSummary> {
+    let deposits = CashDeposit::get_all(Some(from), Some(to))?;
+    let db = conn()?;
+    let opening = match from.pred_opt() {
+        Some(day) => cash_in_hand(&db, day)?,
+        None => 0,
+    };
+
+    let mut by_method = BTreeMap::new();
+    {
+        let mut stmt = db.prepare(
+            "SELECT COALESCE(r.method, 'CASH'), SUM(p.amount) FROM payments p
+             LEFT JOIN receipts r ON r.id = p.receipt_id
+             WHERE p.payment_date BETWEEN ?1 AND ?2
+             GROUP BY 1",
+        )?;
+        let rows = stmt.query_map(params![from, to], |row| {
+            Ok((row.get::<_, PaymentMethod>(0)?, row.get::<_, i32>(1)?))
+        })?;
+        for row in rows {
+            let (method, amount) = row?;
+            by_method.insert(method, amount);
+        }
+    }
+
+    let cash_received = by_method.get(&PaymentMethod::Cash).copied().unwrap_or(0);
+    let deposited = deposits.iter().map(|d| d.amount).sum::<i32>();
+    let paid_out = cash_paid_out(&db, Some(from), to)?;
+    Ok(CashSummary {
+        from,
+        to,
+        opening,
+        cash_received,
+        deposited,
+        paid_out,
+        in_hand: opening + cash_received - deposited - paid_out,
+        by_method,
+        deposits,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_amount_skips_currency_prefix() {
+        assert_eq!(parse_amount("1,500.00"), Some(1500.0));
+        assert_eq!(parse_amount("Tk 1500"), Some(1500.0));
+        assert_eq!(parse_amount("Tk. 1500"), Some(1500.0));
+        assert_eq!(parse_amount("৳1,500"), Some(1500.0));
+        assert_eq!(parse_amount("1500.00 Tk."), Some(1500.0));
+        assert_eq!(parse_amount("-250"), Some(-250.0));
+        assert_eq!(parse_amount("Tk -250"), Some(-250.0));
+        assert_eq!(parse_amount(""), None);
+        assert_eq!(parse_amount("Tk."), None);
+    }
+
+    #[test]
+    fn parse_statement_with_amount_column() {
+        let csv = "Date,TrxID,From,Amount
+2025-01-05,ABC1,01711000000,Tk. 1500
+2025-01-06,ABC2,01711000001,-200
+2025-01-07,,01711000002,300
+";
+        let (entries, outgoing, invalid) = parse_statement(csv).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].amount, 1500);
+        assert_eq!(entries[0].sender.as_deref(), Some("01711000000"));
+        assert_eq!(outgoing, 1);
+        assert_eq!(invalid, vec![4]);
+    }
+
+    #[test]
+    fn parse_statement_with_debit_and_credit_columns() {
+        let csv = "Date,Transaction ID,Account,Debit,Credit
+05/01/2025,ABC1,01711000000,,1500
+06/01/2025,ABC2,01711000001,200,
+07/01/2025,ABC3,01711000002,,abc
+";
+        let (entries, outgoing, invalid) = parse_statement(csv).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].transaction_ref, "ABC1");
+        assert_eq!(entries[0].amount, 1500);
+        assert_eq!(outgoing, 1);
+        assert_eq!(invalid, vec![4]);
+    }
+}
@@ -0,0 +1,277 @@
+use super::attendance::{AttendanceStatus, AttendanceSummary};
+use super::calendar::{CalendarDay, SchoolCalendar};
+use super::conn;
+use super::student::{Attendance, Student};
+use crate::report::{self, TablePdf};
+use chrono::{Datelike, NaiveDate};
+use rusqlite::{params, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RegisterRow {
+    pub student_id: i32,
+    pub roll: i32,
+    pub name: String,
+    // One entry per column in `MonthlyRegister::days`
+    pub marks: Vec<Option<AttendanceStatus>>,
+    pub summary: AttendanceSummary,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RegisterDay {
+    pub date: NaiveDate,
+    pub counts: BTreeMap<String, i32>,
+    pub marked: i32,
+    pub unmarked: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MonthlyRegister {
+    pub session_id: i32,
+    pub class_id: i32,
+    pub section_id: Option<i32>,
+    pub class_name: String,
+    pub section_name: Option<String>,
+    pub year: i32,
+    pub month: u32,
+    // Working days only; holidays and off-days are listed separately
+    pub days: Vec<RegisterDay>,
+    pub holidays: Vec<CalendarDay>,
+    pub rows: Vec<RegisterRow>,
+    // Across all students; `unmarked_days` counts empty cells
+    pub totals: AttendanceSummary,
+}
+
+impl MonthlyRegister {
+    pub fn get(
+        session_id: i32,
+        class_id: i32,
+        section_id: Option<i32>,
+        year: i32,
+        month: u32,
+    ) -> Result<Self> {
+        let first = NaiveDate::from_ymd_opt(year, month, 1).ok_or(rusqlite::Error::InvalidQuery)?;
+        let last = first
+            .checked_add_months(chrono::Months::new(1))
+            .and_then(|d| d.pred_opt())
+            .ok_or(rusqlite::Error::InvalidQuery)?;
+
+        let (class_name, section_name) = {
+            let db = conn()?;
+            let class_name: String = db.query_row(
+                "SELECT name FROM classes WHERE id = ?1",
+                params![class_id],
+                |row| row.get(0),
+            )?;
+            let section_name: Option<String> = match section_id {
+                Some(id) => db
+                    .query_row(
+                        "SELECT name FROM sections WHERE id = ?1",
+                        params![id],
+                        |row| row.get(0),
+                    )
+                    .optional()?,
+                None => None,
+            };
+            (class_name, section_name)
+        };
+
+        let calendar = SchoolCalendar::load(session_id)?;
+        let (working, holidays): (Vec<CalendarDay>, Vec<CalendarDay>) = calendar
+            .days(first, last)
+            .into_iter()
+            .partition(|d| d.working);
+        let working_days: Vec<NaiveDate> = working.iter().map(|d| d.date).collect();
+
+        let students = Student::get(session_id, Some(class_id), section_id)?;
+        let mut rows = Vec::with_capacity(students.len());
+        let mut all = Vec::new();
+
+        for student in students {
+            let records = Attendance::get_by_student(student.id, Some(year), Some(month))?;
+            let marks: Vec<(NaiveDate, AttendanceStatus)> =
+                records.iter().map(|a| (a.date, a.status)).collect();
+
+            let cells = working_days
+                .iter()
+                .map(|date| {
+                    marks
+                        .iter()
+                        .find(|(d, _)| d == date)
+                        .map(|(_, status)| *status)
+                })
+                .collect();
+
+            let summary = AttendanceSummary::from_marks(&marks, &working_days);
+            all.extend(
+                marks
+                    .into_iter()
+                    .filter(|(d, _)| working_days.binary_search(d).is_ok()),
+            );
+
+            rows.push(RegisterRow {
+                student_id: student.id,
+                roll: student.roll,
+                name: student.name,
+                marks: cells,
+                summary,
+            });
+        }
+
+        let days = working_days
+            .iter()
+            .enumerate()
+            .map(|(i, date)| {
+                let mut day = RegisterDay {
+                    date: *date,
+                    counts: BTreeMap::new(),
+                    marked: 0,
+                    unmarked: 0,
+                };
+                for row in &rows {
+                    match row.marks[i] {
+                        Some(status) => {
+                            *day.counts.entry(status.as_str().to_string()).or_insert(0) += 1;
+                            day.marked += 1;
+                        }
+                        None => day.unmarked += 1,
+                    }
+                }
+                day
+            })
+            .collect::<Vec<_>>();
+
+        let mut totals = AttendanceSummary::from_statuses(all.into_iter().map(|(_, s)| s));
+        totals.working_days = days.len() as i32;
+        totals.unmarked_days = days.iter().map(|d| d.unmarked).sum();
+
+        Ok(Self {
+            session_id,
+            class_id,
+            section_id,
+            class_name,
+            section_name,
+            year,
+            month,
+            days,
+            holidays,
+            rows,
+            totals,
+        })
+    }
+
+    pub fn title(&self) -> String {
+        let month = NaiveDate::from_ymd_opt(self.year, self.month, 1)
+            .map(|d| d.format("%B %Y").to_string())
+            .unwrap_or_default();
+        match &self.section_name {
+            Some(section) => format!(
+                "Attendance Register - {} ({}) - {}",
+                self.class_name, section, month
+            ),
+            None => format!("Attendance Register - {} - {}", self.class_name, month),
+        }
+    }
+
+    fn headers(&self) -> Vec<String> {
+        let mut headers = vec!["Roll".to_string(), "Name".to_string()];
+        headers.extend(self.days.iter().map(|d| d.date.day().to_string()));
+        headers.extend(["P", "A", "Days", "%"].map(String::from));
+        headers
+    }
+
+    // Register rows followed by one row of per-day present counts
+    fn table(&self) -> Vec<Vec<String>> {
+        let mut table: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let mut cells = vec![row.roll.to_string(), row.name.clone()];
+                cells.extend(
+                    row.marks
+                        .iter()
+                        .map(|m| m.map(|s| s.code().to_string()).unwrap_or_default()),
+                );
+                cells.push(format_attended(row.summary.attended_days));
+                cells.push(count(&row.summary.counts, AttendanceStatus::Absent).to_string());
+                cells.push(row.summary.counted_days.to_string());
+                cells.push(format_percentage(row.summary.percentage));
+                cells
+            })
+            .collect();
+
+        let mut totals = vec![String::new(), "Present".to_string()];
+        totals.extend(self.days.iter().map(|day| {
+            let present = count(&day.counts, AttendanceStatus::Present)
+                + count(&day.counts, AttendanceStatus::Late);
+            present.to_string()
+        }));
+        totals.push(format_attended(self.totals.attended_days));
+        totals.push(count(&self.totals.counts, AttendanceStatus::Absent).to_string());
+        totals.push(self.totals.counted_days.to_string());
+        totals.push(format_percentage(self.totals.percentage));
+        table.push(totals);
+
+        table
+    }
+
+    pub fn to_csv(&self) -> std::result::Result<String, String> {
+        report::to_csv(&self.headers(), &self.table())
+    }
+
+    pub fn to_pdf(&self) -> std::result::Result<Vec<u8>, String> {
+        let mut widths = vec![9.0, 42.0];
+        widths.extend(self.days.iter().map(|_| 6.5));
+        widths.extend([9.0, 8.0, 9.0, 11.0]);
+
+        let legend = AttendanceStatus::ALL
+            .iter()
+            .map(|s| format!("{} = {}", s.code(), s.as_str().replace('_', " ")))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut subtitle = vec![format!("Working days: {}", self.days.len()), legend];
+        if !self.holidays.is_empty() {
+            let holidays = self
+                .holidays
+                .iter()
+                .filter(|d| d.reason.as_deref() != Some("Weekly off-day"))
+                .map(|d| match &d.reason {
+                    Some(reason) => format!("{} {}", d.date.day(), reason),
+                    None => d.date.day().to_string(),
+                })
+                .collect::<Vec<_>>();
+            if !holidays.is_empty() {
+                subtitle.push(format!("Holidays: {}", holidays.join(", ")));
+            }
+        }
+
+        TablePdf {
+            title: self.title(),
+            subtitle,
+            headers: self.headers(),
+            widths,
+            rows: self.table(),
+            font_size: 7.0,
+        }
+        .render()
+    }
+}
+
+fn count(counts: &BTreeMap<String, i32>, status: AttendanceStatus) -> i32 {
+    counts.get(status.as_str()).copied().unwrap_or(0)
+}
+
+// Half days make the attended count fractional
+fn format_attended(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:.1}", value)
+    }
+}
+
+fn format_percentage(value: Option<f64>) -> String {
+    value.map(|p| format!("{:.1}", p)).unwrap_or_default()
+}
@@ -0,0 +1,290 @@
+use super::conn;
+use super::history::History;
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+fn residency_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+// A stay in the hostel. The student is resident from `start_date` up to but
+// not including `end_date`, the day they moved out; an open period has none.
+// `students.is_resident` is set while a period is open.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ResidentPeriod {
+    pub id: i32,
+    pub student_id: i32,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+}
+
+impl ResidentPeriod {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS resident_periods (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                student_id INTEGER NOT NULL,
+                start_date DATE NOT NULL,
+                end_date DATE,
+                FOREIGN KEY (student_id) REFERENCES students(id) ON DELETE CASCADE,
+                CHECK (end_date IS NULL OR end_date > start_date)
+            )",
+            [],
+        )?;
+        // Students flagged resident before stays were recorded have lived in
+        // since admission
+        db.execute(
+            "INSERT INTO resident_periods (student_id, start_date)
+             SELECT s.id, s.admission_date FROM students s
+             WHERE s.is_resident
+               AND NOT EXISTS (SELECT 1 FROM resident_periods p WHERE p.student_id = s.id)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            student_id: row.get(1)?,
+            start_date: row.get(2)?,
+            end_date: row.get(3)?,
+        })
+    }
+
+    pub(super) fn get_with(db: &Connection, student_id: i32) -> Result<Vec<Self>> {
+        let mut stmt = db.prepare(
+            "SELECT id, student_id, start_date, end_date FROM resident_periods
+             WHERE student_id = ?1
+             ORDER BY start_date ASC",
+        )?;
+        let rows = stmt.query_map(params![student_id], Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn get(student_id: i32) -> Result<Vec<Self>> {
+        let db = conn()?;
+        Self::get_with(&db, student_id)
+    }
+
+    fn open_with(db: &Connection, student_id: i32) -> Result<Option<Self>> {
+        db.query_row(
+            "SELECT id, student_id, start_date, end_date FROM resident_periods
+             WHERE student_id = ?1 AND end_date IS NULL",
+            params![student_id],
+            Self::from_row,
+        )
+        .optional()
+    }
+
+    fn last_end_with(db: &Connection, student_id: i32) -> Result<Option<NaiveDate>> {
+        db.query_row(
+            "SELECT MAX(end_date) FROM resident_periods WHERE student_id = ?1",
+            params![student_id],
+            |row| row.get(0),
+        )
+    }
+
+    // Opens or closes a stay on `date` when the resident flag is changed
+    // from the student form
+    pub(super) fn sync(
+        db: &Connection,
+        student_id: i32,
+        is_resident: bool,
+        date: NaiveDate,
+    ) -> Result<()> {
+        match (is_resident, Self::open_with(db, student_id)?) {
+            (true, None) => {
+                let start = match Self::last_end_with(db, student_id)? {
+                    Some(last_end) => date.max(last_end),
+                    None => date,
+                };
+                db.execute(
+                    "INSERT INTO resident_periods (student_id, start_date) VALUES (?1, ?2)",
+                    params![student_id, start],
+                )?;
+            }
+            (false, Some(open)) => Self::close_with(db, &open, date)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Joins stays that overlap or meet, as happens when two records of the
+    // same student are merged
+    pub(super) fn coalesce_with(db: &Connection, student_id: i32) -> Result<()> {
+        let mut current: Option<Self> = None;
+        for period in Self::get_with(db, student_id)? {
+            match current.as_mut() {
+                Some(stay) if stay.end_date.map_or(true, |end| period.start_date <= end) => {
+                    stay.end_date = match (stay.end_date, period.end_date) {
+                        (Some(a), Some(b)) => Some(a.max(b)),
+                        _ => None,
+                    };
+                    db.execute(
+                        "DELETE FROM resident_periods WHERE id = ?1",
+                        params![period.id],
+                    )?;
+                    db.execute(
+                        "UPDATE resident_periods SET end_date = ?1 WHERE id = ?2",
+                        params![stay.end_date, stay.id],
+                    )?;
+                }
+                _ => current = Some(period),
+            }
+        }
+        Ok(())
+    }
+
+    // A stay closed on the day it began never happened
+    fn close_with(db: &Connection, period: &Self, date: NaiveDate) -> Result<()> {
+        if date <= period.start_date {
+            db.execute(
+                "DELETE FROM resident_periods WHERE id = ?1",
+                params![period.id],
+            )?;
+        } else {
+            db.execute(
+                "UPDATE resident_periods SET end_date = ?1 WHERE id = ?2",
+                params![date, period.id],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn start(student_id: i32, date: NaiveDate) -> Result<Self> {
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+
+        let admission_date: NaiveDate = tx.query_row(
+            "SELECT admission_date FROM students WHERE id = ?1",
+            params![student_id],
+            |row| row.get(0),
+        )?;
+        if Self::open_with(&tx, student_id)?.is_some() {
+            return Err(residency_error("The student is already resident"));
+        }
+        if date < admission_date {
+            return Err(residency_error(&format!(
+                "Residency cannot start before admission on {}",
+                admission_date
+            )));
+        }
+        if let Some(last_end) = Self::last_end_with(&tx, student_id)? {
+            if date < last_end {
+                return Err(residency_error(&format!(
+                    "An earlier stay lasted until {}",
+                    last_end
+                )));
+            }
+        }
+
+        let id: i32 = tx.query_row(
+            "INSERT INTO resident_periods (student_id, start_date) VALUES (?1, ?2)
+             RETURNING id",
+            params![student_id, date],
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "UPDATE students SET is_resident = 1 WHERE id = ?1",
+            params![student_id],
+        )?;
+        History::record(
+            &tx,
+            "student",
+            student_id,
+            "residency",
+            Some(format!("Resident from {}", date)),
+        )?;
+
+        tx.commit()?;
+        Ok(Self {
+            id,
+            student_id,
+            start_date: date,
+            end_date: None,
+        })
+    }
+
+    // `date` is the day the student moved out, which is not charged
+    pub fn end(student_id: i32, date: NaiveDate) -> Result<()> {
+        let mut db = conn()?;
+        let tx = db.transaction()?;
+
+        let Some(open) = Self::open_with(&tx, student_id)? else {
+            return Err(residency_error("The student is not resident"));
+        };
+        if date < open.start_date {
+            return Err(residency_error(&format!(
+                "Residency cannot end before it started on {}",
+                open.start_date
+            )));
+        }
+        Self::close_with(&tx, &open, date)?;
+        tx.execute(
+            "UPDATE students SET is_resident = 0 WHERE id = ?1",
+            params![student_id],
+        )?;
+        History::record(
+            &tx,
+            "student",
+            student_id,
+            "residency",
+            Some(format!("Moved out on {}", date)),
+        )?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    // For a stay recorded by mistake. Months already billed keep their lines.
+    pub fn delete(id: i32) -> Result<()> {
+        let mut db = conn()?;
+        let tx = db.transaction()?;
+
+        let period = tx.query_row(
+            "SELECT id, student_id, start_date, end_date FROM resident_periods WHERE id = ?1",
+            params![id],
+            Self::from_row,
+        )?;
+        tx.execute("DELETE FROM resident_periods WHERE id = ?1", params![id])?;
+        if period.end_date.is_none() {
+            tx.execute(
+                "UPDATE students SET is_resident = 0 WHERE id = ?1",
+                params![period.student_id],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+// Days of [from, to) spent in residence, and the first of them
+pub fn resident_days(
+    periods: &[ResidentPeriod],
+    from: NaiveDate,
+    to: NaiveDate,
+) -> (i64, Option<NaiveDate>) {
+    let mut days = 0;
+    let mut first: Option<NaiveDate> = None;
+    for period in periods {
+        let start = period.start_date.max(from);
+        let end = period.end_date.map_or(to, |end| end.min(to));
+        if start < end {
+            days += (end - start).num_days();
+            first = Some(first.map_or(start, |f| f.min(start)));
+        }
+    }
+    (days, first)
+}
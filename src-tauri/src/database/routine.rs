@@ -0,0 +1,675 @@
+use super::attendance::{ensure_unlocked, AttendanceStatus};
+use super::conn;
+use super::history::History;
+use super::settings::Setting;
+use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
+use rusqlite::{params, Connection, OptionalExtension, Result, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const DAILY_STATUS_RULE_KEY: &str = "period_daily_status_rule";
+
+fn routine_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClassRoutine {
+    pub id: i32,
+    pub session_id: i32,
+    pub class_id: i32,
+    pub section_id: Option<i32>,
+    pub weekday: Weekday,
+    pub period: i32,
+    pub subject_id: i32,
+    pub teacher_id: Option<i32>,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+}
+
+const ROUTINE_COLUMNS: &str = "id, session_id, class_id, section_id, weekday, period, subject_id,
+     teacher_id, start_time, end_time";
+
+impl ClassRoutine {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS class_routines (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id INTEGER NOT NULL,
+                class_id INTEGER NOT NULL,
+                section_id INTEGER,
+                weekday INTEGER NOT NULL CHECK (weekday BETWEEN 0 AND 6),
+                period INTEGER NOT NULL,
+                subject_id INTEGER NOT NULL,
+                teacher_id INTEGER,
+                start_time TIME NOT NULL,
+                end_time TIME NOT NULL,
+                FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE,
+                FOREIGN KEY (class_id) REFERENCES classes(id) ON DELETE CASCADE,
+                FOREIGN KEY (section_id) REFERENCES sections(id) ON DELETE CASCADE,
+                FOREIGN KEY (subject_id) REFERENCES subjects(id) ON DELETE CASCADE,
+                FOREIGN KEY (teacher_id) REFERENCES staffs(id) ON DELETE SET NULL,
+                CHECK (end_time > start_time)
+            )",
+            [],
+        )?;
+        // One period per slot, including for classes without sections
+        db.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_class_routines_slot
+             ON class_routines (session_id, class_id, COALESCE(section_id, 0), weekday, period)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        let weekday: u8 = row.get(4)?;
+        Ok(Self {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            class_id: row.get(2)?,
+            section_id: row.get(3)?,
+            weekday: Weekday::try_from(weekday).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    4,
+                    rusqlite::types::Type::Integer,
+                    Box::new(e),
+                )
+            })?,
+            period: row.get(5)?,
+            subject_id: row.get(6)?,
+            teacher_id: row.get(7)?,
+            start_time: row.get(8)?,
+            end_time: row.get(9)?,
+        })
+    }
+
+    fn get_with(db: &Connection, id: i32) -> Result<Self> {
+        db.query_row(
+            &format!(
+                "SELECT {} FROM class_routines WHERE id = ?1",
+                ROUTINE_COLUMNS
+            ),
+            params![id],
+            Self::from_row,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        session_id: i32,
+        class_id: i32,
+        section_id: Option<i32>,
+        weekday: Weekday,
+        period: i32,
+        subject_id: i32,
+        teacher_id: Option<i32>,
+        start_time: NaiveTime,
+        end_time: NaiveTime,
+    ) -> Result<Self> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "INSERT INTO class_routines (session_id, class_id, section_id, weekday, period,
+                subject_id, teacher_id, start_time, end_time)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                session_id,
+                class_id,
+                section_id,
+                weekday.num_days_from_monday(),
+                period,
+                subject_id,
+                teacher_id,
+                start_time,
+                end_time
+            ],
+        )?;
+
+        let id = db.last_insert_rowid() as i32;
+        Self::get_with(&db, id)
+    }
+
+    pub fn get(
+        session_id: i32,
+        class_id: i32,
+        section_id: Option<i32>,
+        weekday: Option<Weekday>,
+    ) -> Result<Vec<Self>> {
+        let db = conn()?;
+
+        let mut query = format!(
+            "SELECT {} FROM class_routines WHERE session_id = ? AND class_id = ?",
+            ROUTINE_COLUMNS
+        );
+        let mut params_c: Vec<Box<dyn rusqlite::ToSql>> =
+            vec![Box::new(session_id), Box::new(class_id)];
+
+        if let Some(section_id) = section_id {
+            query.push_str(" AND section_id = ?");
+            params_c.push(Box::new(section_id));
+        }
+
+        if let Some(weekday) = weekday {
+            query.push_str(" AND weekday = ?");
+            params_c.push(Box::new(weekday.num_days_from_monday()));
+        }
+
+        query.push_str(" ORDER BY weekday ASC, period ASC");
+
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params_c.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = db.prepare(&query)?;
+        let rows = stmt.query_map(param_refs.as_slice(), Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn get_by_teacher(session_id: i32, teacher_id: i32) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(&format!(
+            "SELECT {} FROM class_routines WHERE session_id = ?1 AND teacher_id = ?2
+             ORDER BY weekday ASC, start_time ASC",
+            ROUTINE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![session_id, teacher_id], Self::from_row)?;
+        rows.collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn edit(
+        id: i32,
+        weekday: Weekday,
+        period: i32,
+        subject_id: i32,
+        teacher_id: Option<i32>,
+        start_time: NaiveTime,
+        end_time: NaiveTime,
+    ) -> Result<Self> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let affected = db.execute(
+            "UPDATE class_routines
+             SET weekday = ?1, period = ?2, subject_id = ?3, teacher_id = ?4,
+                 start_time = ?5, end_time = ?6
+             WHERE id = ?7",
+            params![
+                weekday.num_days_from_monday(),
+                period,
+                subject_id,
+                teacher_id,
+                start_time,
+                end_time,
+                id
+            ],
+        )?;
+
+        if affected == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+
+        Self::get_with(&db, id)
+    }
+
+    pub fn delete(id: i32) -> Result<()> {
+        let db = conn()?;
+        let affected = db.execute("DELETE FROM class_routines WHERE id = ?1", params![id])?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// How the daily `attendance` row is derived from a student's period marks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DailyStatusRule {
+    // Period marks never touch the daily register
+    Manual,
+    // The first period taken decides the day
+    FirstPeriod,
+    // Present when any period was attended
+    AnyPeriod,
+    // Present when at least half of the periods taken were attended
+    Majority,
+}
+
+impl DailyStatusRule {
+    pub const ALL: [Self; 4] = [
+        Self::Manual,
+        Self::FirstPeriod,
+        Self::AnyPeriod,
+        Self::Majority,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Manual => "MANUAL",
+            Self::FirstPeriod => "FIRST_PERIOD",
+            Self::AnyPeriod => "ANY_PERIOD",
+            Self::Majority => "MAJORITY",
+        }
+    }
+
+    pub fn load() -> Result<Self> {
+        let value = Setting::get_or(DAILY_STATUS_RULE_KEY, Self::Majority.as_str())?;
+        Ok(Self::ALL
+            .into_iter()
+            .find(|r| r.as_str() == value)
+            .unwrap_or(Self::Majority))
+    }
+
+    pub fn save(&self) -> Result<Self> {
+        Setting::set(DAILY_STATUS_RULE_KEY, self.as_str())?;
+        Ok(*self)
+    }
+
+    // `statuses` are in period order
+    pub fn derive(&self, statuses: &[AttendanceStatus]) -> Option<AttendanceStatus> {
+        let first = *statuses.first()?;
+
+        // Days made up only of excused or leave periods keep that status
+        let counted: Vec<f64> = statuses.iter().filter_map(|s| s.weight()).collect();
+        if counted.is_empty() {
+            return Some(first);
+        }
+
+        let attended = match self {
+            Self::Manual => return None,
+            Self::FirstPeriod => return Some(first),
+            Self::AnyPeriod => counted.iter().any(|w| *w > 0.0),
+            Self::Majority => counted.iter().sum::<f64>() * 2.0 >= counted.len() as f64,
+        };
+
+        Some(match (attended, first) {
+            (true, AttendanceStatus::Late) => AttendanceStatus::Late,
+            (true, _) => AttendanceStatus::Present,
+            (false, _) => AttendanceStatus::Absent,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PeriodEntry {
+    pub student_id: i32,
+    pub status: AttendanceStatus,
+    pub remark: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PeriodAttendance {
+    pub id: i32,
+    pub student_id: i32,
+    pub routine_id: i32,
+    pub date: NaiveDate,
+    pub subject_id: i32,
+    pub teacher_id: Option<i32>,
+    pub status: AttendanceStatus,
+    pub remark: Option<String>,
+}
+
+const PERIOD_COLUMNS: &str =
+    "id, student_id, routine_id, date, subject_id, teacher_id, status, remark";
+
+impl PeriodAttendance {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS period_attendance (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    student_id INTEGER NOT NULL,
+                    routine_id INTEGER NOT NULL,
+                    date DATE NOT NULL,
+                    subject_id INTEGER NOT NULL,
+                    teacher_id INTEGER,
+                    status TEXT NOT NULL {},
+                    remark TEXT,
+                    FOREIGN KEY (student_id) REFERENCES students(id) ON DELETE CASCADE,
+                    FOREIGN KEY (routine_id) REFERENCES class_routines(id) ON DELETE CASCADE,
+                    FOREIGN KEY (subject_id) REFERENCES subjects(id) ON DELETE CASCADE,
+                    FOREIGN KEY (teacher_id) REFERENCES staffs(id) ON DELETE SET NULL,
+                    UNIQUE (student_id, routine_id, date)
+                )",
+                AttendanceStatus::check_constraint()
+            ),
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            student_id: row.get(1)?,
+            routine_id: row.get(2)?,
+            date: row.get(3)?,
+            subject_id: row.get(4)?,
+            teacher_id: row.get(5)?,
+            status: row.get(6)?,
+            remark: row.get(7)?,
+        })
+    }
+
+    // Records one period for the listed students. `teacher_id` is the teacher
+    // who actually took the period and defaults to the routine's teacher, so
+    // substitutes are kept apart. The daily register is then updated from
+    // all of the day's period marks according to `DailyStatusRule`.
+    pub fn mark(
+        routine_id: i32,
+        date: NaiveDate,
+        teacher_id: Option<i32>,
+        entries: Vec<PeriodEntry>,
+    ) -> Result<Vec<Self>> {
+        let rule = DailyStatusRule::load()?;
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+        ensure_unlocked(&tx, date)?;
+
+        let routine = ClassRoutine::get_with(&tx, routine_id)?;
+        if routine.weekday != date.weekday() {
+            return Err(routine_error(&format!(
+                "Routine slot is on {} but {} is a {}",
+                routine.weekday,
+                date,
+                date.weekday()
+            )));
+        }
+
+        let student_ids: Vec<i32> = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM students
+                 WHERE session_id = ?1 AND class_id = ?2 AND (?3 IS NULL OR section_id = ?3)",
+            )?;
+            let rows = stmt.query_map(
+                params![routine.session_id, routine.class_id, routine.section_id],
+                |row| row.get(0),
+            )?;
+            rows.collect::<Result<_>>()?
+        };
+        for entry in &entries {
+            if !student_ids.contains(&entry.student_id) {
+                return Err(routine_error(&format!(
+                    "Student {} is not in the class/section of this routine slot.",
+                    entry.student_id
+                )));
+            }
+        }
+        let teacher_id = teacher_id.or(routine.teacher_id);
+
+        let mut records = Vec::with_capacity(entries.len());
+        for entry in &entries {
+            let record = tx.query_row(
+                &format!(
+                    "INSERT INTO period_attendance
+                        (student_id, routine_id, date, subject_id, teacher_id, status, remark)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                     ON CONFLICT(student_id, routine_id, date) DO UPDATE SET
+                        subject_id = excluded.subject_id,
+                        teacher_id = excluded.teacher_id,
+                        status = excluded.status,
+                        remark = excluded.remark
+                     RETURNING {}",
+                    PERIOD_COLUMNS
+                ),
+                params![
+                    entry.student_id,
+                    routine_id,
+                    date,
+                    routine.subject_id,
+                    teacher_id,
+                    entry.status,
+                    entry.remark
+                ],
+                Self::from_row,
+            )?;
+            records.push(record);
+        }
+
+        for entry in &entries {
+            derive_daily(&tx, rule, entry.student_id, date)?;
+        }
+
+        tx.commit()?;
+        Ok(records)
+    }
+
+    pub fn get_by_routine(routine_id: i32, date: NaiveDate) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(&format!(
+            "SELECT {} FROM period_attendance WHERE routine_id = ?1 AND date = ?2
+             ORDER BY student_id ASC",
+            PERIOD_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![routine_id, date], Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn get_by_student(student_id: i32, date: NaiveDate) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(
+            "SELECT p.id, p.student_id, p.routine_id, p.date, p.subject_id, p.teacher_id,
+                    p.status, p.remark
+             FROM period_attendance p
+             JOIN class_routines r ON p.routine_id = r.id
+             WHERE p.student_id = ?1 AND p.date = ?2
+             ORDER BY r.period ASC",
+        )?;
+        let rows = stmt.query_map(params![student_id, date], Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn delete(id: i32) -> Result<()> {
+        let rule = DailyStatusRule::load()?;
+        let mut db = conn()?;
+        let tx = db.transaction()?;
+
+        let (student_id, date): (i32, NaiveDate) = tx
+            .query_row(
+                "DELETE FROM period_attendance WHERE id = ?1 RETURNING student_id, date",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        ensure_unlocked(&tx, date)?;
+
+        derive_daily(&tx, rule, student_id, date)?;
+        tx.commit()
+    }
+}
+
+// Rewrites the daily status from the period marks. The daily remark and
+// arrival time are left as they are; a day with no period marks left is not
+// touched. Replacing a different status, which may have been marked by hand,
+// is recorded in the student's history.
+fn derive_daily(
+    tx: &Transaction,
+    rule: DailyStatusRule,
+    student_id: i32,
+    date: NaiveDate,
+) -> Result<()> {
+    let statuses: Vec<AttendanceStatus> = {
+        let mut stmt = tx.prepare(
+            "SELECT p.status FROM period_attendance p
+             JOIN class_routines r ON p.routine_id = r.id
+             WHERE p.student_id = ?1 AND p.date = ?2
+             ORDER BY r.period ASC",
+        )?;
+        let rows = stmt.query_map(params![student_id, date], |row| row.get(0))?;
+        rows.collect::<Result<_>>()?
+    };
+
+    let Some(status) = rule.derive(&statuses) else {
+        return Ok(());
+    };
+    let previous: Option<AttendanceStatus> = tx
+        .query_row(
+            "SELECT status FROM attendance WHERE student_id = ?1 AND date = ?2",
+            params![student_id, date],
+            |row| row.get(0),
+        )
+        .optional()?;
+    tx.execute(
+        "INSERT INTO attendance (student_id, date, status) VALUES (?1, ?2, ?3)
+         ON CONFLICT(student_id, date) DO UPDATE SET status = excluded.status",
+        params![student_id, date, status],
+    )?;
+    if let Some(previous) = previous.filter(|p| *p != status) {
+        History::record(
+            tx,
+            "student",
+            student_id,
+            "attendance",
+            Some(format!(
+                "{} changed from {} to {} by period attendance",
+                date,
+                previous.as_str(),
+                status.as_str()
+            )),
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SubjectAttendance {
+    pub subject_id: i32,
+    pub subject_name: String,
+    pub counts: BTreeMap<String, i32>,
+    pub marked: i32,
+    pub counted: i32,
+    pub attended: f64,
+    pub percentage: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StudentSubjectAttendance {
+    pub student_id: i32,
+    pub name: String,
+    pub roll: i32,
+    pub subjects: Vec<SubjectAttendance>,
+}
+
+// Subject-wise attendance for every student of a class or section
+pub fn subject_report(
+    session_id: i32,
+    class_id: i32,
+    section_id: Option<i32>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<StudentSubjectAttendance>> {
+    let db = conn()?;
+
+    let mut students: Vec<StudentSubjectAttendance> = {
+        let mut stmt = db.prepare(
+            "SELECT id, name, roll FROM students
+             WHERE session_id = ?1 AND class_id = ?2 AND (?3 IS NULL OR section_id = ?3)
+             ORDER BY roll ASC",
+        )?;
+        let rows = stmt.query_map(params![session_id, class_id, section_id], |row| {
+            Ok(StudentSubjectAttendance {
+                student_id: row.get(0)?,
+                name: row.get(1)?,
+                roll: row.get(2)?,
+                subjects: Vec::new(),
+            })
+        })?;
+        rows.collect::<Result<_>>()?
+    };
+
+    let mut stmt = db.prepare(
+        "SELECT p.student_id, p.subject_id, sub.name, p.status
+         FROM period_attendance p
+         JOIN students s ON p.student_id = s.id
+         JOIN subjects sub ON p.subject_id = sub.id
+         WHERE s.session_id = ?1 AND s.class_id = ?2 AND (?3 IS NULL OR s.section_id = ?3)
+         AND p.date >= ?4 AND p.date <= ?5
+         ORDER BY sub.name ASC",
+    )?;
+    let rows = stmt.query_map(params![session_id, class_id, section_id, from, to], |row| {
+        Ok((
+            row.get::<_, i32>(0)?,
+            row.get::<_, i32>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, AttendanceStatus>(3)?,
+        ))
+    })?;
+
+    for row in rows {
+        let (student_id, subject_id, subject_name, status) = row?;
+        let Some(student) = students.iter_mut().find(|s| s.student_id == student_id) else {
+            continue;
+        };
+        let index = match student
+            .subjects
+            .iter()
+            .position(|s| s.subject_id == subject_id)
+        {
+            Some(index) => index,
+            None => {
+                student.subjects.push(SubjectAttendance {
+                    subject_id,
+                    subject_name,
+                    counts: BTreeMap::new(),
+                    marked: 0,
+                    counted: 0,
+                    attended: 0.0,
+                    percentage: None,
+                });
+                student.subjects.len() - 1
+            }
+        };
+        let subject = &mut student.subjects[index];
+        *subject
+            .counts
+            .entry(status.as_str().to_string())
+            .or_insert(0) += 1;
+        subject.marked += 1;
+        if let Some(weight) = status.weight() {
+            subject.counted += 1;
+            subject.attended += weight;
+        }
+    }
+
+    for subject in students.iter_mut().flat_map(|s| s.subjects.iter_mut()) {
+        if subject.counted > 0 {
+            let percentage = subject.attended * 100.0 / subject.counted as f64;
+            subject.percentage = Some((percentage * 100.0).round() / 100.0);
+        }
+    }
+
+    Ok(students)
+}
+
+// Subject-wise attendance of one student
+pub fn subject_summary(
+    student_id: i32,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<SubjectAttendance>> {
+    let (session_id, class_id, section_id): (i32, i32, Option<i32>) = {
+        let db = conn()?;
+        db.query_row(
+            "SELECT session_id, class_id, section_id FROM students WHERE id = ?1",
+            params![student_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?
+    };
+
+    Ok(subject_report(session_id, class_id, section_id, from, to)?
+        .into_iter()
+        .find(|s| s.student_id == student_id)
+        .map(|s| s.subjects)
+        .unwrap_or_default())
+}
@@ -0,0 +1,78 @@
+use super::conn;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+// School details printed on cards, receipts and reports
+pub const SCHOOL_NAME_KEY: &str = "school_name";
+pub const SCHOOL_ADDRESS_KEY: &str = "school_address";
+// Image as a data URL, like photos
+pub const SCHOOL_LOGO_KEY: &str = "school_logo";
+// Path of a TrueType font with Bangla glyphs for receipts; a system font
+// is looked for when unset
+pub const BANGLA_FONT_KEY: &str = "bangla_font";
+// Monthly hostel fee in taka, unless a student has their own
+pub const DEFAULT_RESIDENT_FEE_KEY: &str = "default_resident_fee";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Setting {
+    pub key: String,
+    pub value: String,
+}
+
+impl Setting {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    pub fn get(key: &str) -> Result<Option<String>> {
+        let db = conn()?;
+        Self::read(&db, key)
+    }
+
+    // For callers that already hold the connection
+    pub fn read(db: &Connection, key: &str) -> Result<Option<String>> {
+        db.query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![key],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    pub fn get_or(key: &str, default: &str) -> Result<String> {
+        Ok(Self::get(key)?.unwrap_or_else(|| default.to_string()))
+    }
+
+    pub fn get_all() -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare("SELECT key, value FROM settings ORDER BY key ASC")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Self {
+                key: row.get(0)?,
+                value: row.get(1)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn set(key: &str, value: &str) -> Result<Self> {
+        let db = conn()?;
+        db.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(Self {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
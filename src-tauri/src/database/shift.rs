@@ -0,0 +1,461 @@
+use super::attendance::{ensure_unlocked, AttendanceStatus, AttendanceSummary};
+use super::calendar::{working_days_in, SchoolCalendar};
+use super::conn;
+use super::settings::Setting;
+use super::staff::{Attendance, ATTENDANCE_COLUMNS};
+use chrono::{Duration, NaiveDate, NaiveTime};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+// The default shift, used for staff without one assigned
+pub const SHIFT_START_KEY: &str = "staff_shift_start";
+pub const SHIFT_END_KEY: &str = "staff_shift_end";
+pub const LATE_GRACE_KEY: &str = "staff_late_grace_minutes";
+pub const DEFAULT_SHIFT_START: &str = "09:00";
+pub const DEFAULT_SHIFT_END: &str = "17:00";
+pub const DEFAULT_LATE_GRACE_MINUTES: i32 = 10;
+
+fn shift_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+            extended_code: 0,
+        },
+        Some(message.to_string()),
+    )
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Shift {
+    // 0 for the default shift from settings
+    pub id: i32,
+    pub name: String,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub grace_minutes: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StaffShift {
+    pub staff_id: i32,
+    pub staff_name: String,
+    pub shift: Shift,
+}
+
+impl Shift {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS shifts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                start_time TIME NOT NULL,
+                end_time TIME NOT NULL,
+                grace_minutes INTEGER NOT NULL DEFAULT 0 CHECK (grace_minutes >= 0),
+                CHECK (end_time > start_time)
+            )",
+            [],
+        )?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS staff_shifts (
+                staff_id INTEGER PRIMARY KEY,
+                shift_id INTEGER NOT NULL,
+                FOREIGN KEY (staff_id) REFERENCES staffs(id) ON DELETE CASCADE,
+                FOREIGN KEY (shift_id) REFERENCES shifts(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            start_time: row.get(2)?,
+            end_time: row.get(3)?,
+            grace_minutes: row.get(4)?,
+        })
+    }
+
+    pub fn create(
+        name: &str,
+        start_time: NaiveTime,
+        end_time: NaiveTime,
+        grace_minutes: i32,
+    ) -> Result<Self> {
+        let db = conn()?;
+        db.execute(
+            "INSERT INTO shifts (name, start_time, end_time, grace_minutes) VALUES (?1, ?2, ?3, ?4)",
+            params![name, start_time, end_time, grace_minutes],
+        )?;
+
+        Ok(Self {
+            id: db.last_insert_rowid() as i32,
+            name: name.to_string(),
+            start_time,
+            end_time,
+            grace_minutes,
+        })
+    }
+
+    pub fn get_all() -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(
+            "SELECT id, name, start_time, end_time, grace_minutes FROM shifts ORDER BY start_time ASC",
+        )?;
+        let rows = stmt.query_map([], Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn edit(
+        id: i32,
+        name: &str,
+        start_time: NaiveTime,
+        end_time: NaiveTime,
+        grace_minutes: i32,
+    ) -> Result<Self> {
+        let db = conn()?;
+        let affected = db.execute(
+            "UPDATE shifts SET name = ?1, start_time = ?2, end_time = ?3, grace_minutes = ?4
+             WHERE id = ?5",
+            params![name, start_time, end_time, grace_minutes, id],
+        )?;
+        if affected == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+
+        Ok(Self {
+            id,
+            name: name.to_string(),
+            start_time,
+            end_time,
+            grace_minutes,
+        })
+    }
+
+    pub fn delete(id: i32) -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let affected = db.execute("DELETE FROM shifts WHERE id = ?1", params![id])?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Without a shift the staff member falls back to the default shift
+    pub fn assign(staff_id: i32, shift_id: Option<i32>) -> Result<Self> {
+        {
+            let db = conn()?;
+            db.execute("PRAGMA foreign_keys = ON", [])?;
+            match shift_id {
+                Some(shift_id) => db.execute(
+                    "INSERT INTO staff_shifts (staff_id, shift_id) VALUES (?1, ?2)
+                     ON CONFLICT(staff_id) DO UPDATE SET shift_id = excluded.shift_id",
+                    params![staff_id, shift_id],
+                )?,
+                None => db.execute(
+                    "DELETE FROM staff_shifts WHERE staff_id = ?1",
+                    params![staff_id],
+                )?,
+            };
+        }
+        Self::get_for_staff(staff_id)
+    }
+
+    pub fn get_for_staff(staff_id: i32) -> Result<Self> {
+        let db = conn()?;
+        Self::for_staff(&db, staff_id)
+    }
+
+    pub fn get_assignments() -> Result<Vec<StaffShift>> {
+        let db = conn()?;
+        let staff: Vec<(i32, String)> = {
+            let mut stmt = db.prepare("SELECT id, name FROM staffs ORDER BY name ASC")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_>>()?
+        };
+
+        staff
+            .into_iter()
+            .map(|(staff_id, staff_name)| {
+                Ok(StaffShift {
+                    staff_id,
+                    staff_name,
+                    shift: Self::for_staff(&db, staff_id)?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn default_with(db: &Connection) -> Result<Self> {
+        let time = |key: &str, default: &str| -> Result<NaiveTime> {
+            Ok(Setting::read(db, key)?
+                .and_then(|v| NaiveTime::parse_from_str(&v, "%H:%M").ok())
+                .unwrap_or_else(|| NaiveTime::parse_from_str(default, "%H:%M").unwrap()))
+        };
+
+        Ok(Self {
+            id: 0,
+            name: "Default".to_string(),
+            start_time: time(SHIFT_START_KEY, DEFAULT_SHIFT_START)?,
+            end_time: time(SHIFT_END_KEY, DEFAULT_SHIFT_END)?,
+            grace_minutes: Setting::read(db, LATE_GRACE_KEY)?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_LATE_GRACE_MINUTES),
+        })
+    }
+
+    pub fn for_staff(db: &Connection, staff_id: i32) -> Result<Self> {
+        let assigned = db
+            .query_row(
+                "SELECT s.id, s.name, s.start_time, s.end_time, s.grace_minutes
+                 FROM staff_shifts ss JOIN shifts s ON ss.shift_id = s.id
+                 WHERE ss.staff_id = ?1",
+                params![staff_id],
+                Self::from_row,
+            )
+            .optional()?;
+
+        match assigned {
+            Some(shift) => Ok(shift),
+            None => Self::default_with(db),
+        }
+    }
+
+    // Check-in after this time counts as late
+    pub fn late_after(&self) -> NaiveTime {
+        self.start_time + Duration::minutes(self.grace_minutes as i64)
+    }
+
+    // Late minutes are counted from the shift start once the grace period is
+    // exceeded.
+    pub fn timings(&self, check_in: Option<NaiveTime>, check_out: Option<NaiveTime>) -> Timings {
+        let minutes = |d: Duration| d.num_minutes().max(0) as i32;
+
+        let late_minutes = match check_in {
+            Some(time) if time > self.late_after() => minutes(time - self.start_time),
+            _ => 0,
+        };
+        let (early_departure_minutes, overtime_minutes) = match check_out {
+            Some(time) => (minutes(self.end_time - time), minutes(time - self.end_time)),
+            None => (0, 0),
+        };
+
+        Timings {
+            late_minutes,
+            early_departure_minutes,
+            overtime_minutes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Timings {
+    pub late_minutes: i32,
+    pub early_departure_minutes: i32,
+    pub overtime_minutes: i32,
+}
+
+fn get_attendance(db: &Connection, id: i32) -> Result<Attendance> {
+    db.query_row(
+        &format!(
+            "SELECT {} FROM attendance_staff WHERE id = ?1",
+            ATTENDANCE_COLUMNS
+        ),
+        params![id],
+        Attendance::from_row,
+    )
+}
+
+// Recomputes the late, early departure and overtime minutes of a staff
+// attendance row from its times and the staff member's shift. Days not
+// worked carry no timings.
+pub fn apply_timings(db: &Connection, attendance_id: i32) -> Result<Attendance> {
+    let record = get_attendance(db, attendance_id)?;
+    let timings = match record.status {
+        AttendanceStatus::Present | AttendanceStatus::Late | AttendanceStatus::HalfDay => {
+            Shift::for_staff(db, record.staff_id)?
+                .timings(record.arrival_time, record.departure_time)
+        }
+        _ => Timings::default(),
+    };
+
+    db.execute(
+        "UPDATE attendance_staff
+         SET late_minutes = ?1, early_departure_minutes = ?2, overtime_minutes = ?3
+         WHERE id = ?4",
+        params![
+            timings.late_minutes,
+            timings.early_departure_minutes,
+            timings.overtime_minutes,
+            attendance_id
+        ],
+    )?;
+
+    get_attendance(db, attendance_id)
+}
+
+// Records arrival. A day already marked without a time (for instance absent
+// by a log import) is turned into PRESENT or LATE.
+pub fn check_in(staff_id: i32, date: NaiveDate, time: NaiveTime) -> Result<Attendance> {
+    let mut db = conn()?;
+    db.execute("PRAGMA foreign_keys = ON", [])?;
+    let tx = db.transaction()?;
+    ensure_unlocked(&tx, date)?;
+
+    let existing: Option<(AttendanceStatus, Option<NaiveTime>)> = tx
+        .query_row(
+            "SELECT status, arrival_time FROM attendance_staff WHERE staff_id = ?1 AND date = ?2",
+            params![staff_id, date],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    match existing {
+        Some((_, Some(arrival))) => {
+            return Err(shift_error(&format!("Already checked in at {}", arrival)))
+        }
+        // Cancel the leave first if the staff member came in after all
+        Some((AttendanceStatus::OnLeave, None)) => return Err(shift_error("On leave that day")),
+        _ => {}
+    }
+
+    let status = if time > Shift::for_staff(&tx, staff_id)?.late_after() {
+        AttendanceStatus::Late
+    } else {
+        AttendanceStatus::Present
+    };
+
+    let id: i32 = tx.query_row(
+        "INSERT INTO attendance_staff (staff_id, date, status, arrival_time)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(staff_id, date) DO UPDATE SET
+            status = excluded.status,
+            arrival_time = excluded.arrival_time
+         RETURNING id",
+        params![staff_id, date, status, time],
+        |row| row.get(0),
+    )?;
+
+    let record = apply_timings(&tx, id)?;
+    tx.commit()?;
+    Ok(record)
+}
+
+pub fn check_out(staff_id: i32, date: NaiveDate, time: NaiveTime) -> Result<Attendance> {
+    let mut db = conn()?;
+    let tx = db.transaction()?;
+    ensure_unlocked(&tx, date)?;
+
+    let (id, arrival): (i32, Option<NaiveTime>) = tx
+        .query_row(
+            "SELECT id, arrival_time FROM attendance_staff WHERE staff_id = ?1 AND date = ?2",
+            params![staff_id, date],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| shift_error("Not checked in"))?;
+
+    match arrival {
+        None => return Err(shift_error("Not checked in")),
+        Some(arrival) if time <= arrival => {
+            return Err(shift_error("Check-out must be after check-in"))
+        }
+        _ => {}
+    }
+
+    tx.execute(
+        "UPDATE attendance_staff SET departure_time = ?1 WHERE id = ?2",
+        params![time, id],
+    )?;
+
+    let record = apply_timings(&tx, id)?;
+    tx.commit()?;
+    Ok(record)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StaffMonthlySummary {
+    pub staff_id: i32,
+    pub name: String,
+    pub shift: Shift,
+    pub attendance: AttendanceSummary,
+    pub late_days: i32,
+    pub late_minutes: i32,
+    pub early_departure_minutes: i32,
+    pub overtime_minutes: i32,
+    // Between check-in and check-out, on days with both
+    pub worked_minutes: i32,
+}
+
+pub fn monthly_summary(
+    year: i32,
+    month: u32,
+    staff_id: Option<i32>,
+) -> Result<Vec<StaffMonthlySummary>> {
+    let from = NaiveDate::from_ymd_opt(year, month, 1).ok_or(rusqlite::Error::InvalidQuery)?;
+    let to = from
+        .checked_add_months(chrono::Months::new(1))
+        .and_then(|d| d.pred_opt())
+        .ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let calendars = SchoolCalendar::load_overlapping(from, to)?;
+    let working_days = working_days_in(&calendars, from, to);
+
+    let db = conn()?;
+    let staff: Vec<(i32, String, NaiveDate)> = {
+        let mut stmt = db.prepare(
+            "SELECT id, name, hire_date FROM staffs WHERE (?1 IS NULL OR id = ?1) ORDER BY name ASC",
+        )?;
+        let rows = stmt.query_map(params![staff_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        rows.collect::<Result<_>>()?
+    };
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT {} FROM attendance_staff
+         WHERE staff_id = ?1 AND date >= ?2 AND date <= ?3
+         ORDER BY date ASC",
+        ATTENDANCE_COLUMNS
+    ))?;
+
+    let mut summaries = Vec::with_capacity(staff.len());
+    for (staff_id, name, hire_date) in staff {
+        let records: Vec<Attendance> = stmt
+            .query_map(params![staff_id, from, to], Attendance::from_row)?
+            .collect::<Result<_>>()?;
+
+        // Days before joining are not expected
+        let expected: Vec<NaiveDate> = working_days
+            .iter()
+            .copied()
+            .filter(|d| *d >= hire_date)
+            .collect();
+        let marks: Vec<(NaiveDate, AttendanceStatus)> =
+            records.iter().map(|r| (r.date, r.status)).collect();
+
+        let worked_minutes = records
+            .iter()
+            .filter_map(|r| Some((r.departure_time? - r.arrival_time?).num_minutes() as i32))
+            .filter(|m| *m > 0)
+            .sum();
+
+        summaries.push(StaffMonthlySummary {
+            staff_id,
+            name,
+            shift: Shift::for_staff(&db, staff_id)?,
+            attendance: AttendanceSummary::from_marks(&marks, &expected),
+            late_days: records.iter().filter(|r| r.late_minutes > 0).count() as i32,
+            late_minutes: records.iter().map(|r| r.late_minutes).sum(),
+            early_departure_minutes: records.iter().map(|r| r.early_departure_minutes).sum(),
+            overtime_minutes: records.iter().map(|r| r.overtime_minutes).sum(),
+            worked_minutes,
+        });
+    }
+
+    Ok(summaries)
+}
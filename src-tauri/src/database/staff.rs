@@ -1,6 +1,10 @@
-use super::conn;
-use chrono::NaiveDate;
-use rusqlite::{params, Result};
+use super::attendance::{ensure_unlocked, migrate_legacy_table, AttendanceStatus};
+use super::payroll::SalaryRevision;
+use super::shift::apply_timings;
+use super::{add_column_if_missing, conn};
+use crate::phone;
+use chrono::{Local, NaiveDate, NaiveTime};
+use rusqlite::{params, Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,8 +60,9 @@ impl Staff {
         general_note: Option<String>,
         health_note: Option<String>,
     ) -> Result<Self> {
-        let db = conn()?;
-        db.execute(
+        let mut db = conn()?;
+        let tx = db.transaction()?;
+        tx.execute(
             "INSERT INTO staffs (
                 name, phone, address, salary, hire_date, photo,
                 is_teacher, role, qualification, general_note, health_note
@@ -76,7 +81,9 @@ impl Staff {
                 health_note
             ],
         )?;
-        let id = db.last_insert_rowid() as i32;
+        let id = tx.last_insert_rowid() as i32;
+        SalaryRevision::from_form(&tx, id, salary, hire_date)?;
+        tx.commit()?;
         Ok(Self {
             id,
             name: name.to_string(),
@@ -131,8 +138,9 @@ impl Staff {
         general_note: Option<String>,
         health_note: Option<String>,
     ) -> Result<()> {
-        let db = conn()?;
-        let affected = db.execute(
+        let mut db = conn()?;
+        let tx = db.transaction()?;
+        let affected = tx.execute(
             "UPDATE staffs SET
                 name = ?1,
                 phone = ?2,
@@ -162,10 +170,34 @@ impl Staff {
             ],
         )?;
         if affected == 0 {
-            Err(rusqlite::Error::QueryReturnedNoRows)
-        } else {
-            Ok(())
+            return Err(rusqlite::Error::QueryReturnedNoRows);
         }
+        // A changed salary takes effect from today
+        let today = Local::now().date_naive();
+        SalaryRevision::from_form(&tx, id, salary, today.max(hire_date))?;
+        tx.commit()
+    }
+
+    pub fn normalize_phones(country: &str) -> Result<()> {
+        let db = conn()?;
+        let mut stmt = db.prepare("SELECT id, phone FROM staffs")?;
+        let rows: Vec<(i32, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_>>()?;
+
+        for (id, value) in rows {
+            match phone::normalize(&value, country) {
+                Ok(normalized) if normalized != value => {
+                    db.execute(
+                        "UPDATE staffs SET phone = ?1 WHERE id = ?2",
+                        params![normalized, id],
+                    )?;
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Staff {} phone {} not normalized: {}", id, value, e),
+            }
+        }
+        Ok(())
     }
 
     pub fn delete(id: i32) -> Result<()> {
@@ -273,95 +305,164 @@ pub struct Attendance {
     pub id: i32,
     pub staff_id: i32,
     pub date: NaiveDate,
-    pub status: String,
+    pub status: AttendanceStatus,
+    pub remark: Option<String>,
+    pub arrival_time: Option<NaiveTime>,
+    pub departure_time: Option<NaiveTime>,
+    pub late_minutes: i32,
+    pub early_departure_minutes: i32,
+    pub overtime_minutes: i32,
+}
+
+pub const ATTENDANCE_COLUMNS: &str = "id, staff_id, date, status, remark, arrival_time,
+     departure_time, late_minutes, early_departure_minutes, overtime_minutes";
+
+fn create_attendance_sql() -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS attendance_staff (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            staff_id INTEGER NOT NULL,
+            date DATE NOT NULL,
+            status TEXT NOT NULL {},
+            remark TEXT,
+            arrival_time TIME,
+            departure_time TIME,
+            late_minutes INTEGER NOT NULL DEFAULT 0,
+            early_departure_minutes INTEGER NOT NULL DEFAULT 0,
+            overtime_minutes INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY (staff_id) REFERENCES staffs(id) ON DELETE CASCADE,
+            UNIQUE (staff_id, date)
+        )",
+        AttendanceStatus::check_constraint()
+    )
 }
 
 impl Attendance {
+    pub fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            staff_id: row.get(1)?,
+            date: row.get(2)?,
+            status: row.get(3)?,
+            remark: row.get(4)?,
+            arrival_time: row.get(5)?,
+            departure_time: row.get(6)?,
+            late_minutes: row.get(7)?,
+            early_departure_minutes: row.get(8)?,
+            overtime_minutes: row.get(9)?,
+        })
+    }
+
     pub fn init() -> Result<()> {
-        let db = conn()?;
+        let mut db = conn()?;
         db.execute("PRAGMA foreign_keys = ON", [])?;
-        db.execute(
-            "CREATE TABLE IF NOT EXISTS attendance_staff (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                staff_id INTEGER NOT NULL,
-                date DATE NOT NULL,
-                status TEXT NOT NULL,
-                FOREIGN KEY (staff_id) REFERENCES staffs(id) ON DELETE CASCADE,
-                UNIQUE (staff_id, date)
-            )",
-            [],
-        )?;
+
+        let create_sql = create_attendance_sql();
+        db.execute(&create_sql, [])?;
+        migrate_legacy_table(&mut db, "attendance_staff", "staff_id", &create_sql)?;
+        add_column_if_missing(&db, "attendance_staff", "departure_time", "TIME")?;
+        for column in [
+            "late_minutes",
+            "early_departure_minutes",
+            "overtime_minutes",
+        ] {
+            add_column_if_missing(
+                &db,
+                "attendance_staff",
+                column,
+                "INTEGER NOT NULL DEFAULT 0",
+            )?;
+        }
         Ok(())
     }
 
-    pub fn create(staff_id: i32, date: NaiveDate, status: &str) -> Result<Self> {
+    pub fn create(
+        staff_id: i32,
+        date: NaiveDate,
+        status: AttendanceStatus,
+        remark: Option<String>,
+        arrival_time: Option<NaiveTime>,
+        departure_time: Option<NaiveTime>,
+    ) -> Result<Self> {
         let db = conn()?;
+        ensure_unlocked(&db, date)?;
         db.execute(
-            "INSERT OR REPLACE INTO attendance_staff (staff_id, date, status) VALUES (?1, ?2, ?3)",
-            params![staff_id, date, status],
+            "INSERT OR REPLACE INTO attendance_staff
+                (staff_id, date, status, remark, arrival_time, departure_time)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![staff_id, date, status, remark, arrival_time, departure_time],
         )?;
         let id = db.last_insert_rowid() as i32;
-        Ok(Self {
-            id,
-            staff_id,
-            date,
-            status: status.to_string(),
-        })
+        apply_timings(&db, id)
     }
 
     pub fn get_by_date(date: NaiveDate) -> Result<Vec<Self>> {
         let db = conn()?;
-        let mut stmt = db.prepare(
-            "SELECT id, staff_id, date, status FROM attendance_staff WHERE date = ?1 ORDER BY staff_id ASC",
-        )?;
-        let rows = stmt.query_map(params![date], |row| {
-            Ok(Self {
-                id: row.get(0)?,
-                staff_id: row.get(1)?,
-                date: row.get(2)?,
-                status: row.get(3)?,
-            })
-        })?;
+        let mut stmt = db.prepare(&format!(
+            "SELECT {} FROM attendance_staff WHERE date = ?1 ORDER BY staff_id ASC",
+            ATTENDANCE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![date], Self::from_row)?;
         rows.collect()
     }
 
     pub fn get_by_staff(staff_id: i32) -> Result<Vec<Self>> {
         let db = conn()?;
-        let mut stmt = db.prepare(
-            "SELECT id, staff_id, date, status FROM attendance_staff WHERE staff_id = ?1 ORDER BY date ASC",
-        )?;
-        let rows = stmt.query_map(params![staff_id], |row| {
-            Ok(Self {
-                id: row.get(0)?,
-                staff_id: row.get(1)?,
-                date: row.get(2)?,
-                status: row.get(3)?,
-            })
-        })?;
+        let mut stmt = db.prepare(&format!(
+            "SELECT {} FROM attendance_staff WHERE staff_id = ?1 ORDER BY date ASC",
+            ATTENDANCE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![staff_id], Self::from_row)?;
         rows.collect()
     }
 
-    pub fn update(id: i32, staff_id: i32, date: NaiveDate, status: &str) -> Result<()> {
+    pub fn update(
+        id: i32,
+        staff_id: i32,
+        date: NaiveDate,
+        status: AttendanceStatus,
+        remark: Option<String>,
+        arrival_time: Option<NaiveTime>,
+        departure_time: Option<NaiveTime>,
+    ) -> Result<()> {
         let db = conn()?;
-        let affected = db.execute(
-            "UPDATE attendance_staff SET staff_id = ?1, date = ?2, status = ?3 WHERE id = ?4",
-            params![staff_id, date, status, id],
+        // Both the day being changed and the day it is moved to must be open
+        ensure_unlocked(&db, Self::date_of(&db, id)?)?;
+        ensure_unlocked(&db, date)?;
+        db.execute(
+            "UPDATE attendance_staff
+             SET staff_id = ?1, date = ?2, status = ?3, remark = ?4, arrival_time = ?5,
+                 departure_time = ?6
+             WHERE id = ?7",
+            params![
+                staff_id,
+                date,
+                status,
+                remark,
+                arrival_time,
+                departure_time,
+                id
+            ],
         )?;
-        if affected == 0 {
-            Err(rusqlite::Error::QueryReturnedNoRows)
-        } else {
-            Ok(())
-        }
+        apply_timings(&db, id)?;
+        Ok(())
     }
 
     pub fn delete(id: i32) -> Result<()> {
         let db = conn()?;
-        let affected = db.execute("DELETE FROM attendance_staff WHERE id = ?1", params![id])?;
-        if affected == 0 {
-            Err(rusqlite::Error::QueryReturnedNoRows)
-        } else {
-            Ok(())
-        }
+        ensure_unlocked(&db, Self::date_of(&db, id)?)?;
+        db.execute("DELETE FROM attendance_staff WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    fn date_of(db: &Connection, id: i32) -> Result<NaiveDate> {
+        db.query_row(
+            "SELECT date FROM attendance_staff WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or(rusqlite::Error::QueryReturnedNoRows)
     }
 }
 
@@ -480,10 +581,89 @@ impl TeacherClassSubject {
     }
 }
 
+//
+// ─── STUDENT STAFF LINK ──────────────────────────────────────────────────────────────
+//
+
+// A student who is a child or ward of a staff member, which makes them
+// eligible for staff-child discounts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StudentStaffLink {
+    pub id: i32,
+    pub student_id: i32,
+    pub staff_id: i32,
+    pub relationship: Option<String>,
+}
+
+impl StudentStaffLink {
+    pub fn init() -> Result<()> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS student_staff_links (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                student_id INTEGER NOT NULL,
+                staff_id INTEGER NOT NULL,
+                relationship TEXT,
+                UNIQUE (student_id, staff_id),
+                FOREIGN KEY (student_id) REFERENCES students(id) ON DELETE CASCADE,
+                FOREIGN KEY (staff_id) REFERENCES staffs(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    pub fn create(student_id: i32, staff_id: i32, relationship: Option<String>) -> Result<Self> {
+        let db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        db.execute(
+            "INSERT INTO student_staff_links (student_id, staff_id, relationship)
+             VALUES (?1, ?2, ?3)",
+            params![student_id, staff_id, relationship],
+        )?;
+        let id = db.last_insert_rowid() as i32;
+        Ok(Self {
+            id,
+            student_id,
+            staff_id,
+            relationship,
+        })
+    }
+
+    pub fn get_by_staff(staff_id: i32) -> Result<Vec<Self>> {
+        let db = conn()?;
+        let mut stmt = db.prepare(
+            "SELECT id, student_id, staff_id, relationship
+             FROM student_staff_links WHERE staff_id = ?1",
+        )?;
+        let rows = stmt.query_map(params![staff_id], |row| {
+            Ok(Self {
+                id: row.get(0)?,
+                student_id: row.get(1)?,
+                staff_id: row.get(2)?,
+                relationship: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn delete(id: i32) -> Result<()> {
+        let db = conn()?;
+        let affected = db.execute("DELETE FROM student_staff_links WHERE id = ?1", params![id])?;
+        if affected == 0 {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 pub fn init_all() -> Result<()> {
     Staff::init()?;
     Complaint::init()?;
     Attendance::init()?;
     TeacherClassSubject::init()?;
+    StudentStaffLink::init()?;
     Ok(())
 }
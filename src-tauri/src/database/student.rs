@@ -1,7 +1,11 @@
+use super::attendance::{ensure_unlocked, migrate_legacy_table, AttendanceStatus};
 use super::conn;
-use chrono::{Datelike, Local, NaiveDate};
-use rusqlite::{params, Result};
+use super::residency::ResidentPeriod;
+use crate::phone;
+use chrono::{Datelike, Local, NaiveDate, NaiveTime};
+use rusqlite::{params, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Student {
@@ -162,6 +166,7 @@ impl Student {
         )?;
 
         let id = db.last_insert_rowid() as i32;
+        ResidentPeriod::sync(&db, id, is_resident, admission_date)?;
         Ok(Self::new(
             id,
             name,
@@ -328,6 +333,8 @@ impl Student {
         if affected == 0 {
             Err(rusqlite::Error::QueryReturnedNoRows)
         } else {
+            let today = Local::now().date_naive();
+            ResidentPeriod::sync(&db, id, is_resident, today.max(admission_date))?;
             Ok(Self::new(
                 id,
                 name,
@@ -349,6 +356,33 @@ impl Student {
         }
     }
 
+    pub fn normalize_phones(country: &str) -> Result<()> {
+        let db = conn()?;
+        let mut stmt = db.prepare("SELECT id, phone FROM students WHERE phone IS NOT NULL")?;
+        let rows: Vec<(i32, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_>>()?;
+
+        for (id, value) in rows {
+            let normalized = if value.trim().is_empty() {
+                Ok(None)
+            } else {
+                phone::normalize(&value, country).map(Some)
+            };
+            match normalized {
+                Ok(normalized) if normalized.as_deref() != Some(value.as_str()) => {
+                    db.execute(
+                        "UPDATE students SET phone = ?1 WHERE id = ?2",
+                        params![normalized, id],
+                    )?;
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Student {} phone {} not normalized: {}", id, value, e),
+            }
+        }
+        Ok(())
+    }
+
     pub fn delete(id: i32) -> Result<()> {
         let db = conn()?;
         db.execute("PRAGMA foreign_keys = ON", [])?;
@@ -403,49 +437,230 @@ pub struct Attendance {
     pub id: i32,
     pub student_id: i32,
     pub date: NaiveDate,
-    pub status: String,
+    pub status: AttendanceStatus,
+    pub remark: Option<String>,
+    pub arrival_time: Option<NaiveTime>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AttendanceEntry {
+    pub student_id: i32,
+    pub status: AttendanceStatus,
+    pub remark: Option<String>,
+    pub arrival_time: Option<NaiveTime>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClassRegister {
+    pub date: NaiveDate,
+    pub records: Vec<Attendance>,
+    pub counts: BTreeMap<String, i32>,
+    pub unmarked: Vec<i32>,
+}
+
+fn create_attendance_sql() -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS attendance (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            student_id INTEGER NOT NULL,
+            date DATE NOT NULL,
+            status TEXT NOT NULL {},
+            remark TEXT,
+            arrival_time TIME,
+            FOREIGN KEY (student_id) REFERENCES students(id) ON DELETE CASCADE,
+            UNIQUE (student_id, date)
+        )",
+        AttendanceStatus::check_constraint()
+    )
 }
 
 impl Attendance {
-    pub fn new(id: i32, student_id: i32, date: NaiveDate, status: String) -> Self {
+    pub fn new(
+        id: i32,
+        student_id: i32,
+        date: NaiveDate,
+        status: AttendanceStatus,
+        remark: Option<String>,
+        arrival_time: Option<NaiveTime>,
+    ) -> Self {
         Self {
             id,
             student_id,
             date,
             status,
+            remark,
+            arrival_time,
         }
     }
 
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            student_id: row.get(1)?,
+            date: row.get(2)?,
+            status: row.get(3)?,
+            remark: row.get(4)?,
+            arrival_time: row.get(5)?,
+        })
+    }
+
     pub fn init() -> Result<()> {
-        let db = conn()?;
+        let mut db = conn()?;
         db.execute("PRAGMA foreign_keys = ON", [])?;
 
-        db.execute(
-            "CREATE TABLE IF NOT EXISTS attendance (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                student_id INTEGER NOT NULL,
-                date DATE NOT NULL,
-                status TEXT NOT NULL,
-                FOREIGN KEY (student_id) REFERENCES students(id) ON DELETE CASCADE,
-                UNIQUE (student_id, date)
-            )",
-            [],
-        )?;
+        let create_sql = create_attendance_sql();
+        db.execute(&create_sql, [])?;
+        migrate_legacy_table(&mut db, "attendance", "student_id", &create_sql)?;
         Ok(())
     }
 
-    pub fn create(student_id: i32, date: NaiveDate, status: &str) -> Result<Self> {
+    pub fn create(
+        student_id: i32,
+        date: NaiveDate,
+        status: AttendanceStatus,
+        remark: Option<String>,
+        arrival_time: Option<NaiveTime>,
+    ) -> Result<Self> {
         let db = conn()?;
         db.execute("PRAGMA foreign_keys = ON", [])?;
+        ensure_unlocked(&db, date)?;
 
         db.execute(
-            "INSERT OR REPLACE INTO attendance (student_id, date, status) VALUES (?1, ?2, ?3)",
-            params![student_id, date, status],
+            "INSERT OR REPLACE INTO attendance (student_id, date, status, remark, arrival_time)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![student_id, date, status, remark, arrival_time],
         )?;
 
         let id = db.last_insert_rowid() as i32;
 
-        Ok(Self::new(id, student_id, date, status.to_string()))
+        Ok(Self::new(
+            id,
+            student_id,
+            date,
+            status,
+            remark,
+            arrival_time,
+        ))
+    }
+
+    // Marks a whole class or section in one transaction. Students without an
+    // entry get `default_status` ("mark all present except..."), or are left
+    // untouched when no default is given.
+    pub fn mark_class(
+        date: NaiveDate,
+        session_id: i32,
+        class_id: i32,
+        section_id: Option<i32>,
+        entries: Vec<AttendanceEntry>,
+        default_status: Option<AttendanceStatus>,
+    ) -> Result<ClassRegister> {
+        let mut db = conn()?;
+        db.execute("PRAGMA foreign_keys = ON", [])?;
+        let tx = db.transaction()?;
+        ensure_unlocked(&tx, date)?;
+
+        let student_ids: Vec<i32> = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM students
+                 WHERE session_id = ?1 AND class_id = ?2 AND (?3 IS NULL OR section_id = ?3)
+                 ORDER BY roll ASC",
+            )?;
+            let rows =
+                stmt.query_map(params![session_id, class_id, section_id], |row| row.get(0))?;
+            rows.collect::<Result<_>>()?
+        };
+
+        let mut marks: HashMap<i32, AttendanceEntry> = HashMap::new();
+        for entry in entries {
+            if !student_ids.contains(&entry.student_id) {
+                return Err(rusqlite::Error::SqliteFailure(
+                    rusqlite::ffi::Error {
+                        code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+                        extended_code: 0,
+                    },
+                    Some(format!(
+                        "Student {} is not in the selected class/section.",
+                        entry.student_id
+                    )),
+                ));
+            }
+            marks.insert(entry.student_id, entry);
+        }
+
+        let mut records = Vec::new();
+        let mut counts: BTreeMap<String, i32> = BTreeMap::new();
+        let mut unmarked = Vec::new();
+        {
+            let mut upsert = tx.prepare(
+                "INSERT INTO attendance (student_id, date, status, remark, arrival_time)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(student_id, date) DO UPDATE SET
+                    status = excluded.status,
+                    remark = excluded.remark,
+                    arrival_time = excluded.arrival_time
+                 RETURNING id",
+            )?;
+            let mut existing = tx.prepare(
+                "SELECT id, student_id, date, status, remark, arrival_time
+                 FROM attendance WHERE student_id = ?1 AND date = ?2",
+            )?;
+
+            for student_id in student_ids {
+                let entry = marks.remove(&student_id).or_else(|| {
+                    default_status.map(|status| AttendanceEntry {
+                        student_id,
+                        status,
+                        remark: None,
+                        arrival_time: None,
+                    })
+                });
+
+                let record = match entry {
+                    Some(entry) => {
+                        let id: i32 = upsert.query_row(
+                            params![
+                                student_id,
+                                date,
+                                entry.status,
+                                entry.remark,
+                                entry.arrival_time
+                            ],
+                            |row| row.get(0),
+                        )?;
+                        Some(Self::new(
+                            id,
+                            student_id,
+                            date,
+                            entry.status,
+                            entry.remark,
+                            entry.arrival_time,
+                        ))
+                    }
+                    None => existing
+                        .query_row(params![student_id, date], Self::from_row)
+                        .optional()?,
+                };
+
+                match record {
+                    Some(record) => {
+                        *counts
+                            .entry(record.status.as_str().to_string())
+                            .or_insert(0) += 1;
+                        records.push(record);
+                    }
+                    None => unmarked.push(student_id),
+                }
+            }
+        }
+
+        tx.commit()?;
+
+        Ok(ClassRegister {
+            date,
+            records,
+            counts,
+            unmarked,
+        })
     }
 
     pub fn get_by_date(
@@ -457,7 +672,7 @@ impl Attendance {
         let db = conn()?;
 
         let mut query = String::from(
-            "SELECT a.id, a.student_id, a.date, a.status
+            "SELECT a.id, a.student_id, a.date, a.status, a.remark, a.arrival_time
          FROM attendance a
          JOIN students s ON a.student_id = s.id
          WHERE a.date = ? AND s.session_id = ?",
@@ -480,14 +695,7 @@ impl Attendance {
         let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
         let mut stmt = db.prepare(&query)?;
 
-        let rows = stmt.query_map(param_refs.as_slice(), |row| {
-            Ok(Self {
-                id: row.get(0)?,
-                student_id: row.get(1)?,
-                date: row.get(2)?,
-                status: row.get(3)?,
-            })
-        })?;
+        let rows = stmt.query_map(param_refs.as_slice(), Self::from_row)?;
 
         let result: Result<Vec<Self>, rusqlite::Error> = rows.collect();
         result
@@ -515,29 +723,23 @@ impl Attendance {
 
         let db = conn()?;
         let mut stmt = db.prepare(
-            "SELECT id, student_id, date, status
+            "SELECT id, student_id, date, status, remark, arrival_time
              FROM attendance
              WHERE student_id = ?1 AND date >= ?2 AND date < ?3
              ORDER BY date ASC",
         )?;
 
-        let rows = stmt.query_map(params![student_id, start_date, end_date], |row| {
-            Ok(Self {
-                id: row.get(0)?,
-                student_id: row.get(1)?,
-                date: row.get(2)?,
-                status: row.get(3)?,
-            })
-        })?;
+        let rows = stmt.query_map(params![student_id, start_date, end_date], Self::from_row)?;
 
         rows.collect()
     }
 
     pub fn delete_by_student_and_date(student_id_val: i32, date_val: NaiveDate) -> Result<()> {
         let conn = conn()?;
+        ensure_unlocked(&conn, date_val)?;
 
         conn.execute(
-            "DELETE FROM attendances WHERE student_id = ?1 AND date = ?2",
+            "DELETE FROM attendance WHERE student_id = ?1 AND date = ?2",
             params![student_id_val, date_val],
         )?;
 
@@ -546,12 +748,17 @@ impl Attendance {
 
     pub fn delete(id: i32) -> Result<()> {
         let db = conn()?;
-        let affected = db.execute("DELETE FROM attendance WHERE id = ?", params![id])?;
-
-        if affected == 0 {
-            Err(rusqlite::Error::QueryReturnedNoRows)
-        } else {
-            Ok(())
-        }
+        let date: NaiveDate = db
+            .query_row(
+                "SELECT date FROM attendance WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        ensure_unlocked(&db, date)?;
+
+        db.execute("DELETE FROM attendance WHERE id = ?", params![id])?;
+        Ok(())
     }
 }
@@ -1,7 +1,10 @@
 mod commands;
 mod database;
 mod fake;
+mod phone;
+mod report;
 mod utility;
+mod words;
 
 use commands::class::*;
 use commands::guardian::*;
@@ -33,8 +36,10 @@ pub fn run() {
             edit_student,
             // Attendance commands
             create_attendance,
+            mark_class_attendance,
             get_attendance_by_date,
             get_attendance_by_student,
+            get_attendance_summary,
             delete_attendance,
             //
             //
@@ -54,6 +59,7 @@ pub fn run() {
             commands::staff::create_attendance_staff,
             commands::staff::get_attendance_by_staff,
             commands::staff::update_attendance,
+            commands::staff::get_staff_attendance_summary,
             commands::staff::get_staff_attendance_by_date,
             commands::staff::delete_attendance_staff,
             //
@@ -62,6 +68,9 @@ pub fn run() {
             commands::staff::get_teacher_subject_links,
             commands::staff::update_teacher_subject_link,
             commands::staff::delete_teacher_subject_link,
+            commands::staff::create_student_staff_link,
+            commands::staff::get_student_staff_links,
+            commands::staff::delete_student_staff_link,
             //
             // guardian commands
             create_guardian,
@@ -74,12 +83,178 @@ pub fn run() {
             get_student_relationships,
             delete_student_relationship,
             edit_student_relationship,
+            // family commands
+            commands::family::get_family,
+            commands::family::get_families,
+            commands::family::get_families_by_student,
+            commands::family::get_family_summary,
+            commands::family::detect_siblings,
+            // duplicate commands
+            commands::duplicate::find_duplicate_students,
+            commands::duplicate::find_duplicate_guardians,
+            commands::duplicate::merge_students,
+            commands::duplicate::merge_guardians,
+            commands::duplicate::get_history,
             // session commands
             create_session,
             get_sessions,
             get_session_by_id,
             delete_session,
             edit_session,
+            // settings commands
+            commands::settings::get_settings,
+            commands::settings::get_setting,
+            commands::settings::set_setting,
+            // calendar commands
+            commands::calendar::create_calendar_event,
+            commands::calendar::get_calendar_events,
+            commands::calendar::edit_calendar_event,
+            commands::calendar::delete_calendar_event,
+            commands::calendar::get_weekly_off_days,
+            commands::calendar::set_weekly_off_days,
+            commands::calendar::get_calendar_days,
+            commands::calendar::get_working_days,
+            // register commands
+            commands::register::monthly_register,
+            commands::register::export_monthly_register_csv,
+            commands::register::export_monthly_register_pdf,
+            // shift commands
+            commands::shift::create_shift,
+            commands::shift::get_shifts,
+            commands::shift::edit_shift,
+            commands::shift::delete_shift,
+            commands::shift::assign_staff_shift,
+            commands::shift::get_staff_shifts,
+            commands::shift::staff_check_in,
+            commands::shift::staff_check_out,
+            commands::shift::get_staff_monthly_summary,
+            // fee commands
+            commands::fee::record_payment,
+            commands::fee::get_student_payments,
+            commands::fee::delete_payment,
+            commands::fee::set_fee_override,
+            commands::fee::get_fee_override,
+            commands::fee::delete_fee_override,
+            commands::fee::get_student_ledger,
+            // residency commands
+            commands::residency::get_resident_periods,
+            commands::residency::start_residency,
+            commands::residency::end_residency,
+            commands::residency::delete_resident_period,
+            // billing commands
+            commands::billing::run_monthly_billing,
+            commands::billing::get_invoice_lines,
+            commands::billing::delete_invoice_line,
+            commands::billing::get_dues,
+            commands::billing::get_defaulters,
+            // receipt commands
+            commands::receipt::create_receipt,
+            commands::receipt::create_lump_sum_receipt,
+            commands::receipt::get_receipt,
+            commands::receipt::get_receipts,
+            commands::receipt::cancel_receipt,
+            commands::receipt::export_receipt_pdf,
+            // allocation commands
+            commands::allocation::get_payment_allocations,
+            commands::allocation::get_receipt_allocations,
+            commands::allocation::reallocate_payment,
+            commands::allocation::get_student_credit,
+            // reconciliation commands
+            commands::reconciliation::import_payment_statement,
+            commands::reconciliation::rematch_statement_lines,
+            commands::reconciliation::get_statement_lines,
+            commands::reconciliation::match_statement_line,
+            commands::reconciliation::record_statement_line,
+            commands::reconciliation::ignore_statement_line,
+            commands::reconciliation::unmatch_statement_line,
+            commands::reconciliation::get_reconciliation,
+            commands::reconciliation::record_cash_deposit,
+            commands::reconciliation::get_cash_deposits,
+            commands::reconciliation::delete_cash_deposit,
+            commands::reconciliation::get_cash_summary,
+            // discount commands
+            commands::discount::create_discount_rule,
+            commands::discount::get_discount_rules,
+            commands::discount::edit_discount_rule,
+            commands::discount::delete_discount_rule,
+            commands::discount::grant_discount,
+            commands::discount::get_discount_grants,
+            commands::discount::revoke_discount,
+            commands::discount::get_student_discounts,
+            // late fee commands
+            commands::late_fee::set_late_fee_policy,
+            commands::late_fee::get_late_fee_policies,
+            commands::late_fee::delete_late_fee_policy,
+            commands::late_fee::apply_late_fees,
+            commands::late_fee::waive_late_fee,
+            // student card commands
+            commands::card::get_student_card,
+            commands::card::get_class_cards,
+            commands::card::scan_attendance,
+            commands::card::get_card_template,
+            commands::card::set_card_template,
+            commands::card::export_id_cards_pdf,
+            // leave commands
+            commands::leave::create_leave_type,
+            commands::leave::get_leave_types,
+            commands::leave::edit_leave_type,
+            commands::leave::delete_leave_type,
+            commands::leave::set_leave_entitlement,
+            commands::leave::get_leave_entitlements,
+            commands::leave::delete_leave_entitlement,
+            commands::leave::apply_leave,
+            commands::leave::get_leave_applications,
+            commands::leave::approve_leave,
+            commands::leave::reject_leave,
+            commands::leave::cancel_leave,
+            commands::leave::get_leave_balances,
+            // payroll commands
+            commands::payroll::get_salary_revisions,
+            commands::payroll::create_salary_revision,
+            commands::payroll::delete_salary_revision,
+            commands::payroll::create_salary_deduction,
+            commands::payroll::get_salary_deductions,
+            commands::payroll::delete_salary_deduction,
+            commands::payroll::run_payroll,
+            commands::payroll::get_salary_payments,
+            commands::payroll::delete_salary_payment,
+            commands::payroll::export_payslip_pdf,
+            // salary advance commands
+            commands::advance::create_salary_advance,
+            commands::advance::get_salary_advances,
+            commands::advance::repay_salary_advance,
+            commands::advance::delete_salary_advance,
+            commands::advance::get_open_advances,
+            // biometric import commands
+            commands::biometric::set_staff_device_id,
+            commands::biometric::get_staff_device_ids,
+            commands::biometric::delete_staff_device_id,
+            commands::biometric::import_biometric_log,
+            // attendance lock commands
+            commands::attendance::get_attendance_lock,
+            commands::attendance::set_attendance_lock,
+            commands::attendance::override_attendance,
+            commands::attendance::get_attendance_overrides,
+            // analytics commands
+            commands::analytics::get_student_analytics,
+            commands::analytics::get_section_analytics,
+            commands::analytics::get_chronic_absentees,
+            commands::analytics::get_chronic_absence_rule,
+            commands::analytics::set_chronic_absence_rule,
+            // routine commands
+            commands::routine::create_class_routine,
+            commands::routine::get_class_routines,
+            commands::routine::get_teacher_routine,
+            commands::routine::edit_class_routine,
+            commands::routine::delete_class_routine,
+            commands::routine::mark_period_attendance,
+            commands::routine::get_period_attendance,
+            commands::routine::get_student_period_attendance,
+            commands::routine::delete_period_attendance,
+            commands::routine::get_subject_attendance_report,
+            commands::routine::get_student_subject_attendance,
+            commands::routine::get_daily_status_rule,
+            commands::routine::set_daily_status_rule,
             // class commands
             create_class,
             get_classes,
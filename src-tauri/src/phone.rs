@@ -0,0 +1,145 @@
+use crate::database::settings::Setting;
+
+pub const DEFAULT_COUNTRY_KEY: &str = "default_country";
+pub const DEFAULT_COUNTRY: &str = "BD";
+
+// (ISO country, calling code, national number length range)
+const COUNTRIES: &[(&str, &str, usize, usize)] = &[
+    ("BD", "880", 8, 10),
+    ("IN", "91", 10, 10),
+    ("PK", "92", 9, 10),
+    ("NP", "977", 8, 10),
+    ("LK", "94", 9, 9),
+    ("MY", "60", 8, 10),
+    ("SA", "966", 8, 9),
+    ("AE", "971", 8, 9),
+    ("QA", "974", 8, 8),
+    ("KW", "965", 8, 8),
+    ("GB", "44", 9, 10),
+    ("US", "1", 10, 10),
+];
+
+fn country(code: &str) -> Option<(&'static str, usize, usize)> {
+    COUNTRIES
+        .iter()
+        .find(|(iso, ..)| iso.eq_ignore_ascii_case(code))
+        .map(|(_, cc, min, max)| (*cc, *min, *max))
+}
+
+pub fn is_supported_country(code: &str) -> bool {
+    country(code).is_some()
+}
+
+pub fn default_country() -> String {
+    Setting::get_or(DEFAULT_COUNTRY_KEY, DEFAULT_COUNTRY)
+        .unwrap_or_else(|_| DEFAULT_COUNTRY.to_string())
+}
+
+// Normalizes a phone number to E.164 (`+8801711000000`). Numbers without an
+// international prefix are read as national numbers of `default_country`.
+pub fn normalize(input: &str, default_country: &str) -> Result<String, String> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return Err("Phone number is empty".to_string());
+    }
+
+    if let Some(c) = trimmed
+        .chars()
+        .find(|c| !c.is_ascii_digit() && !" +-().".contains(*c))
+    {
+        return Err(format!(
+            "Invalid character '{}' in phone number {}",
+            c, trimmed
+        ));
+    }
+
+    let digits: String = trimmed.chars().filter(|c| c.is_ascii_digit()).collect();
+    let international = trimmed.starts_with('+') || digits.starts_with("00");
+    let digits = if trimmed.starts_with('+') {
+        digits
+    } else {
+        digits.strip_prefix("00").unwrap_or(&digits).to_string()
+    };
+
+    let (home_cc, home_min, home_max) = country(default_country)
+        .ok_or_else(|| format!("Unsupported country: {}", default_country))?;
+
+    if international {
+        // Longest calling code first so "880" wins over a shorter prefix
+        let mut known: Vec<_> = COUNTRIES.iter().collect();
+        known.sort_by_key(|(_, cc, ..)| std::cmp::Reverse(cc.len()));
+
+        for (_, cc, min, max) in known {
+            if let Some(national) = digits.strip_prefix(cc) {
+                let national = national.strip_prefix('0').unwrap_or(national);
+                if (*min..=*max).contains(&national.len()) {
+                    return Ok(format!("+{}{}", cc, national));
+                }
+                return Err(format!("Invalid phone number length: {}", trimmed));
+            }
+        }
+
+        if (8..=15).contains(&digits.len()) {
+            return Ok(format!("+{}", digits));
+        }
+        return Err(format!("Invalid phone number: {}", trimmed));
+    }
+
+    // "8801711000000" typed without the plus sign
+    if let Some(national) = digits.strip_prefix(home_cc) {
+        let national = national.strip_prefix('0').unwrap_or(national);
+        if (home_min..=home_max).contains(&national.len()) && digits.len() > home_max {
+            return Ok(format!("+{}{}", home_cc, national));
+        }
+    }
+
+    let national = digits.strip_prefix('0').unwrap_or(&digits);
+    if (home_min..=home_max).contains(&national.len()) {
+        Ok(format!("+{}{}", home_cc, national))
+    } else {
+        Err(format!("Invalid phone number length: {}", trimmed))
+    }
+}
+
+pub fn normalize_default(input: &str) -> Result<String, String> {
+    normalize(input, &default_country())
+}
+
+pub fn normalize_optional(input: Option<String>) -> Result<Option<String>, String> {
+    match input {
+        Some(phone) if !phone.trim().is_empty() => normalize_default(&phone).map(Some),
+        _ => Ok(None),
+    }
+}
+
+// Digits to look for when a search query looks like a phone number in any
+// format, with prefixes stripped so they match the stored E.164 value.
+pub fn search_digits(query: &str, default_country: &str) -> Option<String> {
+    let query = query.trim();
+    if query.is_empty()
+        || !query
+            .chars()
+            .all(|c| c.is_ascii_digit() || " +-().".contains(c))
+    {
+        return None;
+    }
+
+    let digits: String = query.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.len() < 3 {
+        return None;
+    }
+
+    let mut core = digits.strip_prefix("00").unwrap_or(&digits);
+    if let Some((cc, ..)) = country(default_country) {
+        if core.len() > cc.len() + 3 {
+            core = core.strip_prefix(cc).unwrap_or(core);
+        }
+    }
+    let core = core.trim_start_matches('0');
+
+    if core.is_empty() {
+        None
+    } else {
+        Some(core.to_string())
+    }
+}
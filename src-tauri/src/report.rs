@@ -0,0 +1,213 @@
+use base64::engine::general_purpose;
+use base64::Engine;
+use printpdf::image_crate::{self, DynamicImage, Rgb, RgbImage};
+use printpdf::{
+    BuiltinFont, Color, Image, ImageTransform, IndirectFontRef, Line, Mm, PdfDocument,
+    PdfLayerReference, Point,
+};
+
+// A4 landscape, in millimetres
+const PAGE_WIDTH: f32 = 297.0;
+const PAGE_HEIGHT: f32 = 210.0;
+const MARGIN: f32 = 10.0;
+const ROW_HEIGHT: f32 = 5.5;
+
+pub fn pdf_data_url(bytes: &[u8]) -> String {
+    format!(
+        "data:application/pdf;base64,{}",
+        general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+pub fn to_csv(headers: &[String], rows: &[Vec<String>]) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(headers).map_err(|e| e.to_string())?;
+    for row in rows {
+        writer.write_record(row).map_err(|e| e.to_string())?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+// Printable table spread over as many pages as needed. Column widths are in
+// millimetres and are scaled down when they do not fit the page; the header
+// row is repeated on every page.
+pub struct TablePdf {
+    pub title: String,
+    pub subtitle: Vec<String>,
+    pub headers: Vec<String>,
+    pub widths: Vec<f32>,
+    pub rows: Vec<Vec<String>>,
+    pub font_size: f32,
+}
+
+impl TablePdf {
+    pub fn render(&self) -> Result<Vec<u8>, String> {
+        let (doc, page, layer) =
+            PdfDocument::new(&self.title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        let font = doc
+            .add_builtin_font(BuiltinFont::Helvetica)
+            .map_err(|e| e.to_string())?;
+        let bold = doc
+            .add_builtin_font(BuiltinFont::HelveticaBold)
+            .map_err(|e| e.to_string())?;
+
+        let available = PAGE_WIDTH - 2.0 * MARGIN;
+        let total: f32 = self.widths.iter().sum();
+        let scale = if total > available {
+            available / total
+        } else {
+            1.0
+        };
+        let widths: Vec<f32> = self.widths.iter().map(|w| w * scale).collect();
+
+        let mut layer = doc.get_page(page).get_layer(layer);
+        let mut y = self.draw_heading(&layer, &bold, &font);
+        y = self.draw_row(&layer, &bold, &widths, &self.headers, y);
+
+        for row in &self.rows {
+            if y - ROW_HEIGHT < MARGIN {
+                let (page, index) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+                layer = doc.get_page(page).get_layer(index);
+                y = self.draw_row(&layer, &bold, &widths, &self.headers, PAGE_HEIGHT - MARGIN);
+            }
+            y = self.draw_row(&layer, &font, &widths, row, y);
+        }
+
+        doc.save_to_bytes().map_err(|e| e.to_string())
+    }
+
+    fn draw_heading(
+        &self,
+        layer: &PdfLayerReference,
+        bold: &IndirectFontRef,
+        font: &IndirectFontRef,
+    ) -> f32 {
+        let mut y = PAGE_HEIGHT - MARGIN - 4.0;
+        layer.use_text(&self.title, 14.0, Mm(MARGIN), Mm(y), bold);
+        y -= 6.0;
+        for line in &self.subtitle {
+            layer.use_text(line, 9.0, Mm(MARGIN), Mm(y), font);
+            y -= 4.5;
+        }
+        y - 2.0
+    }
+
+    // Draws one bordered row with its top edge at `top` and returns the top of
+    // the next row.
+    fn draw_row(
+        &self,
+        layer: &PdfLayerReference,
+        font: &IndirectFontRef,
+        widths: &[f32],
+        cells: &[String],
+        top: f32,
+    ) -> f32 {
+        let bottom = top - ROW_HEIGHT;
+        let right = MARGIN + widths.iter().sum::<f32>();
+        layer.set_outline_thickness(0.2);
+        layer.add_line(line((MARGIN, bottom), (right, bottom)));
+        layer.add_line(line((MARGIN, top), (right, top)));
+
+        let mut x = MARGIN;
+        layer.add_line(line((x, bottom), (x, top)));
+        for (width, cell) in widths.iter().zip(cells) {
+            let text = fit(cell, *width, self.font_size);
+            layer.use_text(text, self.font_size, Mm(x + 0.8), Mm(bottom + 1.6), font);
+            x += width;
+            layer.add_line(line((x, bottom), (x, top)));
+        }
+        bottom
+    }
+}
+
+pub fn line(from: (f32, f32), to: (f32, f32)) -> Line {
+    Line {
+        points: vec![
+            (Point::new(Mm(from.0), Mm(from.1)), false),
+            (Point::new(Mm(to.0), Mm(to.1)), false),
+        ],
+        is_closed: false,
+    }
+}
+
+// Builtin fonts carry no metrics here, so text is cut by an average glyph
+// width of roughly half the font size.
+pub fn fit(text: &str, width: f32, font_size: f32) -> String {
+    let glyph = font_size * 0.3528 * 0.55;
+    let max = ((width - 1.6) / glyph).floor().max(1.0) as usize;
+    if text.chars().count() <= max {
+        text.to_string()
+    } else {
+        text.chars().take(max).collect()
+    }
+}
+
+// "#RRGGBB" to a PDF colour
+pub fn hex_color(value: &str) -> Option<Color> {
+    let hex = value.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(hex.get(i..i + 2)?, 16)
+            .ok()
+            .map(|v| v as f32 / 255.0)
+    };
+    Some(Color::Rgb(printpdf::Rgb::new(
+        channel(0)?,
+        channel(2)?,
+        channel(4)?,
+        None,
+    )))
+}
+
+// Decodes a base64 data URL as stored for photos and logos. The image is
+// shrunk to at most `max_pixels` on its longer side and flattened onto white,
+// since transparency does not survive the PDF embedding.
+pub fn decode_image(data_url: &str, max_pixels: u32) -> Option<DynamicImage> {
+    let encoded = match data_url.split_once("base64,") {
+        Some((_, encoded)) => encoded,
+        None => data_url,
+    };
+    let bytes = general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+    let image = image_crate::load_from_memory(&bytes)
+        .ok()?
+        .thumbnail(max_pixels, max_pixels)
+        .to_rgba8();
+
+    let flattened = RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        Rgb([blend(r), blend(g), blend(b)])
+    });
+    Some(DynamicImage::ImageRgb8(flattened))
+}
+
+// Places an image inside the box, keeping its aspect ratio and centring it
+pub fn place_image(
+    layer: &PdfLayerReference,
+    image: &DynamicImage,
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+) {
+    let dpi = 300.0;
+    let (px_w, px_h) = (image.width() as f32, image.height() as f32);
+    let (natural_w, natural_h) = (px_w * 25.4 / dpi, px_h * 25.4 / dpi);
+    let scale = (w / natural_w).min(h / natural_h);
+    let (drawn_w, drawn_h) = (natural_w * scale, natural_h * scale);
+
+    Image::from_dynamic_image(image).add_to_layer(
+        layer.clone(),
+        ImageTransform {
+            translate_x: Some(Mm(x + (w - drawn_w) / 2.0)),
+            translate_y: Some(Mm(y + (h - drawn_h) / 2.0)),
+            scale_x: Some(scale),
+            scale_y: Some(scale),
+            dpi: Some(dpi),
+            ..Default::default()
+        },
+    );
+}
@@ -0,0 +1,210 @@
+// Taka amounts in words for receipts, grouped the way they are read in
+// Bangladesh: crore, lakh, thousand, hundred.
+
+const ONES: [&str; 20] = [
+    "",
+    "One",
+    "Two",
+    "Three",
+    "Four",
+    "Five",
+    "Six",
+    "Seven",
+    "Eight",
+    "Nine",
+    "Ten",
+    "Eleven",
+    "Twelve",
+    "Thirteen",
+    "Fourteen",
+    "Fifteen",
+    "Sixteen",
+    "Seventeen",
+    "Eighteen",
+    "Nineteen",
+];
+
+const TENS: [&str; 10] = [
+    "", "", "Twenty", "Thirty", "Forty", "Fifty", "Sixty", "Seventy", "Eighty", "Ninety",
+];
+
+// Bangla numbers below a hundred do not follow a pattern, so each has its own word
+const BANGLA: [&str; 100] = [
+    "",
+    "এক",
+    "দুই",
+    "তিন",
+    "চার",
+    "পাঁচ",
+    "ছয়",
+    "সাত",
+    "আট",
+    "নয়",
+    "দশ",
+    "এগারো",
+    "বারো",
+    "তেরো",
+    "চৌদ্দ",
+    "পনেরো",
+    "ষোল",
+    "সতেরো",
+    "আঠারো",
+    "উনিশ",
+    "বিশ",
+    "একুশ",
+    "বাইশ",
+    "তেইশ",
+    "চব্বিশ",
+    "পঁচিশ",
+    "ছাব্বিশ",
+    "সাতাশ",
+    "আটাশ",
+    "ঊনত্রিশ",
+    "ত্রিশ",
+    "একত্রিশ",
+    "বত্রিশ",
+    "তেত্রিশ",
+    "চৌত্রিশ",
+    "পঁয়ত্রিশ",
+    "ছত্রিশ",
+    "সাঁইত্রিশ",
+    "আটত্রিশ",
+    "ঊনচল্লিশ",
+    "চল্লিশ",
+    "একচল্লিশ",
+    "বিয়াল্লিশ",
+    "তেতাল্লিশ",
+    "চুয়াল্লিশ",
+    "পঁয়তাল্লিশ",
+    "ছেচল্লিশ",
+    "সাতচল্লিশ",
+    "আটচল্লিশ",
+    "ঊনপঞ্চাশ",
+    "পঞ্চাশ",
+    "একান্ন",
+    "বাহান্ন",
+    "তিপ্পান্ন",
+    "চুয়ান্ন",
+    "পঞ্চান্ন",
+    "ছাপ্পান্ন",
+    "সাতান্ন",
+    "আটান্ন",
+    "ঊনষাট",
+    "ষাট",
+    "একষট্টি",
+    "বাষট্টি",
+    "তেষট্টি",
+    "চৌষট্টি",
+    "পঁয়ষট্টি",
+    "ছেষট্টি",
+    "সাতষট্টি",
+    "আটষট্টি",
+    "ঊনসত্তর",
+    "সত্তর",
+    "একাত্তর",
+    "বাহাত্তর",
+    "তিয়াত্তর",
+    "চুয়াত্তর",
+    "পঁচাত্তর",
+    "ছিয়াত্তর",
+    "সাতাত্তর",
+    "আটাত্তর",
+    "ঊনআশি",
+    "আশি",
+    "একাশি",
+    "বিরাশি",
+    "তিরাশি",
+    "চুরাশি",
+    "পঁচাশি",
+    "ছিয়াশি",
+    "সাতাশি",
+    "অষ্টাশি",
+    "ঊননব্বই",
+    "নব্বই",
+    "একানব্বই",
+    "বিরানব্বই",
+    "তিরানব্বই",
+    "চুরানব্বই",
+    "পঁচানব্বই",
+    "ছিয়ানব্বই",
+    "সাতানব্বই",
+    "আটানব্বই",
+    "নিরানব্বই",
+];
+
+// Splits into (crore, lakh, thousand, hundred, rest); crores above 99 stay
+// whole and are read recursively.
+fn groups(amount: u64) -> (u64, u64, u64, u64, u64) {
+    (
+        amount / 10_000_000,
+        amount / 100_000 % 100,
+        amount / 1_000 % 100,
+        amount / 100 % 10,
+        amount % 100,
+    )
+}
+
+fn english_below_hundred(n: u64) -> String {
+    let n = n as usize;
+    let (tens, ones) = (n / 10, n % 10);
+    if n < 20 {
+        ONES[n].to_string()
+    } else if ones == 0 {
+        TENS[tens].to_string()
+    } else {
+        format!("{}-{}", TENS[tens], ONES[ones])
+    }
+}
+
+fn english(amount: u64) -> String {
+    let (crore, lakh, thousand, hundred, rest) = groups(amount);
+    let mut parts = Vec::new();
+    if crore > 0 {
+        parts.push(format!("{} Crore", english(crore)));
+    }
+    for (value, unit) in [(lakh, "Lakh"), (thousand, "Thousand"), (hundred, "Hundred")] {
+        if value > 0 {
+            parts.push(format!("{} {}", english_below_hundred(value), unit));
+        }
+    }
+    if rest > 0 {
+        parts.push(english_below_hundred(rest));
+    }
+    parts.join(" ")
+}
+
+fn bangla(amount: u64) -> String {
+    let (crore, lakh, thousand, hundred, rest) = groups(amount);
+    let mut parts = Vec::new();
+    if crore > 0 {
+        parts.push(format!("{} কোটি", bangla(crore)));
+    }
+    for (value, unit) in [(lakh, "লক্ষ"), (thousand, "হাজার"), (hundred, "শত")]
+    {
+        if value > 0 {
+            parts.push(format!("{} {}", BANGLA[value as usize], unit));
+        }
+    }
+    if rest > 0 {
+        parts.push(BANGLA[rest as usize].to_string());
+    }
+    parts.join(" ")
+}
+
+// "Taka One Thousand Two Hundred Fifty Only"
+pub fn taka_in_english(amount: i32) -> String {
+    let amount = amount.unsigned_abs() as u64;
+    if amount == 0 {
+        return "Taka Zero Only".to_string();
+    }
+    format!("Taka {} Only", english(amount))
+}
+
+// "এক হাজার দুই শত পঞ্চাশ টাকা মাত্র"
+pub fn taka_in_bangla(amount: i32) -> String {
+    let amount = amount.unsigned_abs() as u64;
+    if amount == 0 {
+        return "শূন্য টাকা মাত্র".to_string();
+    }
+    format!("{} টাকা মাত্র", bangla(amount))
+}
@@ -1,10 +1,12 @@
-export type AttendanceState = 'PRESENT' | 'ABSENT' | `LATE-${string}`;
+export type AttendanceState = 'PRESENT' | 'ABSENT' | 'LATE' | 'EXCUSED' | 'ON_LEAVE' | 'HALF_DAY';
 
 export interface Attendance {
     id: number;
     student_id: number;
     date: string;
     status: AttendanceState;
+    remark: string | null;
+    arrival_time: string | null;
 }
 
 export interface AttendanceStaff {
@@ -12,4 +14,10 @@ export interface AttendanceStaff {
     staff_id: number;
     date: string;
     status: AttendanceState;
+    remark: string | null;
+    arrival_time: string | null;
+    departure_time: string | null;
+    late_minutes: number;
+    early_departure_minutes: number;
+    overtime_minutes: number;
 }